@@ -0,0 +1,304 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use time::PrimitiveDateTime;
+
+use crate::config::CoreConfig;
+use crate::state::{PowerState, Temperature};
+use crate::thermal::ThermalModel;
+use crate::{SetPoint, SetPointReason};
+
+const SECONDS_PER_HOUR: f32 = 3600.0;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AwayState {
+    // Hold the frost protection temperature
+    Away,
+    // Heat back to the normal minimum temperature ahead of the return
+    Returning,
+    // The return time has passed
+    Home,
+}
+
+// Hold a frost protection temperature until the return time, resuming
+// normal control early enough for the floor to reach the minimum
+// temperature on arrival
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize, Serialize)]
+pub struct AwayMode {
+    pub frost_protection_temperature: Temperature,
+    // Local time of return
+    pub return_time: PrimitiveDateTime,
+    // Set once heating back has started, so that the floor warming up
+    // does not switch back to holding the frost protection temperature
+    #[serde(default)]
+    pub returning: bool,
+}
+
+impl AwayMode {
+    // The thermal model predicts how long heating takes to recover
+    // from the current temperature to the minimum temperature of the
+    // normal configuration. Returning is latched until the return time.
+    // Without a temperature reading there is nothing to predict from,
+    // so the frost protection temperature is held.
+    pub fn state(
+        &mut self,
+        config: &CoreConfig,
+        model: &ThermalModel,
+        current_temperature: Option<Temperature>,
+        now: PrimitiveDateTime,
+    ) -> AwayState {
+        if now >= self.return_time {
+            return AwayState::Home;
+        }
+        if self.returning {
+            return AwayState::Returning;
+        }
+
+        let Some(current_temperature) = current_temperature else {
+            return AwayState::Away;
+        };
+        let remaining = (self.return_time - now).as_seconds_f32() / SECONDS_PER_HOUR;
+        match model.hours_to_reach(current_temperature, config.minimum_temperature) {
+            Some(hours) if hours < remaining => AwayState::Away,
+            _ => {
+                self.returning = true;
+                AwayState::Returning
+            }
+        }
+    }
+
+    // Temperature limits held at the frost protection temperature
+    pub fn set_points(&self, config: &CoreConfig) -> CoreConfig {
+        CoreConfig {
+            minimum_temperature: self.frost_protection_temperature,
+            fallback_minimum_temperature: self.frost_protection_temperature,
+            maximum_temperature: self.frost_protection_temperature,
+            ..*config
+        }
+    }
+
+    // Check the frost protection temperature is consistent with the
+    // rest of the configuration
+    pub fn validate(&self, config: &CoreConfig) -> Result<()> {
+        self.set_points(config)
+            .validate()
+            .map_err(|err| anyhow!("Invalid frost protection temperature: {}", err))
+    }
+
+    // Heating stays off while away unless the frost protection limit
+    // applies, regardless of price
+    pub fn set_point(&self) -> SetPoint {
+        SetPoint {
+            power: PowerState::Off,
+            temperature: self.frost_protection_temperature,
+            reason: SetPointReason::Away,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::TemperatureDelta;
+    use time::macros::datetime;
+
+    fn settings() -> CoreConfig {
+        CoreConfig {
+            minimum_temperature: Temperature::new(18.0),
+            fallback_minimum_temperature: Temperature::new(19.0),
+            maximum_temperature: Temperature::new(24.0),
+            upper_hysteresis: TemperatureDelta::new(0.5),
+            lower_hysteresis: TemperatureDelta::new(0.5),
+            ..crate::fixtures::settings()
+        }
+    }
+
+    fn model() -> ThermalModel {
+        ThermalModel {
+            heating_rate: 2.0,
+            cooling_rate: 0.1,
+            ambient_temperature: Temperature::new(10.0),
+        }
+    }
+
+    fn away() -> AwayMode {
+        AwayMode {
+            frost_protection_temperature: Temperature::new(8.0),
+            return_time: datetime!(2024-10-27 18:00),
+            returning: false,
+        }
+    }
+
+    #[test]
+    fn test_away_until_return() {
+        let settings = settings();
+        let model = model();
+        let mut away = away();
+        let temperature = Temperature::new(12.0);
+
+        let state = away.state(
+            &settings,
+            &model,
+            Some(temperature),
+            datetime!(2024-10-20 18:00),
+        );
+        assert_eq!(state, AwayState::Away);
+
+        // About 4.1 hours are needed to heat from 12 to 18 degrees
+        let state = away.state(
+            &settings,
+            &model,
+            Some(temperature),
+            datetime!(2024-10-27 13:30),
+        );
+        assert_eq!(state, AwayState::Away);
+        let state = away.state(
+            &settings,
+            &model,
+            Some(temperature),
+            datetime!(2024-10-27 14:00),
+        );
+        assert_eq!(state, AwayState::Returning);
+
+        let state = away.state(
+            &settings,
+            &model,
+            Some(temperature),
+            datetime!(2024-10-27 18:00),
+        );
+        assert_eq!(state, AwayState::Home);
+    }
+
+    #[test]
+    fn test_returning_latched() {
+        let settings = settings();
+        let model = model();
+        let mut away = away();
+
+        let now = datetime!(2024-10-27 14:00);
+        let state = away.state(&settings, &model, Some(Temperature::new(12.0)), now);
+        assert_eq!(state, AwayState::Returning);
+        assert!(away.returning);
+
+        // The floor warms, so recovery would now fit in the remaining
+        // time, but heating continues
+        for (minutes, temperature) in [(15, 13.0), (30, 14.0), (60, 16.0)] {
+            let now = now + time::Duration::minutes(minutes);
+            let temperature = Temperature::new(temperature);
+            let state = away.state(&settings, &model, Some(temperature), now);
+            assert_eq!(state, AwayState::Returning, "{}", temperature);
+        }
+
+        let state = away.state(
+            &settings,
+            &model,
+            Some(Temperature::new(18.0)),
+            datetime!(2024-10-27 18:00),
+        );
+        assert_eq!(state, AwayState::Home);
+    }
+
+    #[test]
+    fn test_away_without_temperature() {
+        let settings = settings();
+        let model = model();
+        let mut away = away();
+
+        // Frost protection is held however close the return is
+        let now = datetime!(2024-10-27 17:00);
+        let state = away.state(&settings, &model, None, now);
+        assert_eq!(state, AwayState::Away);
+        assert!(!away.returning);
+
+        // Heating back continues once started
+        let state = away.state(&settings, &model, Some(Temperature::new(12.0)), now);
+        assert_eq!(state, AwayState::Returning);
+        let state = away.state(&settings, &model, None, now);
+        assert_eq!(state, AwayState::Returning);
+
+        let state = away.state(&settings, &model, None, datetime!(2024-10-27 18:00));
+        assert_eq!(state, AwayState::Home);
+    }
+
+    #[test]
+    fn test_return_when_target_unreachable() {
+        let model = ThermalModel {
+            heating_rate: 0.5,
+            ..model()
+        };
+        let state = away().state(
+            &settings(),
+            &model,
+            Some(Temperature::new(12.0)),
+            datetime!(2024-10-20 18:00),
+        );
+        assert_eq!(state, AwayState::Returning);
+    }
+
+    #[test]
+    fn test_hold_frost_protection() {
+        let settings = settings();
+        let away = away();
+        let config = away.set_points(&settings);
+
+        let set_point = SetPoint::from_plan(
+            &config,
+            Temperature::new(12.0),
+            away.set_point(),
+            PowerState::Off,
+        );
+        assert_eq!(set_point.power, PowerState::Off);
+
+        let set_point = SetPoint::from_plan(
+            &config,
+            Temperature::new(7.0),
+            away.set_point(),
+            PowerState::Off,
+        );
+        assert_eq!(set_point.power, PowerState::On);
+
+        // Heats through the hysteresis band above the frost limit
+        let set_point = SetPoint::from_plan(
+            &config,
+            Temperature::new(8.2),
+            away.set_point(),
+            PowerState::On,
+        );
+        assert_eq!(set_point.power, PowerState::On);
+    }
+
+    #[test]
+    fn test_validate() {
+        away().validate(&settings()).unwrap();
+
+        let away = AwayMode {
+            frost_protection_temperature: Temperature::new(35.0),
+            ..away()
+        };
+        assert!(away.validate(&settings()).is_err());
+        let away = AwayMode {
+            frost_protection_temperature: Temperature::new(f32::NAN),
+            ..away
+        };
+        assert!(away.validate(&settings()).is_err());
+    }
+
+    #[test]
+    fn test_serialize() {
+        let json = serde_json::to_string(&away()).unwrap();
+        let away: AwayMode = serde_json::from_str(&json).unwrap();
+        assert_eq!(away, self::away());
+
+        // Not returning unless given
+        let json = r#"{"frost_protection_temperature":8.0,"return_time":"2024-10-27 18:00:00.0"}"#;
+        let parsed: AwayMode = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed, self::away());
+
+        let returning = AwayMode {
+            returning: true,
+            ..self::away()
+        };
+        let json = serde_json::to_string(&returning).unwrap();
+        let parsed: AwayMode = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, returning);
+    }
+}
@@ -0,0 +1,243 @@
+use anyhow::{bail, Result};
+#[allow(unused_imports)]
+use micromath::F32Ext; // Required for f32::ln and f32::exp
+use serde::{Deserialize, Serialize};
+
+use crate::state::Temperature;
+use crate::thermistor::{ThermistorModel, KELVIN_OFFSET};
+
+// Temperature the solved beta model is referenced to
+const NOMINAL_TEMPERATURE: f32 = 25.0;
+
+// Thermistor reading against a reference thermometer
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize, Serialize)]
+pub struct CalibrationPoint {
+    // Thermistor voltage as a fraction of the divider supply voltage
+    pub voltage_ratio: f32,
+    pub temperature: Temperature,
+}
+
+// Solve the thermistor model through two or three calibration points.
+//
+// Two points give a beta model, with the beta value and the resistance
+// at 25 degrees; three points give the Steinhart-Hart coefficients.
+pub fn calibrate(
+    reference_resistance: f32,
+    points: &[CalibrationPoint],
+) -> Result<ThermistorModel> {
+    if reference_resistance <= 0.0 {
+        bail!("Reference resistance must be positive");
+    }
+
+    // ln(R) and 1 / T (Kelvin) for each point; the remaining
+    // arithmetic is in f64 as the Steinhart-Hart terms differ by
+    // several orders of magnitude
+    let mut samples = [(0.0f64, 0.0f64); 3];
+    if !(2..=3).contains(&points.len()) {
+        bail!("Calibration needs two or three points");
+    }
+    for (sample, point) in samples.iter_mut().zip(points) {
+        if !(point.voltage_ratio > 0.0 && point.voltage_ratio < 1.0) {
+            bail!("Voltage ratio {:?} out of range", point.voltage_ratio);
+        }
+        let resistance = point.voltage_ratio * reference_resistance / (1.0 - point.voltage_ratio);
+        let kelvin = f32::from(point.temperature) + KELVIN_OFFSET;
+        if kelvin <= 0.0 {
+            bail!("Temperature {:?} out of range", point.temperature);
+        }
+        *sample = (resistance.ln() as f64, 1.0 / kelvin as f64);
+    }
+
+    let samples = &samples[..points.len()];
+    for (index, (ln_r, inverse_t)) in samples.iter().enumerate() {
+        for (other_ln_r, other_inverse_t) in &samples[index + 1..] {
+            if ln_r == other_ln_r || inverse_t == other_inverse_t {
+                bail!("Calibration points must be distinct");
+            }
+        }
+    }
+
+    match *samples {
+        [first, second] => solve_beta(first, second),
+        [first, second, third] => solve_steinhart_hart(first, second, third),
+        _ => unreachable!(),
+    }
+}
+
+fn solve_beta((ln_r1, y1): (f64, f64), (ln_r2, y2): (f64, f64)) -> Result<ThermistorModel> {
+    //   1 / T = 1 / T0 + ln(R / R0) / beta
+    let beta = (ln_r1 - ln_r2) / (y1 - y2);
+    if beta <= 0.0 {
+        bail!("Calibration points do not describe an NTC thermistor");
+    }
+
+    let y0 = 1.0 / (NOMINAL_TEMPERATURE + KELVIN_OFFSET) as f64;
+    let ln_r0 = ln_r1 - beta * (y1 - y0);
+
+    Ok(ThermistorModel::Beta {
+        beta: beta as f32,
+        r1: (ln_r0 as f32).exp(),
+        t1: NOMINAL_TEMPERATURE,
+    })
+}
+
+fn solve_steinhart_hart(
+    (l1, y1): (f64, f64),
+    (l2, y2): (f64, f64),
+    (l3, y3): (f64, f64),
+) -> Result<ThermistorModel> {
+    //   1 / T = a + b * L + c * L^3, with L = ln(R)
+    let gamma2 = (y2 - y1) / (l2 - l1);
+    let gamma3 = (y3 - y1) / (l3 - l1);
+
+    let c = (gamma3 - gamma2) / (l3 - l2) / (l1 + l2 + l3);
+    let b = gamma2 - c * (l1 * l1 + l1 * l2 + l2 * l2);
+    let a = y1 - (b + l1 * l1 * c) * l1;
+
+    if b <= 0.0 {
+        bail!("Calibration points do not describe an NTC thermistor");
+    }
+
+    Ok(ThermistorModel::SteinhartHart {
+        a: a as f32,
+        b: b as f32,
+        c: c as f32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thermistor::{temperature_from_resistance, temperature_from_voltage};
+    use crate::ThermistorProperties;
+
+    // YSI 44006 10k NTC
+    fn steinhart_hart_10k() -> ThermistorModel {
+        ThermistorModel::SteinhartHart {
+            a: 1.129148e-3,
+            b: 2.34125e-4,
+            c: 8.76741e-8,
+        }
+    }
+
+    fn point(reference_resistance: f32, resistance: f32, temperature: f32) -> CalibrationPoint {
+        CalibrationPoint {
+            voltage_ratio: resistance / (reference_resistance + resistance),
+            temperature: Temperature::new(temperature),
+        }
+    }
+
+    fn assert_close(value: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (value - expected).abs() < tolerance,
+            "{:?} != {:?}",
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_calibrate_beta() {
+        let properties = ThermistorProperties::default();
+        let reference = properties.reference_resistance;
+        let points = [
+            point(reference, 37_942.0, 0.0),
+            point(reference, 957.84, 100.0),
+        ];
+
+        let model = calibrate(reference, &points).unwrap();
+        let ThermistorModel::Beta { beta, r1, t1 } = model else {
+            panic!("{:?}", model);
+        };
+        assert_close(beta, 3750.0, 0.5);
+        assert_close(r1, 12_000.0, 2.0);
+        assert_eq!(t1, 25.0);
+
+        let temperature = temperature_from_resistance(&model, 4_535.2);
+        assert_close(temperature, 50.0, 0.01);
+    }
+
+    #[test]
+    fn test_calibrate_offset() {
+        // The sensor reads 0.8 degrees high against the reference
+        let properties = ThermistorProperties::default();
+        let reference = properties.reference_resistance;
+        let points = [20.0, 30.0].map(|reference_temperature| {
+            let measured = reference_temperature + 0.8;
+            let resistance = 12_000.0 * (3750.0 * (1.0 / (measured + 273.15) - 1.0 / 298.15)).exp();
+            point(reference, resistance, reference_temperature)
+        });
+
+        let model = calibrate(reference, &points).unwrap();
+        let calibrated = ThermistorProperties {
+            model,
+            ..properties
+        };
+
+        for point in points {
+            let vcc = 3300.0;
+            let sample = vcc * point.voltage_ratio;
+            assert_close(
+                temperature_from_voltage(&calibrated, vcc, sample).unwrap(),
+                f32::from(point.temperature),
+                0.01,
+            );
+            // The uncalibrated model reads high
+            assert_close(
+                temperature_from_voltage(&properties, vcc, sample).unwrap(),
+                f32::from(point.temperature) + 0.8,
+                0.01,
+            );
+        }
+    }
+
+    #[test]
+    fn test_calibrate_steinhart_hart() {
+        let reference = 10_000.0;
+        let points = [
+            point(reference, 32_650.0, 0.0),
+            point(reference, 10_000.0, 25.0),
+            point(reference, 3_602.0, 50.0),
+        ];
+
+        let model = calibrate(reference, &points).unwrap();
+        let ThermistorModel::SteinhartHart { a, b, c } = model else {
+            panic!("{:?}", model);
+        };
+        let ThermistorModel::SteinhartHart {
+            a: expected_a,
+            b: expected_b,
+            c: expected_c,
+        } = steinhart_hart_10k()
+        else {
+            unreachable!();
+        };
+        assert!((a - expected_a).abs() < 1e-5, "{:?}", a);
+        assert!((b - expected_b).abs() < 1e-6, "{:?}", b);
+        assert!((c - expected_c).abs() < 1e-8, "{:?}", c);
+
+        // Outside of the calibrated range
+        let temperature = temperature_from_resistance(&model, 678.3);
+        assert_close(temperature, 100.0, 0.2);
+    }
+
+    #[test]
+    fn test_calibrate_invalid_points() {
+        let reference = 10_000.0;
+        let valid = point(reference, 10_000.0, 25.0);
+
+        assert!(calibrate(reference, &[valid]).is_err());
+        assert!(calibrate(reference, &[valid; 4]).is_err());
+        assert!(calibrate(reference, &[valid, valid]).is_err());
+
+        let out_of_range = CalibrationPoint {
+            voltage_ratio: 1.0,
+            ..valid
+        };
+        assert!(calibrate(reference, &[valid, out_of_range]).is_err());
+
+        // Resistance rising with temperature
+        let ptc = point(reference, 12_000.0, 30.0);
+        assert!(calibrate(reference, &[valid, ptc]).is_err());
+    }
+}
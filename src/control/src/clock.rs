@@ -0,0 +1,461 @@
+use anyhow::{anyhow, bail, Result};
+use core::str::FromStr;
+use time::{Date, Duration, Month, PrimitiveDateTime, Time, UtcOffset, Weekday};
+
+// Wall clock time read once and kept in both time scales. Prices and
+// other data keyed in UTC are always looked up by `utc`; only the
+// comfort schedule follows the `local` clock.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ClockTime {
+    pub utc: PrimitiveDateTime,
+    pub local: PrimitiveDateTime,
+}
+
+// Day of the year a daylight saving time transition happens on
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum TransitionDay {
+    // "Jn"; 1 to 365, never counting 29 February
+    Julian(u16),
+    // "n"; 0 to 365, counting 29 February
+    Ordinal(u16),
+    // "Mm.w.d"; day `weekday` of week `week` of `month`, where week 5
+    // is the last
+    MonthWeekday {
+        month: Month,
+        week: u8,
+        weekday: Weekday,
+    },
+}
+
+impl TransitionDay {
+    fn date(&self, year: i32) -> Option<Date> {
+        let date = match *self {
+            TransitionDay::Julian(day) => {
+                let leap_day = time::util::is_leap_year(year) && day >= 60;
+                Date::from_ordinal_date(year, day + leap_day as u16).ok()?
+            }
+            TransitionDay::Ordinal(day) => Date::from_ordinal_date(year, day + 1).ok()?,
+            TransitionDay::MonthWeekday {
+                month,
+                week,
+                weekday,
+            } => {
+                let first = Date::from_calendar_date(year, month, 1).ok()?;
+                let offset = (weekday.number_days_from_sunday() + 7
+                    - first.weekday().number_days_from_sunday())
+                    % 7;
+                let mut date = first + Duration::days((offset + (week - 1) * 7) as i64);
+                while date.month() != month {
+                    date -= Duration::WEEK;
+                }
+                date
+            }
+        };
+        Some(date)
+    }
+}
+
+// Daylight saving time transition, at local time `time` after midnight
+// on `day`; the time may be negative or beyond a day
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Transition {
+    day: TransitionDay,
+    time: Duration,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct DaylightSaving {
+    offset: UtcOffset,
+    start: Transition,
+    end: Transition,
+}
+
+// Local time zone, configured as a POSIX TZ string such as
+// "CET-1CEST,M3.5.0,M10.5.0/3".
+//
+// Note the sign of POSIX offsets is the opposite of the usual
+// convention; "CET-1" is one hour ahead of UTC.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TimeZone {
+    standard: UtcOffset,
+    daylight_saving: Option<DaylightSaving>,
+}
+
+impl TimeZone {
+    pub const UTC: TimeZone = TimeZone {
+        standard: UtcOffset::UTC,
+        daylight_saving: None,
+    };
+
+    // Offset from UTC in effect at `utc`
+    pub fn offset_at(&self, utc: PrimitiveDateTime) -> UtcOffset {
+        let Some(daylight_saving) = self.daylight_saving else {
+            return self.standard;
+        };
+        let year = utc.year();
+        // Transitions are in local time; the start in standard time
+        // and the end in daylight saving time
+        let start = transition_utc(&daylight_saving.start, year, self.standard);
+        let end = transition_utc(&daylight_saving.end, year, daylight_saving.offset);
+        let (Some(start), Some(end)) = (start, end) else {
+            return self.standard;
+        };
+
+        // Daylight saving time spans the new year in the southern
+        // hemisphere
+        let daylight = if start < end {
+            start <= utc && utc < end
+        } else {
+            !(end <= utc && utc < start)
+        };
+        if daylight {
+            daylight_saving.offset
+        } else {
+            self.standard
+        }
+    }
+
+    pub fn to_local(&self, utc: PrimitiveDateTime) -> PrimitiveDateTime {
+        utc + Duration::seconds(self.offset_at(utc).whole_seconds() as i64)
+    }
+
+    // UTC time of a local time; local times that happen twice as the
+    // clocks go back are taken in standard time
+    pub fn to_utc(&self, local: PrimitiveDateTime) -> PrimitiveDateTime {
+        let standard = local - Duration::seconds(self.standard.whole_seconds() as i64);
+        match self.daylight_saving {
+            Some(daylight_saving) if self.offset_at(standard) != self.standard => {
+                local - Duration::seconds(daylight_saving.offset.whole_seconds() as i64)
+            }
+            _ => standard,
+        }
+    }
+
+    pub fn clock_time(&self, utc: PrimitiveDateTime) -> ClockTime {
+        ClockTime {
+            utc,
+            local: self.to_local(utc),
+        }
+    }
+}
+
+fn transition_utc(
+    transition: &Transition,
+    year: i32,
+    offset: UtcOffset,
+) -> Option<PrimitiveDateTime> {
+    let date = transition.day.date(year)?;
+    let local = PrimitiveDateTime::new(date, Time::MIDNIGHT) + transition.time;
+    Some(local - Duration::seconds(offset.whole_seconds() as i64))
+}
+
+impl FromStr for TimeZone {
+    type Err = anyhow::Error;
+
+    // std offset [dst [offset] ,start[/time],end[/time]]
+    fn from_str(tz: &str) -> Result<Self> {
+        let invalid = || anyhow!("Invalid time zone {:?}", tz);
+        let mut parser = Parser { rest: tz };
+
+        parser.name().ok_or_else(invalid)?;
+        let standard = parser.offset().ok_or_else(invalid)?;
+        let standard = utc_offset(-standard)?;
+        if parser.rest.is_empty() {
+            return Ok(TimeZone {
+                standard,
+                daylight_saving: None,
+            });
+        }
+
+        parser.name().ok_or_else(invalid)?;
+        let offset = if parser.rest.starts_with(',') {
+            // One hour ahead of standard time by default
+            standard.whole_seconds() as i64 + 3600
+        } else {
+            -parser.offset().ok_or_else(invalid)?.whole_seconds()
+        };
+        let offset = utc_offset(Duration::seconds(offset))?;
+        if parser.rest.is_empty() {
+            bail!("Missing daylight saving time rule in time zone {:?}", tz);
+        }
+
+        let start = parser.transition().ok_or_else(invalid)?;
+        let end = parser.transition().ok_or_else(invalid)?;
+        if !parser.rest.is_empty() {
+            return Err(invalid());
+        }
+        Ok(TimeZone {
+            standard,
+            daylight_saving: Some(DaylightSaving { offset, start, end }),
+        })
+    }
+}
+
+fn utc_offset(offset: Duration) -> Result<UtcOffset> {
+    UtcOffset::from_whole_seconds(offset.whole_seconds() as i32)
+        .map_err(|_| anyhow!("Time zone offset {} out of range", offset))
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl Parser<'_> {
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> &str {
+        let end = self.rest.find(|c| !predicate(c)).unwrap_or(self.rest.len());
+        let (taken, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        taken
+    }
+
+    fn skip(&mut self, prefix: char) -> bool {
+        match self.rest.strip_prefix(prefix) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // At least three letters, or anything quoted in angle brackets
+    fn name(&mut self) -> Option<()> {
+        if self.skip('<') {
+            self.take_while(|c| c != '>');
+            return self.skip('>').then_some(());
+        }
+        (self.take_while(|c| c.is_ascii_alphabetic()).len() >= 3).then_some(())
+    }
+
+    fn number(&mut self) -> Option<i64> {
+        self.take_while(|c| c.is_ascii_digit()).parse().ok()
+    }
+
+    // [+|-]hh[:mm[:ss]]
+    fn offset(&mut self) -> Option<Duration> {
+        let negative = self.skip('-');
+        if !negative {
+            self.skip('+');
+        }
+        let hours = self.number()?;
+        let minutes = if self.skip(':') { self.number()? } else { 0 };
+        let seconds = if self.skip(':') { self.number()? } else { 0 };
+        if hours > 167 || minutes > 59 || seconds > 59 {
+            return None;
+        }
+        let offset =
+            Duration::hours(hours) + Duration::minutes(minutes) + Duration::seconds(seconds);
+        Some(if negative { -offset } else { offset })
+    }
+
+    // ,day[/time]
+    fn transition(&mut self) -> Option<Transition> {
+        if !self.skip(',') {
+            return None;
+        }
+        let day = if self.skip('J') {
+            let day = self.number()?;
+            (1..=365).contains(&day).then_some(())?;
+            TransitionDay::Julian(day as u16)
+        } else if self.skip('M') {
+            let month = self.number()?;
+            self.skip('.').then_some(())?;
+            let week = self.number()?;
+            self.skip('.').then_some(())?;
+            let weekday = self.number()?;
+            if !(1..=5).contains(&week) || weekday > 6 {
+                return None;
+            }
+            TransitionDay::MonthWeekday {
+                month: Month::try_from(u8::try_from(month).ok()?).ok()?,
+                week: week as u8,
+                weekday: Weekday::Sunday.nth_next(weekday as u8),
+            }
+        } else {
+            let day = self.number()?;
+            (0..=365).contains(&day).then_some(())?;
+            TransitionDay::Ordinal(day as u16)
+        };
+        let time = if self.skip('/') {
+            self.offset()?
+        } else {
+            Duration::hours(2)
+        };
+        Some(Transition { day, time })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::series::PriceSeries;
+    use crate::state::ElectricityPrice;
+    use time::macros::{datetime, offset};
+
+    fn central_europe() -> TimeZone {
+        "CET-1CEST,M3.5.0,M10.5.0/3".parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("UTC0".parse::<TimeZone>().unwrap(), TimeZone::UTC);
+
+        let tz: TimeZone = "<+0530>-5:30".parse().unwrap();
+        assert_eq!(tz.offset_at(datetime!(2024-07-01 12:00)), offset!(+5:30));
+
+        let tz: TimeZone = "EST5EDT,M3.2.0,M11.1.0".parse().unwrap();
+        assert_eq!(tz.offset_at(datetime!(2024-01-01 12:00)), offset!(-5));
+        assert_eq!(tz.offset_at(datetime!(2024-07-01 12:00)), offset!(-4));
+
+        let tz: TimeZone = "EET-2EEST-3,J86/3,J304/4".parse().unwrap();
+        assert_eq!(tz.offset_at(datetime!(2024-07-01 12:00)), offset!(+3));
+
+        for tz in [
+            "",
+            "C-1",
+            "CET",
+            "CET-1CEST",
+            "CET-1CEST,M13.5.0,M10.5.0/3",
+            "CET-1CEST,M3.6.0,M10.5.0/3",
+            "CET-1CEST,M3.5.0",
+            "CET-1CEST,M3.5.0,M10.5.0/3,",
+        ] {
+            assert!(tz.parse::<TimeZone>().is_err(), "{:?}", tz);
+        }
+    }
+
+    #[test]
+    fn test_spring_forward() {
+        let tz = central_europe();
+
+        // 02:00 CET on 31 March 2024 becomes 03:00 CEST
+        assert_eq!(
+            tz.to_local(datetime!(2024-03-31 00:59:59)),
+            datetime!(2024-03-31 01:59:59)
+        );
+        assert_eq!(
+            tz.to_local(datetime!(2024-03-31 01:00)),
+            datetime!(2024-03-31 03:00)
+        );
+        assert_eq!(tz.offset_at(datetime!(2024-03-30 12:00)), offset!(+1));
+        assert_eq!(tz.offset_at(datetime!(2024-04-01 12:00)), offset!(+2));
+
+        // The local day has 23 hours
+        let midnight = datetime!(2024-03-30 23:00);
+        let next_midnight = datetime!(2024-03-31 22:00);
+        assert_eq!(tz.to_local(midnight), datetime!(2024-03-31 00:00));
+        assert_eq!(tz.to_local(next_midnight), datetime!(2024-04-01 00:00));
+        assert_eq!(next_midnight - midnight, Duration::hours(23));
+    }
+
+    #[test]
+    fn test_fall_back() {
+        let tz = central_europe();
+
+        // 03:00 CEST on 27 October 2024 becomes 02:00 CET
+        assert_eq!(
+            tz.to_local(datetime!(2024-10-27 00:59:59)),
+            datetime!(2024-10-27 02:59:59)
+        );
+        assert_eq!(
+            tz.to_local(datetime!(2024-10-27 01:00)),
+            datetime!(2024-10-27 02:00)
+        );
+
+        // The local day has 25 hours
+        let midnight = datetime!(2024-10-26 22:00);
+        let next_midnight = datetime!(2024-10-27 23:00);
+        assert_eq!(tz.to_local(midnight), datetime!(2024-10-27 00:00));
+        assert_eq!(tz.to_local(next_midnight), datetime!(2024-10-28 00:00));
+        assert_eq!(next_midnight - midnight, Duration::hours(25));
+    }
+
+    #[test]
+    fn test_to_utc() {
+        let tz = central_europe();
+        assert_eq!(
+            tz.to_utc(datetime!(2024-01-15 00:00)),
+            datetime!(2024-01-14 23:00)
+        );
+        assert_eq!(
+            tz.to_utc(datetime!(2024-10-27 00:00)),
+            datetime!(2024-10-26 22:00)
+        );
+        assert_eq!(
+            tz.to_utc(datetime!(2024-10-28 00:00)),
+            datetime!(2024-10-27 23:00)
+        );
+        assert_eq!(
+            tz.to_utc(datetime!(2024-10-27 02:30)),
+            datetime!(2024-10-27 01:30)
+        );
+        assert_eq!(
+            TimeZone::UTC.to_utc(datetime!(2024-10-27 02:30)),
+            datetime!(2024-10-27 02:30)
+        );
+    }
+
+    #[test]
+    fn test_southern_hemisphere() {
+        let tz: TimeZone = "AEST-10AEDT,M10.1.0,M4.1.0/3".parse().unwrap();
+        assert_eq!(tz.offset_at(datetime!(2024-01-15 12:00)), offset!(+11));
+        assert_eq!(tz.offset_at(datetime!(2024-07-15 12:00)), offset!(+10));
+        assert_eq!(tz.offset_at(datetime!(2024-12-15 12:00)), offset!(+11));
+
+        // 03:00 AEDT on 7 April 2024 becomes 02:00 AEST
+        assert_eq!(tz.offset_at(datetime!(2024-04-06 15:59)), offset!(+11));
+        assert_eq!(tz.offset_at(datetime!(2024-04-06 16:00)), offset!(+10));
+    }
+
+    // A day of hourly prices keyed in UTC, for the local day starting at
+    // `midnight` UTC
+    fn hourly_prices(midnight: PrimitiveDateTime, hours: i64) -> PriceSeries {
+        let mut series =
+            PriceSeries::new(midnight, midnight + Duration::hours(hours), Duration::HOUR).unwrap();
+        for hour in 0..hours {
+            let start = midnight + Duration::hours(hour);
+            series
+                .insert(start, ElectricityPrice::new(hour as f32))
+                .unwrap();
+        }
+        series
+    }
+
+    #[test]
+    fn test_price_lookup_across_daylight_saving() {
+        let tz = central_europe();
+
+        // Each of the 25 hours of the local day has its own price, even
+        // though 02:00 to 03:00 local time happens twice
+        let prices = hourly_prices(datetime!(2024-10-26 22:00), 25);
+        let mut utc = datetime!(2024-10-26 22:30);
+        for hour in 0..25 {
+            let now = tz.clock_time(utc);
+            assert_eq!(
+                prices.price_at(now.utc),
+                Some(ElectricityPrice::new(hour as f32)),
+                "{:?}",
+                now
+            );
+            utc += Duration::HOUR;
+        }
+        let first = tz.clock_time(datetime!(2024-10-27 00:30));
+        let second = tz.clock_time(datetime!(2024-10-27 01:30));
+        assert_eq!(first.local, second.local);
+        assert_ne!(prices.price_at(first.utc), prices.price_at(second.utc));
+
+        // No local hour from 02:00 to 03:00 is skipped in the prices for
+        // the 23 hour day
+        let prices = hourly_prices(datetime!(2024-03-30 23:00), 23);
+        let before = tz.clock_time(datetime!(2024-03-31 00:30));
+        let after = tz.clock_time(datetime!(2024-03-31 01:30));
+        assert_eq!(before.local, datetime!(2024-03-31 01:30));
+        assert_eq!(after.local, datetime!(2024-03-31 03:30));
+        assert_eq!(
+            prices.price_at(before.utc),
+            Some(ElectricityPrice::new(1.0))
+        );
+        assert_eq!(prices.price_at(after.utc), Some(ElectricityPrice::new(2.0)));
+        assert_eq!(prices.len(), 23);
+    }
+}
@@ -1,4 +1,27 @@
-use crate::state::{ElectricityPrice, Temperature};
+use anyhow::{bail, Result};
+
+use crate::pwm::PwmConfig;
+use crate::state::{ElectricityPrice, PowerState, Temperature, TemperatureDelta};
+
+// How the electricity price selects the temperature set point
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PriceMode {
+    // Scale linearly between zero and the maximum price
+    Absolute,
+    // Scale by the rank of the price within the known price window,
+    // optionally still turning heating off above the maximum price
+    Relative { hard_ceiling: bool },
+}
+
+// How the heating output follows the temperature set point
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ControlStrategy {
+    // Switch on and off at the set point thresholds
+    Hysteresis,
+    // Time-proportional heating, with the on-fraction of each cycle
+    // from a PID controller on the set point temperature
+    Pid(PwmConfig),
+}
 
 #[derive(Copy, Clone, Debug)]
 pub struct CoreConfig {
@@ -15,4 +38,296 @@ pub struct CoreConfig {
 
     // Electricity price at which to turn heating off
     pub maximum_price: ElectricityPrice,
+
+    pub price_mode: PriceMode,
+
+    // Distance above a threshold the temperature must rise before
+    // heating that is currently on is turned off
+    pub upper_hysteresis: TemperatureDelta,
+
+    // Distance below a threshold the temperature must fall before
+    // heating that is currently off is turned on
+    pub lower_hysteresis: TemperatureDelta,
+}
+
+impl CoreConfig {
+    // The temperature at which the output should change, given the
+    // current output state.
+    //
+    // While heating is on, the switching point is the top of the
+    // hysteresis band around the threshold; while heating is off, it
+    // is the bottom of the band.
+    fn switching_edge(&self, threshold: Temperature, previous: PowerState) -> Temperature {
+        match previous {
+            PowerState::On => threshold + self.upper_hysteresis,
+            PowerState::Off => threshold - self.lower_hysteresis,
+        }
+    }
+
+    // Whether the temperature is above a threshold, taking the
+    // hysteresis band around the threshold into account
+    pub fn is_above(
+        &self,
+        temperature: Temperature,
+        threshold: Temperature,
+        previous: PowerState,
+    ) -> bool {
+        temperature > self.switching_edge(threshold, previous)
+    }
+
+    // Whether the temperature is below a threshold, taking the
+    // hysteresis band around the threshold into account
+    pub fn is_below(
+        &self,
+        temperature: Temperature,
+        threshold: Temperature,
+        previous: PowerState,
+    ) -> bool {
+        temperature < self.switching_edge(threshold, previous)
+    }
+
+    // Whether heating should be off at this price regardless of the
+    // temperature set point
+    pub fn is_above_price_ceiling(&self, price: ElectricityPrice) -> bool {
+        let hard_ceiling = match self.price_mode {
+            PriceMode::Absolute => true,
+            PriceMode::Relative { hard_ceiling } => hard_ceiling,
+        };
+        hard_ceiling && price > self.maximum_price
+    }
+
+    // Check the configuration is consistent, describing the first
+    // problem found
+    pub fn validate(&self) -> Result<()> {
+        let temperatures = [
+            ("Minimum temperature", self.minimum_temperature),
+            (
+                "Fallback minimum temperature",
+                self.fallback_minimum_temperature,
+            ),
+            ("Maximum temperature", self.maximum_temperature),
+            ("Turbo temperature", self.turbo_temperature),
+        ];
+        for (name, temperature) in temperatures {
+            if !temperature.is_finite() {
+                bail!("{} must be a number", name);
+            }
+        }
+        let hysteresis = [
+            ("Upper hysteresis", self.upper_hysteresis),
+            ("Lower hysteresis", self.lower_hysteresis),
+        ];
+        for (name, delta) in hysteresis {
+            if !delta.is_finite() {
+                bail!("{} must be a number", name);
+            }
+            if delta < TemperatureDelta::ZERO {
+                bail!("{} {} must not be negative", name, delta);
+            }
+        }
+
+        let minimum = self.minimum_temperature;
+        let fallback_minimum = self.fallback_minimum_temperature;
+        let maximum = self.maximum_temperature;
+        let turbo = self.turbo_temperature;
+        if minimum > maximum {
+            bail!(
+                "Minimum temperature {} is above the maximum temperature {}",
+                minimum,
+                maximum
+            );
+        }
+        if fallback_minimum < minimum || fallback_minimum > maximum {
+            bail!(
+                "Fallback minimum temperature {} is outside the range {} to {}",
+                fallback_minimum,
+                minimum,
+                maximum
+            );
+        }
+        if turbo < maximum {
+            bail!(
+                "Turbo temperature {} is below the maximum temperature {}",
+                turbo,
+                maximum
+            );
+        }
+
+        // The price is scaled against the maximum price
+        let maximum_price = self.maximum_price;
+        if !maximum_price.is_finite() || maximum_price <= ElectricityPrice::new(0.0) {
+            bail!("Maximum price {} must be positive", maximum_price);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::format;
+
+    fn settings() -> CoreConfig {
+        CoreConfig {
+            upper_hysteresis: TemperatureDelta::new(0.5),
+            lower_hysteresis: TemperatureDelta::new(0.5),
+            ..crate::fixtures::settings()
+        }
+    }
+
+    fn assert_invalid(config: CoreConfig, message: &str) {
+        let err = config.validate().unwrap_err();
+        assert_eq!(format!("{}", err), message);
+    }
+
+    #[test]
+    fn test_valid() {
+        settings().validate().unwrap();
+
+        // A single temperature, as in away mode
+        let frost = Temperature::new(8.0);
+        CoreConfig {
+            minimum_temperature: frost,
+            fallback_minimum_temperature: frost,
+            maximum_temperature: frost,
+            ..settings()
+        }
+        .validate()
+        .unwrap();
+
+        // Equal turbo and maximum temperatures, without hysteresis
+        CoreConfig {
+            turbo_temperature: Temperature::new(22.0),
+            upper_hysteresis: TemperatureDelta::new(0.0),
+            lower_hysteresis: TemperatureDelta::new(0.0),
+            ..settings()
+        }
+        .validate()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_temperature_not_a_number() {
+        assert_invalid(
+            CoreConfig {
+                minimum_temperature: Temperature::new(f32::NAN),
+                ..settings()
+            },
+            "Minimum temperature must be a number",
+        );
+        assert_invalid(
+            CoreConfig {
+                turbo_temperature: Temperature::new(f32::INFINITY),
+                ..settings()
+            },
+            "Turbo temperature must be a number",
+        );
+        assert_invalid(
+            CoreConfig {
+                lower_hysteresis: TemperatureDelta::new(f32::NAN),
+                ..settings()
+            },
+            "Lower hysteresis must be a number",
+        );
+        assert_invalid(
+            CoreConfig {
+                fallback_minimum_temperature: Temperature::new(f32::NEG_INFINITY),
+                ..settings()
+            },
+            "Fallback minimum temperature must be a number",
+        );
+        assert_invalid(
+            CoreConfig {
+                maximum_temperature: Temperature::new(f32::NAN),
+                ..settings()
+            },
+            "Maximum temperature must be a number",
+        );
+        assert_invalid(
+            CoreConfig {
+                upper_hysteresis: TemperatureDelta::new(f32::INFINITY),
+                ..settings()
+            },
+            "Upper hysteresis must be a number",
+        );
+    }
+
+    #[test]
+    fn test_minimum_above_maximum() {
+        assert_invalid(
+            CoreConfig {
+                minimum_temperature: Temperature::new(23.0),
+                ..settings()
+            },
+            "Minimum temperature 23 °C is above the maximum temperature 22 °C",
+        );
+    }
+
+    #[test]
+    fn test_fallback_minimum_out_of_range() {
+        assert_invalid(
+            CoreConfig {
+                fallback_minimum_temperature: Temperature::new(14.0),
+                ..settings()
+            },
+            "Fallback minimum temperature 14 °C is outside the range 15 °C to 22 °C",
+        );
+        assert_invalid(
+            CoreConfig {
+                fallback_minimum_temperature: Temperature::new(22.5),
+                ..settings()
+            },
+            "Fallback minimum temperature 22.5 °C is outside the range 15 °C to 22 °C",
+        );
+    }
+
+    #[test]
+    fn test_turbo_below_maximum() {
+        assert_invalid(
+            CoreConfig {
+                turbo_temperature: Temperature::new(21.0),
+                ..settings()
+            },
+            "Turbo temperature 21 °C is below the maximum temperature 22 °C",
+        );
+    }
+
+    #[test]
+    fn test_maximum_price_not_positive() {
+        for price in [0.0, -0.1, f32::NAN, f32::INFINITY] {
+            let config = CoreConfig {
+                maximum_price: ElectricityPrice::new(price),
+                ..settings()
+            };
+            assert!(config.validate().is_err(), "{:?}", price);
+        }
+        assert_invalid(
+            CoreConfig {
+                maximum_price: ElectricityPrice::new(0.0),
+                ..settings()
+            },
+            "Maximum price 0 must be positive",
+        );
+    }
+
+    #[test]
+    fn test_negative_hysteresis() {
+        assert_invalid(
+            CoreConfig {
+                upper_hysteresis: TemperatureDelta::new(-0.5),
+                ..settings()
+            },
+            "Upper hysteresis -0.5 K must not be negative",
+        );
+        assert_invalid(
+            CoreConfig {
+                lower_hysteresis: TemperatureDelta::new(-0.25),
+                ..settings()
+            },
+            "Lower hysteresis -0.25 K must not be negative",
+        );
+    }
 }
@@ -0,0 +1,398 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use time::{Duration, PrimitiveDateTime};
+
+use crate::provider::PriceProvider;
+use crate::series::PriceSeries;
+use crate::state::{ElectricityPrice, PriceSlot};
+use crate::unit::{PriceScale, PriceUnit};
+
+// Tomorrow's prices are fetched within this long of the end of today's
+const FETCH_AHEAD: Duration = Duration::hours(3);
+
+// Source of the current time, in UTC
+pub trait Clock {
+    fn now(&self) -> Result<PrimitiveDateTime>;
+}
+
+// Fetches a price provider's response, e.g. over HTTP
+pub trait PriceFetcher {
+    type Body: AsRef<str>;
+
+    fn fetch(&mut self, url: &str) -> Result<Self::Body>;
+}
+
+// The today and tomorrow JSON feed, as in electricity-price/*.json
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct JsonFeed {
+    pub url: &'static str,
+    // The feed does not say; the sample data is in EUR cents/kWh
+    pub unit: PriceUnit,
+}
+
+impl PriceProvider for JsonFeed {
+    fn fetch<F: PriceFetcher>(
+        &self,
+        fetcher: &mut F,
+        _now: PrimitiveDateTime,
+    ) -> Result<MultiDayElectricityPrice> {
+        let json = fetcher.fetch(self.url)?;
+        let data: MultiDayElectricityPrice = serde_json::from_str(json.as_ref())?;
+        if self.unit.scale == PriceScale::CentsPerKwh {
+            return Ok(data);
+        }
+        Ok(data.map_prices(|slot| self.unit.price(f32::from(slot.price))))
+    }
+
+    fn unit(&self) -> PriceUnit {
+        self.unit
+    }
+}
+
+// What `MultiDayElectricityPrice::maybe_update` did
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PriceUpdate {
+    // Today's prices are current and tomorrow's are known
+    Current,
+    // Today's prices are current and it is too early to fetch tomorrow's
+    TooEarly,
+    // Tomorrow's prices became today's
+    Promoted,
+    // Fetched new prices
+    Fetched,
+    // Fetched prices where tomorrow's prices are for today
+    FetchedTomorrowAsToday,
+    // Fetched prices that have already expired; no prices are known
+    FetchedStale,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq)]
+pub struct MultiDayElectricityPrice {
+    // Feeds may be hourly or at a shorter interval, such as 15 minutes
+    today: Option<PriceSeries>,
+    tomorrow: Option<PriceSeries>,
+}
+
+impl MultiDayElectricityPrice {
+    pub fn new(today: Option<PriceSeries>, tomorrow: Option<PriceSeries>) -> Self {
+        MultiDayElectricityPrice { today, tomorrow }
+    }
+
+    // Parse the JSON feed, discarding prices that have expired at `now`
+    pub fn parse(json: &str, now: PrimitiveDateTime) -> Result<(Self, PriceUpdate)> {
+        let data: MultiDayElectricityPrice = serde_json::from_str(json)?;
+        Ok(data.current(now))
+    }
+
+    // Fetched prices as of `now`, discarding those that have expired
+    fn current(self, now: PrimitiveDateTime) -> (Self, PriceUpdate) {
+        let data = self;
+        if let Some(tomorrow) = &data.tomorrow {
+            if now >= tomorrow.valid_until() {
+                return (Self::default(), PriceUpdate::FetchedStale);
+            }
+            if tomorrow.is_valid_at(now) {
+                let data = MultiDayElectricityPrice {
+                    today: data.tomorrow,
+                    tomorrow: None,
+                };
+                return (data, PriceUpdate::FetchedTomorrowAsToday);
+            }
+        }
+
+        (data, PriceUpdate::Fetched)
+    }
+
+    pub fn today(&self) -> Option<&PriceSeries> {
+        self.today.as_ref()
+    }
+
+    pub fn tomorrow(&self) -> Option<&PriceSeries> {
+        self.tomorrow.as_ref()
+    }
+
+    // Today's prices followed by tomorrow's
+    pub fn days(&self) -> impl Iterator<Item = &PriceSeries> {
+        self.today.iter().chain(self.tomorrow.iter())
+    }
+
+    // Price for the interval containing `time`, in UTC
+    pub fn price_at(&self, time: PrimitiveDateTime) -> Option<ElectricityPrice> {
+        self.days().find_map(|day| day.price_at(time))
+    }
+
+    // All known prices, today and tomorrow
+    pub fn known_prices(&self) -> impl Iterator<Item = ElectricityPrice> + '_ {
+        self.days()
+            .flat_map(|day| day.slots().map(|slot| slot.price))
+    }
+
+    // The same days with each price replaced
+    pub fn map_prices(&self, f: impl Fn(&PriceSlot) -> ElectricityPrice) -> Self {
+        MultiDayElectricityPrice {
+            today: self.today.as_ref().map(|day| day.map_prices(&f)),
+            tomorrow: self.tomorrow.as_ref().map(|day| day.map_prices(&f)),
+        }
+    }
+
+    // Keep the prices current; tomorrow's prices become today's at the
+    // end of the day, and are fetched shortly before the end of today.
+    //
+    // Known prices are kept when fetching fails.
+    pub fn maybe_update<F: PriceFetcher>(
+        &mut self,
+        clock: &impl Clock,
+        provider: &impl PriceProvider,
+        fetcher: &mut F,
+    ) -> Result<PriceUpdate> {
+        let now = clock.now()?;
+
+        let Some(today) = &self.today else {
+            return self.update(provider, fetcher, now);
+        };
+        let fetch_from = today.valid_until() - FETCH_AHEAD;
+
+        let tomorrow = self
+            .tomorrow
+            .as_ref()
+            .map(|tomorrow| (tomorrow.valid_from(), tomorrow.valid_until()));
+        match tomorrow {
+            Some((valid_from, _)) if now < valid_from => Ok(PriceUpdate::Current),
+            Some((_, valid_until)) if now < valid_until => {
+                self.today = self.tomorrow.take();
+                Ok(PriceUpdate::Promoted)
+            }
+            // Both days have expired
+            Some(_) => self.update(provider, fetcher, now),
+            None if now > fetch_from => self.update(provider, fetcher, now),
+            None => Ok(PriceUpdate::TooEarly),
+        }
+    }
+
+    fn update<F: PriceFetcher>(
+        &mut self,
+        provider: &impl PriceProvider,
+        fetcher: &mut F,
+        now: PrimitiveDateTime,
+    ) -> Result<PriceUpdate> {
+        let (data, update) = provider.fetch(fetcher, now)?.current(now);
+        *self = data;
+        Ok(update)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::PriceDelta;
+    use anyhow::anyhow;
+    use time::macros::datetime;
+
+    const SINGLE_DAY: &str = include_str!("../../../electricity-price/singleday.json");
+    use crate::fixtures::MULTI_DAY;
+
+    struct SimulatedClock(PrimitiveDateTime);
+
+    impl Clock for SimulatedClock {
+        fn now(&self) -> Result<PrimitiveDateTime> {
+            Ok(self.0)
+        }
+    }
+
+    // Price server publishing the following day's prices at `publish`
+    struct SimulatedServer {
+        now: PrimitiveDateTime,
+        publish: PrimitiveDateTime,
+        available: bool,
+        fetches: usize,
+    }
+
+    impl SimulatedServer {
+        fn new() -> SimulatedServer {
+            SimulatedServer {
+                now: datetime!(2024-10-24 22:00),
+                publish: datetime!(2024-10-25 11:00),
+                available: true,
+                fetches: 0,
+            }
+        }
+    }
+
+    impl PriceFetcher for SimulatedServer {
+        type Body = &'static str;
+
+        fn fetch(&mut self, _url: &str) -> Result<&'static str> {
+            self.fetches += 1;
+            if !self.available {
+                return Err(anyhow!("Connection refused"));
+            }
+            if self.now < self.publish {
+                Ok(SINGLE_DAY)
+            } else {
+                Ok(MULTI_DAY)
+            }
+        }
+    }
+
+    const FEED: JsonFeed = JsonFeed {
+        url: "http://prices.local/multiday.json",
+        unit: PriceUnit::EUR_CENTS_PER_KWH,
+    };
+
+    fn first_day() -> PrimitiveDateTime {
+        datetime!(2024-10-24 22:00)
+    }
+
+    fn second_day() -> PrimitiveDateTime {
+        datetime!(2024-10-25 22:00)
+    }
+
+    #[test]
+    fn test_parse() {
+        let now = datetime!(2024-10-25 12:00);
+        let (prices, update) = MultiDayElectricityPrice::parse(MULTI_DAY, now).unwrap();
+        assert_eq!(update, PriceUpdate::Fetched);
+        assert_eq!(prices.today().unwrap().valid_from(), first_day());
+        assert_eq!(prices.tomorrow().unwrap().valid_from(), second_day());
+        assert_eq!(prices.known_prices().count(), 48);
+        assert_eq!(
+            prices.price_at(datetime!(2024-10-25 06:30)),
+            Some(ElectricityPrice::new(40.17))
+        );
+
+        let (prices, update) = MultiDayElectricityPrice::parse(SINGLE_DAY, now).unwrap();
+        assert_eq!(update, PriceUpdate::Fetched);
+        assert!(prices.tomorrow().is_none());
+
+        assert!(MultiDayElectricityPrice::parse("{\"today\": {}}", now).is_err());
+    }
+
+    #[test]
+    fn test_parse_late() {
+        // Tomorrow's prices are already current
+        let now = datetime!(2024-10-26 01:00);
+        let (prices, update) = MultiDayElectricityPrice::parse(MULTI_DAY, now).unwrap();
+        assert_eq!(update, PriceUpdate::FetchedTomorrowAsToday);
+        assert_eq!(prices.today().unwrap().valid_from(), second_day());
+        assert!(prices.tomorrow().is_none());
+
+        let now = datetime!(2024-10-26 22:00);
+        let (prices, update) = MultiDayElectricityPrice::parse(MULTI_DAY, now).unwrap();
+        assert_eq!(update, PriceUpdate::FetchedStale);
+        assert_eq!(prices, MultiDayElectricityPrice::default());
+    }
+
+    #[test]
+    fn test_feed_unit() {
+        let now = datetime!(2024-10-25 12:00);
+        let mut server = SimulatedServer::new();
+        server.now = now;
+        let feed = JsonFeed {
+            unit: "EUR/MWh".parse().unwrap(),
+            ..FEED
+        };
+
+        // 40.17 EUR/MWh is 4.017 c/kWh
+        let prices = feed.fetch(&mut server, now).unwrap();
+        let price = prices.price_at(datetime!(2024-10-25 06:30)).unwrap();
+        assert!(price.approx_eq(ElectricityPrice::new(4.017), PriceDelta::new(0.0001)));
+        assert_eq!(feed.unit(), PriceUnit::EUR_PER_MWH);
+
+        let prices = FEED.fetch(&mut server, now).unwrap();
+        assert_eq!(
+            prices.price_at(datetime!(2024-10-25 06:30)),
+            Some(ElectricityPrice::new(40.17))
+        );
+    }
+
+    #[test]
+    fn test_48_hours() {
+        let mut server = SimulatedServer::new();
+        let mut prices = MultiDayElectricityPrice::default();
+        let mut updates = [0usize; 6];
+
+        // Every five minutes, as the measurement timer does
+        let mut now = first_day();
+        while now < second_day() + Duration::DAY {
+            server.now = now;
+            let update = prices
+                .maybe_update(&SimulatedClock(now), &FEED, &mut server)
+                .unwrap();
+            updates[update as usize] += 1;
+
+            assert!(prices.price_at(now).is_some(), "{:?}", now);
+            assert!(prices.today().unwrap().is_valid_at(now), "{:?}", now);
+            match update {
+                PriceUpdate::Promoted => assert_eq!(now, second_day()),
+                PriceUpdate::Fetched => {
+                    assert!(now == first_day() || now > second_day() - FETCH_AHEAD)
+                }
+                _ => {}
+            }
+            if now > second_day() - FETCH_AHEAD && now < second_day() {
+                assert!(prices.tomorrow().is_some(), "{:?}", now);
+            }
+
+            now += Duration::minutes(5);
+        }
+
+        // Fetched at start up and once tomorrow's prices were due
+        assert_eq!(updates[PriceUpdate::Fetched as usize], 2);
+        assert_eq!(updates[PriceUpdate::Promoted as usize], 1);
+        assert_eq!(updates[PriceUpdate::FetchedStale as usize], 0);
+        // The server has no prices for the third day; fetching retries
+        // for the last three hours of the second day
+        assert_eq!(
+            updates[PriceUpdate::FetchedTomorrowAsToday as usize],
+            3 * 12 - 1
+        );
+        assert_eq!(server.fetches, 2 + 3 * 12 - 1);
+    }
+
+    #[test]
+    fn test_fetch_failure_keeps_prices() {
+        let mut server = SimulatedServer::new();
+        let mut prices = MultiDayElectricityPrice::default();
+        prices
+            .maybe_update(&SimulatedClock(first_day()), &FEED, &mut server)
+            .unwrap();
+
+        // Tomorrow's prices are due but the server is down
+        server.available = false;
+        let now = datetime!(2024-10-25 20:00);
+        server.now = now;
+        assert!(prices
+            .maybe_update(&SimulatedClock(now), &FEED, &mut server)
+            .is_err());
+        assert!(prices.price_at(now).is_some());
+
+        // Recovers once the server is back
+        server.available = true;
+        let update = prices
+            .maybe_update(&SimulatedClock(now), &FEED, &mut server)
+            .unwrap();
+        assert_eq!(update, PriceUpdate::Fetched);
+        assert!(prices.tomorrow().is_some());
+    }
+
+    #[test]
+    fn test_expired_prices_are_refetched() {
+        let mut server = SimulatedServer::new();
+        server.now = datetime!(2024-10-25 12:00);
+        let mut prices = MultiDayElectricityPrice::default();
+        prices
+            .maybe_update(&SimulatedClock(server.now), &FEED, &mut server)
+            .unwrap();
+        assert!(prices.tomorrow().is_some());
+
+        // Both days have passed, e.g. after a long loss of power
+        let now = datetime!(2024-10-27 12:00);
+        server.now = now;
+        let update = prices
+            .maybe_update(&SimulatedClock(now), &FEED, &mut server)
+            .unwrap();
+        assert_eq!(update, PriceUpdate::FetchedStale);
+        assert_eq!(prices, MultiDayElectricityPrice::default());
+        assert_eq!(server.fetches, 2);
+    }
+}
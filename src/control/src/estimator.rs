@@ -0,0 +1,392 @@
+use core::time::Duration;
+use serde::{Deserialize, Serialize};
+
+use crate::state::{PowerState, Temperature};
+use crate::thermal::ThermalModel;
+
+// Observations with heating both on and off are needed before the
+// estimate is used
+const MINIMUM_SAMPLES: u32 = 12;
+
+// Intervals outside of these bounds are skipped; very short intervals
+// are dominated by measurement noise, and long gaps break the
+// assumption of constant power over the interval
+const MINIMUM_INTERVAL: Duration = Duration::from_secs(60);
+const MAXIMUM_INTERVAL: Duration = Duration::from_secs(2 * 60 * 60);
+
+// Initial covariance; large values let early samples dominate
+const INITIAL_COVARIANCE: f32 = 1000.0;
+
+// Without excitation, e.g. with heating off for weeks, forgetting grows
+// the covariance without bound until it overflows. Its trace is scaled
+// back to this limit, which keeps the estimator as responsive as when
+// it started.
+const MAXIMUM_COVARIANCE_TRACE: f32 = 3.0 * INITIAL_COVARIANCE;
+
+const SECONDS_PER_HOUR: f32 = 3600.0;
+
+// Learned parameters of the thermal model, suitable for persisting.
+//
+// The model's rate of change is linear in its parameters:
+//
+//   dT/dt = heating_rate * P - cooling_rate * T + cooling_rate * ambient
+//         = theta[0] * P + theta[1] * T + theta[2]
+//
+// The ambient temperature is learned as a constant rather than measured,
+// so losses are relative to a fixed ambient temperature instead of the
+// outdoor temperature. The forgetting factor lets it follow slow
+// changes, such as the seasons, but not day to day weather.
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize, Serialize)]
+pub struct ThermalEstimate {
+    pub theta: [f32; 3],
+    pub covariance: [[f32; 3]; 3],
+    pub heating_samples: u32,
+    pub cooling_samples: u32,
+}
+
+impl Default for ThermalEstimate {
+    fn default() -> Self {
+        let mut covariance = [[0.0; 3]; 3];
+        for (index, row) in covariance.iter_mut().enumerate() {
+            row[index] = INITIAL_COVARIANCE;
+        }
+        Self {
+            theta: [0.0; 3],
+            covariance,
+            heating_samples: 0,
+            cooling_samples: 0,
+        }
+    }
+}
+
+impl ThermalEstimate {
+    // Thermal model from the estimate, once enough samples have been
+    // seen and the parameters are physically plausible
+    pub fn model(&self) -> Option<ThermalModel> {
+        if self.heating_samples < MINIMUM_SAMPLES || self.cooling_samples < MINIMUM_SAMPLES {
+            return None;
+        }
+
+        let heating_rate = self.theta[0];
+        let cooling_rate = -self.theta[1];
+        let ambient = self.theta[2] / cooling_rate;
+        // Comparisons with NaN are false, so check for it explicitly
+        if !heating_rate.is_finite() || !cooling_rate.is_finite() || !ambient.is_finite() {
+            return None;
+        }
+        if heating_rate <= 0.0 || cooling_rate <= 0.0 {
+            return None;
+        }
+
+        Some(ThermalModel {
+            heating_rate,
+            cooling_rate,
+            ambient_temperature: Temperature::new(ambient),
+        })
+    }
+
+    fn is_finite(&self) -> bool {
+        self.theta.iter().all(|value| value.is_finite())
+            && self
+                .covariance
+                .iter()
+                .flatten()
+                .all(|value| value.is_finite())
+    }
+}
+
+// Recursive least squares estimation of the floor's thermal model from
+// a stream of temperature measurements and relay states.
+#[derive(Debug, Clone, Copy)]
+pub struct ThermalEstimator {
+    estimate: ThermalEstimate,
+    // Weight given to older samples, in (0, 1]; lower values track
+    // changes faster, e.g. with the seasons
+    forgetting_factor: f32,
+    previous: Option<(Duration, Temperature)>,
+}
+
+impl ThermalEstimator {
+    pub fn new(forgetting_factor: f32) -> Self {
+        Self::from_estimate(ThermalEstimate::default(), forgetting_factor)
+    }
+
+    // Resume estimation from a persisted estimate; a corrupt estimate
+    // starts over
+    pub fn from_estimate(estimate: ThermalEstimate, forgetting_factor: f32) -> Self {
+        let estimate = if estimate.is_finite() {
+            estimate
+        } else {
+            ThermalEstimate::default()
+        };
+        Self {
+            estimate,
+            forgetting_factor,
+            previous: None,
+        }
+    }
+
+    pub fn estimate(&self) -> &ThermalEstimate {
+        &self.estimate
+    }
+
+    pub fn model(&self) -> Option<ThermalModel> {
+        self.estimate.model()
+    }
+
+    // Add a measurement taken at `now`, a monotonic timestamp, where
+    // `power` is the heating state since the previous measurement.
+    //
+    // Returns whether the estimate was updated.
+    pub fn update(&mut self, now: Duration, temperature: Temperature, power: PowerState) -> bool {
+        let previous = self.previous.replace((now, temperature));
+        let Some((previous_time, previous_temperature)) = previous else {
+            return false;
+        };
+
+        let interval = now.saturating_sub(previous_time);
+        if !(MINIMUM_INTERVAL..=MAXIMUM_INTERVAL).contains(&interval) {
+            return false;
+        }
+
+        let hours = interval.as_secs_f32() / SECONDS_PER_HOUR;
+        let start = f32::from(previous_temperature);
+        let end = f32::from(temperature);
+
+        let rate = (end - start) / hours;
+        let heating = match power {
+            PowerState::On => 1.0,
+            PowerState::Off => 0.0,
+        };
+        let regressor = [heating, (start + end) / 2.0, 1.0];
+
+        if !self.add_sample(regressor, rate) {
+            return false;
+        }
+
+        match power {
+            PowerState::On => self.estimate.heating_samples += 1,
+            PowerState::Off => self.estimate.cooling_samples += 1,
+        }
+        true
+    }
+
+    // Returns whether the sample was used; samples that would make the
+    // estimate non-finite are discarded
+    fn add_sample(&mut self, x: [f32; 3], y: f32) -> bool {
+        let lambda = self.forgetting_factor;
+        let mut estimate = self.estimate;
+        let theta = &mut estimate.theta;
+        let p = &mut estimate.covariance;
+
+        // P x
+        let mut px = [0.0; 3];
+        for (row, value) in px.iter_mut().enumerate() {
+            *value = (0..3).map(|column| p[row][column] * x[column]).sum();
+        }
+
+        // Gain k = P x / (lambda + x' P x)
+        let denominator = lambda + (0..3).map(|index| x[index] * px[index]).sum::<f32>();
+        let gain = px.map(|value| value / denominator);
+
+        let error = y - (0..3).map(|index| x[index] * theta[index]).sum::<f32>();
+        for (value, gain) in theta.iter_mut().zip(gain) {
+            *value += gain * error;
+        }
+
+        // P = (P - k x' P) / lambda; P is symmetric so x' P = (P x)'
+        for (row, gain) in gain.iter().enumerate() {
+            for (column, px) in px.iter().enumerate() {
+                p[row][column] = (p[row][column] - gain * px) / lambda;
+            }
+        }
+
+        let trace: f32 = (0..3).map(|index| p[index][index]).sum();
+        if trace > MAXIMUM_COVARIANCE_TRACE {
+            let scale = MAXIMUM_COVARIANCE_TRACE / trace;
+            for value in p.iter_mut().flatten() {
+                *value *= scale;
+            }
+        }
+
+        if !estimate.is_finite() {
+            return false;
+        }
+        self.estimate = estimate;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::TemperatureDelta;
+
+    fn model() -> ThermalModel {
+        ThermalModel {
+            heating_rate: 2.0,
+            cooling_rate: 0.1,
+            ambient_temperature: Temperature::new(10.0),
+        }
+    }
+
+    // Simulate a bang-bang thermostat between 18 and 24 degrees,
+    // measuring every five minutes, with optional measurement noise
+    fn simulate(estimator: &mut ThermalEstimator, steps: usize, noise: f32) {
+        let model = model();
+        let step = Duration::from_secs(300);
+
+        let mut temperature = Temperature::new(20.0);
+        let mut power = PowerState::On;
+        let mut seed: u32 = 12345;
+        for index in 0..steps {
+            // Linear congruential generator for repeatable noise
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let random = (seed >> 16) as f32 / 32768.0 - 1.0;
+            let measured = temperature + TemperatureDelta::new(noise * random);
+
+            estimator.update(step * index as u32, measured, power);
+
+            if temperature > Temperature::new(24.0) {
+                power = PowerState::Off;
+            } else if temperature < Temperature::new(18.0) {
+                power = PowerState::On;
+            }
+            temperature = model.predict(temperature, power, step.as_secs_f32() / SECONDS_PER_HOUR);
+        }
+    }
+
+    fn assert_close(value: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (value - expected).abs() < tolerance,
+            "{:?} != {:?}",
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_no_model_without_samples() {
+        let mut estimator = ThermalEstimator::new(1.0);
+        assert_eq!(estimator.model(), None);
+
+        // Heating only; the cooling rate cannot be identified
+        for index in 0..30 {
+            let temperature = Temperature::new(18.0 + index as f32 * 0.1);
+            estimator.update(
+                Duration::from_secs(300 * index),
+                temperature,
+                PowerState::On,
+            );
+        }
+        assert_eq!(estimator.estimate().heating_samples, 29);
+        assert_eq!(estimator.model(), None);
+    }
+
+    #[test]
+    fn test_skip_irregular_intervals() {
+        let mut estimator = ThermalEstimator::new(1.0);
+        let temperature = Temperature::new(20.0);
+
+        assert!(!estimator.update(Duration::from_secs(0), temperature, PowerState::Off));
+        assert!(!estimator.update(Duration::from_secs(10), temperature, PowerState::Off));
+        assert!(estimator.update(Duration::from_secs(310), temperature, PowerState::Off));
+        assert!(!estimator.update(
+            Duration::from_secs(3 * 60 * 60),
+            temperature,
+            PowerState::Off
+        ));
+        assert_eq!(estimator.estimate().cooling_samples, 1);
+    }
+
+    #[test]
+    fn test_learn_synthetic_trace() {
+        let mut estimator = ThermalEstimator::new(1.0);
+        simulate(&mut estimator, 2000, 0.0);
+
+        let learned = estimator.model().unwrap();
+        assert_close(learned.heating_rate, 2.0, 0.05);
+        assert_close(learned.cooling_rate, 0.1, 0.005);
+        let ambient = learned.ambient_temperature;
+        let expected = Temperature::new(10.0);
+        assert!(
+            ambient.approx_eq(expected, TemperatureDelta::new(0.5)),
+            "{}",
+            ambient
+        );
+    }
+
+    #[test]
+    fn test_learn_noisy_trace() {
+        let mut estimator = ThermalEstimator::new(1.0);
+        simulate(&mut estimator, 4000, 0.05);
+
+        let learned = estimator.model().unwrap();
+        assert_close(learned.heating_rate, 2.0, 0.2);
+        assert_close(learned.cooling_rate, 0.1, 0.02);
+        let ambient = learned.ambient_temperature;
+        let expected = Temperature::new(10.0);
+        assert!(
+            ambient.approx_eq(expected, TemperatureDelta::new(2.0)),
+            "{}",
+            ambient
+        );
+    }
+
+    #[test]
+    fn test_unexcited_trace() {
+        let mut estimator = ThermalEstimator::new(0.999);
+        simulate(&mut estimator, 2000, 0.0);
+        let learned = estimator.model().unwrap();
+        let samples = estimator.estimate().cooling_samples;
+
+        // A year with heating off and the floor settled at ambient,
+        // measuring every five minutes
+        let start = Duration::from_secs(300 * 2000);
+        for index in 0..105_000 {
+            estimator.update(
+                start + Duration::from_secs(300 * index),
+                Temperature::new(10.0),
+                PowerState::Off,
+            );
+        }
+
+        // Every sample is used, none discarded as non-finite
+        let estimate = estimator.estimate();
+        assert_eq!(estimate.cooling_samples, samples + 105_000);
+        assert!(estimate.is_finite());
+        let trace: f32 = (0..3).map(|index| estimate.covariance[index][index]).sum();
+        assert!(trace <= MAXIMUM_COVARIANCE_TRACE * 1.001, "{:?}", trace);
+
+        // The heating rate was not observed, so it is still roughly known
+        let model = estimator.model().unwrap();
+        assert_close(model.heating_rate, learned.heating_rate, 0.5);
+        assert!(f32::from(model.ambient_temperature).is_finite());
+    }
+
+    #[test]
+    fn test_reject_non_finite_estimate() {
+        let mut estimator = ThermalEstimator::new(1.0);
+        simulate(&mut estimator, 2000, 0.0);
+
+        let mut estimate = *estimator.estimate();
+        estimate.theta[0] = f32::NAN;
+        assert_eq!(estimate.model(), None);
+        estimate.theta[0] = 2.0;
+        estimate.theta[2] = f32::INFINITY;
+        assert_eq!(estimate.model(), None);
+
+        // A corrupt persisted estimate starts over
+        let resumed = ThermalEstimator::from_estimate(estimate, 1.0);
+        assert_eq!(*resumed.estimate(), ThermalEstimate::default());
+    }
+
+    #[test]
+    fn test_resume_from_estimate() {
+        let mut estimator = ThermalEstimator::new(1.0);
+        simulate(&mut estimator, 2000, 0.0);
+
+        let resumed = ThermalEstimator::from_estimate(*estimator.estimate(), 1.0);
+        assert_eq!(resumed.model(), estimator.model());
+    }
+}
@@ -0,0 +1,299 @@
+use time::PrimitiveDateTime;
+
+use crate::away::AwayMode;
+use crate::config::CoreConfig;
+use crate::rank::price_rank;
+use crate::state::{ElectricityPrice, PowerState};
+use crate::thermal::ThermalModel;
+use crate::{SetPoint, SetPointReason};
+
+const SECONDS_PER_HOUR: f32 = 3600.0;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FallbackConfig {
+    // Fraction of each hour heating is on while the sensor is broken,
+    // from 0.0 to 1.0
+    pub duty_cycle: f32,
+
+    // Shift heating toward the cheapest hours of the known prices,
+    // keeping the same average duty cycle
+    pub price_biased: bool,
+
+    // Consecutive failed measurements before the fallback duty cycle
+    // starts; heating is off until then
+    pub failure_threshold: u8,
+}
+
+// Time-proportional heating while the temperature sensor is unreadable,
+// so that the floor does not freeze without a working sensor
+#[derive(Debug, Clone, Copy)]
+pub struct SensorFallback {
+    config: FallbackConfig,
+    failures: u8,
+}
+
+impl SensorFallback {
+    pub fn new(config: FallbackConfig) -> SensorFallback {
+        SensorFallback {
+            config,
+            failures: 0,
+        }
+    }
+
+    // Record the outcome of a measurement; a single valid measurement
+    // leaves the fallback mode
+    pub fn record(&mut self, valid: bool) -> bool {
+        if valid {
+            self.failures = 0;
+        } else {
+            self.failures = self.failures.saturating_add(1);
+        }
+        self.is_active()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.failures > 0 && self.failures >= self.config.failure_threshold
+    }
+
+    // Fraction of the hour heating is on at the current price
+    pub fn on_fraction(&self, price: Option<ElectricityPrice>, window: &[ElectricityPrice]) -> f32 {
+        let duty_cycle = self.config.duty_cycle.clamp(0.0, 1.0);
+        if !self.config.price_biased {
+            return duty_cycle;
+        }
+
+        // The rank averages 0.5 over the window, so scaling by twice
+        // its complement preserves the average duty cycle
+        match price.and_then(|price| price_rank(price, window)) {
+            Some(rank) => (duty_cycle * 2.0 * (1.0 - rank)).clamp(0.0, 1.0),
+            None => duty_cycle,
+        }
+    }
+
+    // Heating is on for the first part of each hour, or None when the
+    // sensor is not in the fallback mode
+    pub fn set_point(
+        &self,
+        config: &CoreConfig,
+        now: PrimitiveDateTime,
+        price: Option<ElectricityPrice>,
+        window: &[ElectricityPrice],
+    ) -> Option<SetPoint> {
+        if !self.is_active() {
+            return None;
+        }
+
+        let on_fraction = self.on_fraction(price, window);
+        let power = if elapsed_fraction(now) < on_fraction {
+            PowerState::On
+        } else {
+            PowerState::Off
+        };
+        let temperature = match power {
+            PowerState::On => config.maximum_temperature,
+            PowerState::Off => config.minimum_temperature,
+        };
+        Some(SetPoint {
+            power,
+            temperature,
+            reason: SetPointReason::SensorFallback,
+        })
+    }
+
+    // While away, heating is on only for as much of each hour as the
+    // thermal model needs to hold the frost protection temperature, and
+    // never for more than the configured duty cycle
+    pub fn away_set_point(
+        &self,
+        away: &AwayMode,
+        model: &ThermalModel,
+        now: PrimitiveDateTime,
+    ) -> Option<SetPoint> {
+        if !self.is_active() {
+            return None;
+        }
+
+        let duty_cycle = self.config.duty_cycle.clamp(0.0, 1.0);
+        let on_fraction = model
+            .holding_fraction(away.frost_protection_temperature)
+            .min(duty_cycle);
+        let power = if elapsed_fraction(now) < on_fraction {
+            PowerState::On
+        } else {
+            PowerState::Off
+        };
+        Some(SetPoint {
+            power,
+            ..away.set_point()
+        })
+    }
+}
+
+// Fraction of the current hour that has elapsed
+fn elapsed_fraction(now: PrimitiveDateTime) -> f32 {
+    (now.minute() as f32 * 60.0 + now.second() as f32) / SECONDS_PER_HOUR
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{settings, today_prices};
+    use crate::state::Temperature;
+    use time::macros::datetime;
+    use time::Duration;
+
+    fn fallback(price_biased: bool) -> SensorFallback {
+        SensorFallback::new(FallbackConfig {
+            duty_cycle: 0.25,
+            price_biased,
+            failure_threshold: 3,
+        })
+    }
+
+    // Minutes heating is on over an hour, sampled each minute
+    fn minutes_on(
+        fallback: &SensorFallback,
+        price: Option<ElectricityPrice>,
+        window: &[ElectricityPrice],
+    ) -> usize {
+        let settings = settings();
+        let start = datetime!(2024-10-25 03:00);
+        (0..60)
+            .filter(|minute| {
+                let now = start + Duration::minutes(*minute);
+                fallback
+                    .set_point(&settings, now, price, window)
+                    .unwrap()
+                    .power
+                    == PowerState::On
+            })
+            .count()
+    }
+
+    #[test]
+    fn test_mode_transition() {
+        let mut fallback = fallback(false);
+        assert!(!fallback.is_active());
+
+        assert!(!fallback.record(false));
+        assert!(!fallback.record(false));
+        assert!(fallback.record(false));
+        assert!(fallback.record(false));
+
+        // A valid measurement leaves the fallback mode
+        assert!(!fallback.record(true));
+        assert!(!fallback.record(false));
+
+        let set_point = fallback.set_point(&settings(), datetime!(2024-10-25 03:00), None, &[]);
+        assert_eq!(set_point, None);
+    }
+
+    #[test]
+    fn test_duty_cycle() {
+        let mut fallback = fallback(false);
+        for _ in 0..3 {
+            fallback.record(false);
+        }
+
+        assert_eq!(minutes_on(&fallback, None, &[]), 15);
+
+        let set_point = fallback
+            .set_point(&settings(), datetime!(2024-10-25 03:14:59), None, &[])
+            .unwrap();
+        assert_eq!(set_point.power, PowerState::On);
+        let set_point = fallback
+            .set_point(&settings(), datetime!(2024-10-25 03:15), None, &[])
+            .unwrap();
+        assert_eq!(set_point.power, PowerState::Off);
+    }
+
+    #[test]
+    fn test_price_biased_duty_cycle() {
+        let mut fallback = fallback(true);
+        for _ in 0..3 {
+            fallback.record(false);
+        }
+        let window = today_prices();
+
+        // The cheapest hour heats for twice the duty cycle, and the
+        // most expensive not at all
+        let cheapest = Some(ElectricityPrice::new(2.0));
+        assert_eq!(minutes_on(&fallback, cheapest, &window), 30);
+        let most_expensive = Some(ElectricityPrice::new(40.17));
+        assert_eq!(minutes_on(&fallback, most_expensive, &window), 0);
+
+        // The duty cycle averages out over the window
+        let total: f32 = window
+            .iter()
+            .map(|price| fallback.on_fraction(Some(*price), &window))
+            .sum();
+        let average = total / window.len() as f32;
+        assert!((average - 0.25).abs() < 0.001, "{:?}", average);
+
+        // Without prices to rank against
+        assert_eq!(minutes_on(&fallback, None, &window), 15);
+        assert_eq!(minutes_on(&fallback, cheapest, &[]), 15);
+    }
+
+    #[test]
+    fn test_away_duty_cycle() {
+        let mut fallback = fallback(false);
+        let away = AwayMode {
+            frost_protection_temperature: Temperature::new(8.0),
+            return_time: datetime!(2024-10-27 18:00),
+            returning: false,
+        };
+        let model = ThermalModel {
+            heating_rate: 2.0,
+            cooling_rate: 0.1,
+            ambient_temperature: Temperature::new(5.0),
+        };
+        let minutes_on = |fallback: &SensorFallback, model: &ThermalModel| {
+            let start = datetime!(2024-10-25 03:00);
+            (0..60)
+                .filter(|minute| {
+                    let now = start + Duration::minutes(*minute);
+                    let set_point = fallback.away_set_point(&away, model, now).unwrap();
+                    assert_eq!(set_point.reason, SetPointReason::Away);
+                    set_point.power == PowerState::On
+                })
+                .count()
+        };
+
+        assert_eq!(
+            fallback.away_set_point(&away, &model, datetime!(2024-10-25 03:00)),
+            None
+        );
+        for _ in 0..3 {
+            fallback.record(false);
+        }
+
+        // Holding 8 degrees against 5 degrees outside needs 15%
+        assert_eq!(minutes_on(&fallback, &model), 9);
+
+        // No more than the configured duty cycle in the cold
+        let cold = ThermalModel {
+            ambient_temperature: Temperature::new(-20.0),
+            ..model
+        };
+        assert_eq!(minutes_on(&fallback, &cold), 15);
+
+        // Off when the surroundings alone keep the floor warm enough
+        let warm = ThermalModel {
+            ambient_temperature: Temperature::new(12.0),
+            ..model
+        };
+        assert_eq!(minutes_on(&fallback, &warm), 0);
+    }
+
+    #[test]
+    fn test_inactive_without_failures() {
+        let fallback = SensorFallback::new(FallbackConfig {
+            duty_cycle: 1.0,
+            price_biased: false,
+            failure_threshold: 0,
+        });
+        // Never active without a failure
+        assert!(!fallback.is_active());
+    }
+}
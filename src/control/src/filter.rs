@@ -0,0 +1,353 @@
+use anyhow::{bail, Result};
+
+use crate::state::{Temperature, TemperatureDelta};
+
+// Largest burst of conversions taken for a single measurement
+pub const MAX_BURST_SAMPLES: usize = 16;
+
+// Median of a burst of samples, sorting the samples in place
+pub fn median(samples: &mut [f32]) -> Option<f32> {
+    if samples.is_empty() {
+        return None;
+    }
+
+    samples.sort_unstable_by(|a, b| a.total_cmp(b));
+    let middle = samples.len() / 2;
+    if samples.len() % 2 == 0 {
+        Some((samples[middle - 1] + samples[middle]) / 2.0)
+    } else {
+        Some(samples[middle])
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Smoothing {
+    // Use each measurement as is
+    None,
+    // Exponential moving average; `alpha` is the weight of the newest
+    // measurement, in (0, 1]
+    Exponential {
+        alpha: f32,
+    },
+    // One dimensional Kalman filter for a slowly drifting temperature,
+    // with variances in degrees squared
+    Kalman {
+        process_variance: f32,
+        measurement_variance: f32,
+    },
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FilterConfig {
+    pub smoothing: Smoothing,
+
+    // Measurements further than this from the filtered temperature are
+    // rejected as outliers
+    pub outlier_threshold: TemperatureDelta,
+
+    // Consecutive outliers after which the filter accepts the
+    // measurement as a real change in temperature
+    pub maximum_rejections: u8,
+}
+
+impl FilterConfig {
+    // Check the smoothing weights and variances, and the outlier
+    // threshold
+    pub fn validate(&self) -> Result<()> {
+        match self.smoothing {
+            Smoothing::None => {}
+            Smoothing::Exponential { alpha } => {
+                if !(alpha > 0.0 && alpha <= 1.0) {
+                    bail!("Smoothing weight {} must be above 0 and at most 1", alpha);
+                }
+            }
+            Smoothing::Kalman {
+                process_variance,
+                measurement_variance,
+            } => {
+                if !process_variance.is_finite() || process_variance < 0.0 {
+                    bail!("Process variance {} must not be negative", process_variance);
+                }
+                if !measurement_variance.is_finite() || measurement_variance < 0.0 {
+                    bail!(
+                        "Measurement variance {} must not be negative",
+                        measurement_variance
+                    );
+                }
+            }
+        }
+        let threshold = f32::from(self.outlier_threshold);
+        if threshold.is_nan() || threshold <= 0.0 {
+            bail!(
+                "Outlier threshold {} must be positive",
+                self.outlier_threshold
+            );
+        }
+        Ok(())
+    }
+}
+
+// Smoothing across measurements, with outlier rejection
+#[derive(Debug, Clone, Copy)]
+pub struct MeasurementFilter {
+    config: FilterConfig,
+    estimate: Option<Temperature>,
+    // Variance of the estimate for `Smoothing::Kalman`
+    variance: f32,
+    rejections: u8,
+}
+
+impl MeasurementFilter {
+    pub fn new(config: FilterConfig) -> MeasurementFilter {
+        MeasurementFilter {
+            config,
+            estimate: None,
+            variance: 0.0,
+            rejections: 0,
+        }
+    }
+
+    pub fn estimate(&self) -> Option<Temperature> {
+        self.estimate
+    }
+
+    // Add a measurement, returning the filtered temperature
+    pub fn update(&mut self, measurement: Temperature) -> Temperature {
+        let Some(estimate) = self.estimate else {
+            return self.reset(measurement);
+        };
+
+        if (measurement - estimate).abs() > self.config.outlier_threshold {
+            if self.rejections >= self.config.maximum_rejections {
+                return self.reset(measurement);
+            }
+            self.rejections = self.rejections.saturating_add(1);
+            return estimate;
+        }
+        self.rejections = 0;
+
+        let estimate = match self.config.smoothing {
+            Smoothing::None => measurement,
+            Smoothing::Exponential { alpha } => estimate + (measurement - estimate) * alpha,
+            Smoothing::Kalman {
+                process_variance,
+                measurement_variance,
+            } => {
+                let variance = self.variance + process_variance;
+                let gain = variance / (variance + measurement_variance);
+                self.variance = (1.0 - gain) * variance;
+                estimate + (measurement - estimate) * gain
+            }
+        };
+        self.estimate = Some(estimate);
+        estimate
+    }
+
+    fn reset(&mut self, measurement: Temperature) -> Temperature {
+        self.estimate = Some(measurement);
+        self.rejections = 0;
+        if let Smoothing::Kalman {
+            measurement_variance,
+            ..
+        } = self.config.smoothing
+        {
+            self.variance = measurement_variance;
+        }
+        measurement
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    // Repeatable noise in [-1, 1)
+    struct Noise(u32);
+
+    impl Noise {
+        fn next(&mut self) -> f32 {
+            self.0 = self.0.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (self.0 >> 16) as f32 / 32768.0 - 1.0
+        }
+    }
+
+    fn config(smoothing: Smoothing) -> FilterConfig {
+        FilterConfig {
+            smoothing,
+            outlier_threshold: TemperatureDelta::new(2.0),
+            maximum_rejections: 3,
+        }
+    }
+
+    // Root mean square error of the filtered temperature against a
+    // constant true temperature, with uniform noise and occasional
+    // spikes
+    fn filtered_error(filter: &mut MeasurementFilter, noise: f32) -> f32 {
+        let mut random = Noise(12345);
+        let actual = Temperature::new(20.0);
+        let mut squared_error = 0.0;
+        let count = 500;
+        for index in 0..count {
+            let spike = if index % 50 == 25 { 10.0 } else { 0.0 };
+            let measured = actual + TemperatureDelta::new(noise * random.next() + spike);
+            let error = f32::from(filter.update(measured) - actual);
+            if index >= 20 {
+                squared_error += error * error;
+            }
+        }
+        (squared_error / (count - 20) as f32).sqrt()
+    }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(&mut []), None);
+        assert_eq!(median(&mut [3.0]), Some(3.0));
+        assert_eq!(median(&mut [3.0, 1.0, 2.0]), Some(2.0));
+        assert_eq!(median(&mut [4.0, 1.0, 3.0, 2.0]), Some(2.5));
+
+        // A single wild sample does not move the median
+        assert_eq!(median(&mut [20.1, 19.9, 85.0, 20.0, 20.2]), Some(20.1));
+    }
+
+    #[test]
+    fn test_median_of_noisy_burst() {
+        let mut random = Noise(54321);
+        let mut burst = [0.0; MAX_BURST_SAMPLES];
+        for sample in burst.iter_mut() {
+            *sample = 20.0 + 0.5 * random.next();
+        }
+        burst[3] = -40.0;
+        burst[9] = 125.0;
+
+        let median = median(&mut burst[..15]).unwrap();
+        assert!((median - 20.0).abs() < 0.25, "{:?}", median);
+    }
+
+    #[test]
+    fn test_no_smoothing() {
+        let mut filter = MeasurementFilter::new(config(Smoothing::None));
+        assert_eq!(filter.estimate(), None);
+
+        let filtered = filter.update(Temperature::new(20.0));
+        assert_eq!(filtered, Temperature::new(20.0));
+        let filtered = filter.update(Temperature::new(20.5));
+        assert_eq!(filtered, Temperature::new(20.5));
+        assert_eq!(filter.estimate(), Some(Temperature::new(20.5)));
+    }
+
+    #[test]
+    fn test_exponential_smoothing_reduces_noise() {
+        let mut unfiltered = MeasurementFilter::new(config(Smoothing::None));
+        let mut filter = MeasurementFilter::new(config(Smoothing::Exponential { alpha: 0.2 }));
+
+        let unfiltered_error = filtered_error(&mut unfiltered, 0.5);
+        let error = filtered_error(&mut filter, 0.5);
+        assert!(error < unfiltered_error / 2.0, "{:?}", error);
+    }
+
+    #[test]
+    fn test_kalman_smoothing_reduces_noise() {
+        let mut unfiltered = MeasurementFilter::new(config(Smoothing::None));
+        let mut filter = MeasurementFilter::new(config(Smoothing::Kalman {
+            process_variance: 0.001,
+            measurement_variance: 0.1,
+        }));
+
+        let unfiltered_error = filtered_error(&mut unfiltered, 0.5);
+        let error = filtered_error(&mut filter, 0.5);
+        assert!(error < unfiltered_error / 2.0, "{:?}", error);
+    }
+
+    #[test]
+    fn test_reject_outliers() {
+        let mut filter = MeasurementFilter::new(config(Smoothing::Exponential { alpha: 0.5 }));
+        filter.update(Temperature::new(20.0));
+
+        let filtered = filter.update(Temperature::new(30.0));
+        assert_eq!(filtered, Temperature::new(20.0));
+
+        let filtered = filter.update(Temperature::new(21.0));
+        assert_eq!(filtered, Temperature::new(20.5));
+    }
+
+    #[test]
+    fn test_accept_sustained_change() {
+        let mut filter = MeasurementFilter::new(config(Smoothing::Exponential { alpha: 0.5 }));
+        filter.update(Temperature::new(20.0));
+
+        for _ in 0..3 {
+            let filtered = filter.update(Temperature::new(25.0));
+            assert_eq!(filtered, Temperature::new(20.0));
+        }
+        let filtered = filter.update(Temperature::new(25.0));
+        assert_eq!(filtered, Temperature::new(25.0));
+    }
+
+    #[test]
+    fn test_maximum_rejections_limit() {
+        let mut filter = MeasurementFilter::new(FilterConfig {
+            maximum_rejections: u8::MAX,
+            ..config(Smoothing::None)
+        });
+        filter.update(Temperature::new(20.0));
+
+        for _ in 0..u8::MAX {
+            let filtered = filter.update(Temperature::new(25.0));
+            assert_eq!(filtered, Temperature::new(20.0));
+        }
+        let filtered = filter.update(Temperature::new(25.0));
+        assert_eq!(filtered, Temperature::new(25.0));
+    }
+
+    #[test]
+    fn test_validate() {
+        config(Smoothing::None).validate().unwrap();
+        config(Smoothing::Exponential { alpha: 1.0 })
+            .validate()
+            .unwrap();
+        let kalman = |process_variance, measurement_variance| {
+            config(Smoothing::Kalman {
+                process_variance,
+                measurement_variance,
+            })
+            .validate()
+        };
+        kalman(0.0, 0.1).unwrap();
+
+        for alpha in [0.0, -0.5, 1.5, f32::NAN] {
+            let error = config(Smoothing::Exponential { alpha })
+                .validate()
+                .unwrap_err();
+            assert!(
+                format!("{}", error).contains("Smoothing weight"),
+                "{}",
+                error
+            );
+        }
+        let error = kalman(-0.001, 0.1).unwrap_err();
+        assert!(
+            format!("{}", error).contains("Process variance"),
+            "{}",
+            error
+        );
+        let error = kalman(0.001, f32::INFINITY).unwrap_err();
+        assert!(
+            format!("{}", error).contains("Measurement variance"),
+            "{}",
+            error
+        );
+
+        let error = FilterConfig {
+            outlier_threshold: TemperatureDelta::new(0.0),
+            ..config(Smoothing::None)
+        }
+        .validate()
+        .unwrap_err();
+        assert!(
+            format!("{}", error).contains("Outlier threshold"),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,53 @@
+// Fixtures shared by the tests of several modules
+use alloc::vec::Vec;
+use time::macros::datetime;
+
+use crate::config::{CoreConfig, PriceMode};
+use crate::electricity_price::MultiDayElectricityPrice;
+use crate::state::{ElectricityPrice, PriceSlot, Temperature, TemperatureDelta};
+
+pub const MULTI_DAY: &str = include_str!("../../../electricity-price/multiday.json");
+
+// Settings for tests; tests override the fields they exercise
+pub fn settings() -> CoreConfig {
+    CoreConfig {
+        minimum_temperature: Temperature::new(15.0),
+        fallback_minimum_temperature: Temperature::new(18.0),
+        maximum_temperature: Temperature::new(22.0),
+        turbo_temperature: Temperature::new(30.0),
+        maximum_price: ElectricityPrice::new(30.0),
+        price_mode: PriceMode::Absolute,
+        upper_hysteresis: TemperatureDelta::new(0.0),
+        lower_hysteresis: TemperatureDelta::new(0.0),
+    }
+}
+
+fn multi_day() -> MultiDayElectricityPrice {
+    let (prices, _) = MultiDayElectricityPrice::parse(MULTI_DAY, datetime!(2024-10-24 22:00))
+        .expect("Invalid multiday.json");
+    prices
+}
+
+// Today's hourly prices from electricity-price/multiday.json, from
+// 2024-10-24 22:00 UTC
+pub fn today() -> Vec<PriceSlot> {
+    multi_day()
+        .today()
+        .expect("No prices for today in multiday.json")
+        .slots()
+        .copied()
+        .collect()
+}
+
+pub fn today_prices() -> Vec<ElectricityPrice> {
+    today().iter().map(|slot| slot.price).collect()
+}
+
+// Today and tomorrow's prices from electricity-price/multiday.json
+pub fn price_window() -> Vec<ElectricityPrice> {
+    multi_day()
+        .days()
+        .flat_map(|day| day.slots())
+        .map(|slot| slot.price)
+        .collect()
+}
@@ -1,83 +1,258 @@
 #![no_std]
 
+extern crate alloc;
+
+mod away;
+mod calibration;
+mod clock;
 mod config;
+mod electricity_price;
+mod estimator;
+mod fallback;
+mod filter;
+#[cfg(test)]
+mod fixtures;
+mod pid;
+mod plan;
+mod preheat;
+mod provider;
+mod pwm;
+mod rank;
+mod relay;
+mod schedule;
+mod series;
 mod state;
+mod tariff;
+mod thermal;
 mod thermistor;
+mod unit;
+mod value;
 
-pub use config::CoreConfig;
-pub use state::{ElectricityPrice, PowerState, Temperature};
-pub use thermistor::temperature_from_voltage;
+use serde::{Deserialize, Serialize};
 
-pub fn select_temperature(config: &CoreConfig, current_price: ElectricityPrice) -> Temperature {
-    let max_price = f32::from(config.maximum_price);
-    let price_difference = max_price - f32::from(current_price);
+pub use away::{AwayMode, AwayState};
+pub use calibration::{calibrate, CalibrationPoint};
+pub use clock::{ClockTime, TimeZone};
+pub use config::{ControlStrategy, CoreConfig, PriceMode};
+pub use electricity_price::{Clock, JsonFeed, MultiDayElectricityPrice, PriceFetcher, PriceUpdate};
+pub use estimator::{ThermalEstimate, ThermalEstimator};
+pub use fallback::{FallbackConfig, SensorFallback};
+pub use filter::{median, FilterConfig, MeasurementFilter, Smoothing, MAX_BURST_SAMPLES};
+pub use pid::{Pid, PidConfig};
+pub use plan::{HeatingBudget, HeatingPlan, PlannedSlot, MAX_PLAN_SLOTS};
+pub use preheat::{PreheatAction, PreheatPlan, PreheatSlot};
+pub use provider::{
+    Awattar, Entsoe, NordPool, Octopus, PriceProvider, PriceSource, CENTRAL_EUROPEAN_TIME,
+};
+pub use pwm::{PwmConfig, PwmCycle, TimeProportional};
+pub use rank::{price_rank, select_temperature_for_rank};
+pub use relay::{RelayConfig, RelayProtection, SwitchPriority};
+pub use schedule::{
+    ComfortSchedule, DayProfile, ScheduleBlock, TemperatureRange, MAX_SCHEDULE_BLOCKS,
+};
+pub use series::{PriceSeries, MAX_SERIES_SLOTS};
+pub use state::{
+    ElectricityPrice, PowerState, PriceDelta, PriceSlot, Temperature, TemperatureDelta,
+};
+pub use tariff::{FeeWindow, NetworkFees, Tariff, MAX_FEE_WINDOWS};
+pub use thermal::ThermalModel;
+pub use thermistor::{
+    temperature_from_resistance, temperature_from_voltage, SensorFault, ThermistorModel,
+    ThermistorProperties,
+};
+pub use unit::{price_scale_mismatch, Currency, PriceScale, PriceUnit};
+
+// Fraction of the temperature range selected by the price, from 1.0 at
+// zero price to 0.0 at the maximum price
+fn price_fraction(config: &CoreConfig, current_price: ElectricityPrice) -> f32 {
+    let max_price = config.maximum_price.as_delta();
+    let price_difference = config.maximum_price - current_price;
 
-    let scaling_factor = price_difference / max_price;
+    price_difference / max_price
+}
+
+pub fn select_temperature(config: &CoreConfig, current_price: ElectricityPrice) -> Temperature {
+    let scaling_factor = price_fraction(config, current_price);
 
-    let temperature_range =
-        f32::from(config.maximum_temperature) - f32::from(config.minimum_temperature);
+    let temperature_range = config.maximum_temperature - config.minimum_temperature;
     let temperature_delta = temperature_range * scaling_factor;
 
-    let set_temperature = f32::from(config.minimum_temperature) + temperature_delta;
+    config.minimum_temperature + temperature_delta
+}
 
-    Temperature::new(set_temperature)
+// Why a set point was chosen
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize, Serialize)]
+pub enum SetPointReason {
+    // Temperature above the maximum
+    OverMaximum,
+    // Temperature below the minimum; heating to the turbo temperature
+    BelowMinimumTurbo,
+    // Electricity price above the maximum price
+    PriceAboveCap,
+    // Temperature set `fraction` of the way from the minimum to the
+    // maximum by the electricity price
+    PriceScaled { fraction: f32 },
+    // No electricity price; heating up to the fallback minimum
+    NoPriceFallbackHeat,
+    // No electricity price; above the fallback minimum
+    NoPriceFallbackIdle,
+    // The temperature sensor is faulty or the reading is invalid
+    SensorFault,
+    // Heating on a duty cycle while the sensor is unreadable
+    SensorFallback,
+    // Following a plan over the price curve
+    Planned,
+    // Holding the frost protection temperature while away
+    Away,
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct SetPoint {
     pub power: PowerState,
     pub temperature: Temperature,
+    pub reason: SetPointReason,
 }
 
 impl SetPoint {
-    pub fn from_current_state(
+    // Limits on the floor temperature that apply regardless of price
+    fn from_temperature_limits(
         config: &CoreConfig,
         current_temperature: Temperature,
-        current_price: Option<ElectricityPrice>,
-    ) -> SetPoint {
-        if current_temperature > config.maximum_temperature {
-            return SetPoint {
+        previous_power: PowerState,
+    ) -> Option<SetPoint> {
+        // Never act on an invalid reading
+        if !current_temperature.is_finite() {
+            return Some(SetPoint::from_sensor_fault(config));
+        }
+
+        if config.is_above(
+            current_temperature,
+            config.maximum_temperature,
+            previous_power,
+        ) {
+            return Some(SetPoint {
                 power: PowerState::Off,
                 temperature: config.minimum_temperature,
-            };
+                reason: SetPointReason::OverMaximum,
+            });
         }
 
         // Temperature below our low point
-        if current_temperature < config.minimum_temperature {
+        if config.is_below(
+            current_temperature,
+            config.minimum_temperature,
+            previous_power,
+        ) {
             // Turn on heating high to recover low point
-            return SetPoint {
+            return Some(SetPoint {
                 power: PowerState::On,
                 temperature: config.turbo_temperature,
-            };
+                reason: SetPointReason::BelowMinimumTurbo,
+            });
+        }
+
+        None
+    }
+
+    // Heating stays off while the temperature sensor is faulty
+    pub fn from_sensor_fault(config: &CoreConfig) -> SetPoint {
+        SetPoint {
+            power: PowerState::Off,
+            temperature: config.minimum_temperature,
+            reason: SetPointReason::SensorFault,
+        }
+    }
+
+    // Follow a set point planned ahead of time, e.g. from a
+    // `HeatingPlan`, within the temperature limits
+    pub fn from_plan(
+        config: &CoreConfig,
+        current_temperature: Temperature,
+        planned: SetPoint,
+        previous_power: PowerState,
+    ) -> SetPoint {
+        Self::from_temperature_limits(config, current_temperature, previous_power)
+            .unwrap_or(planned)
+    }
+
+    pub fn from_current_state(
+        config: &CoreConfig,
+        current_temperature: Temperature,
+        current_price: Option<ElectricityPrice>,
+        previous_power: PowerState,
+    ) -> SetPoint {
+        Self::from_price_window(
+            config,
+            current_temperature,
+            current_price,
+            &[],
+            previous_power,
+        )
+    }
+
+    // Select the set point with the current price ranked against the
+    // known price window when using `PriceMode::Relative`.
+    //
+    // Without enough known prices to rank against, the current price
+    // is scaled against the maximum price as in `PriceMode::Absolute`.
+    pub fn from_price_window(
+        config: &CoreConfig,
+        current_temperature: Temperature,
+        current_price: Option<ElectricityPrice>,
+        price_window: &[ElectricityPrice],
+        previous_power: PowerState,
+    ) -> SetPoint {
+        if let Some(set_point) =
+            Self::from_temperature_limits(config, current_temperature, previous_power)
+        {
+            return set_point;
         }
 
         if let Some(current_price) = current_price {
             // Electricity price too high
-            if current_price > config.maximum_price {
+            if config.is_above_price_ceiling(current_price) {
                 // Turn on heating high to recover low point
                 return SetPoint {
                     power: PowerState::Off,
                     temperature: config.minimum_temperature,
+                    reason: SetPointReason::PriceAboveCap,
                 };
             }
 
-            let set_temperature = select_temperature(config, current_price);
+            let rank = match config.price_mode {
+                PriceMode::Absolute => None,
+                PriceMode::Relative { .. } => price_rank(current_price, price_window),
+            };
+            let (fraction, set_temperature) = match rank {
+                Some(rank) => (1.0 - rank, select_temperature_for_rank(config, rank)),
+                None => (
+                    price_fraction(config, current_price),
+                    select_temperature(config, current_price),
+                ),
+            };
             SetPoint {
                 power: PowerState::On,
                 temperature: set_temperature,
+                reason: SetPointReason::PriceScaled { fraction },
             }
         } else {
             // We don't have electricity price data; use a conservatve
             // heating level to reduce cost
-            if current_temperature < config.fallback_minimum_temperature {
+            if config.is_below(
+                current_temperature,
+                config.fallback_minimum_temperature,
+                previous_power,
+            ) {
                 return SetPoint {
                     power: PowerState::On,
                     temperature: config.maximum_temperature,
+                    reason: SetPointReason::NoPriceFallbackHeat,
                 };
             }
             SetPoint {
                 power: PowerState::Off,
                 temperature: config.fallback_minimum_temperature,
+                reason: SetPointReason::NoPriceFallbackIdle,
             }
         }
     }
@@ -96,6 +271,9 @@ mod tests {
             maximum_temperature: Temperature::new(22.0),
             turbo_temperature: Temperature::new(30.0),
             maximum_price: ElectricityPrice::new(0.30),
+            price_mode: PriceMode::Absolute,
+            upper_hysteresis: TemperatureDelta::new(0.0),
+            lower_hysteresis: TemperatureDelta::new(0.0),
         };
         let set_temperature = select_temperature(&settings, electricity_price);
         assert_eq!(set_temperature, settings.maximum_temperature);
@@ -110,6 +288,9 @@ mod tests {
             maximum_temperature: Temperature::new(22.0),
             turbo_temperature: Temperature::new(30.0),
             maximum_price: ElectricityPrice::new(0.30),
+            price_mode: PriceMode::Absolute,
+            upper_hysteresis: TemperatureDelta::new(0.0),
+            lower_hysteresis: TemperatureDelta::new(0.0),
         };
         let set_temperature = select_temperature(&settings, electricity_price);
 
@@ -128,6 +309,9 @@ mod tests {
             maximum_temperature: Temperature::new(20.0),
             turbo_temperature: Temperature::new(30.0),
             maximum_price: ElectricityPrice::new(0.30),
+            price_mode: PriceMode::Absolute,
+            upper_hysteresis: TemperatureDelta::new(0.0),
+            lower_hysteresis: TemperatureDelta::new(0.0),
         };
         let set_temperature = select_temperature(&settings, electricity_price);
         assert_eq!(set_temperature, settings.minimum_temperature);
@@ -143,11 +327,24 @@ mod tests {
             maximum_temperature: Temperature::new(22.0),
             turbo_temperature: Temperature::new(30.0),
             maximum_price: ElectricityPrice::new(0.30),
+            price_mode: PriceMode::Absolute,
+            upper_hysteresis: TemperatureDelta::new(0.0),
+            lower_hysteresis: TemperatureDelta::new(0.0),
         };
-        let result =
-            SetPoint::from_current_state(&settings, current_temperature, Some(electricity_price));
+        let result = SetPoint::from_current_state(
+            &settings,
+            current_temperature,
+            Some(electricity_price),
+            PowerState::Off,
+        );
 
         assert_eq!(result.power, PowerState::On);
+        match result.reason {
+            SetPointReason::PriceScaled { fraction } => {
+                assert!((fraction - 0.25 / 0.30).abs() < 0.001, "{:?}", fraction)
+            }
+            reason => panic!("Unexpected reason {:?}", reason),
+        }
         let min = Temperature::new(20.8);
         let max = Temperature::new(20.9);
         assert!(
@@ -174,12 +371,20 @@ mod tests {
             maximum_temperature: Temperature::new(22.0),
             turbo_temperature: Temperature::new(30.0),
             maximum_price: ElectricityPrice::new(0.30),
+            price_mode: PriceMode::Absolute,
+            upper_hysteresis: TemperatureDelta::new(0.0),
+            lower_hysteresis: TemperatureDelta::new(0.0),
         };
-        let result =
-            SetPoint::from_current_state(&settings, current_temperature, Some(electricity_price));
+        let result = SetPoint::from_current_state(
+            &settings,
+            current_temperature,
+            Some(electricity_price),
+            PowerState::Off,
+        );
         let expected = SetPoint {
             power: PowerState::Off,
             temperature: settings.minimum_temperature,
+            reason: SetPointReason::PriceAboveCap,
         };
         assert_eq!(result, expected);
     }
@@ -194,12 +399,20 @@ mod tests {
             maximum_temperature: Temperature::new(22.0),
             turbo_temperature: Temperature::new(30.0),
             maximum_price: ElectricityPrice::new(0.30),
+            price_mode: PriceMode::Absolute,
+            upper_hysteresis: TemperatureDelta::new(0.0),
+            lower_hysteresis: TemperatureDelta::new(0.0),
         };
-        let result =
-            SetPoint::from_current_state(&settings, current_temperature, Some(electricity_price));
+        let result = SetPoint::from_current_state(
+            &settings,
+            current_temperature,
+            Some(electricity_price),
+            PowerState::Off,
+        );
         let expected = SetPoint {
             power: PowerState::On,
             temperature: settings.turbo_temperature,
+            reason: SetPointReason::BelowMinimumTurbo,
         };
         assert_eq!(result, expected);
     }
@@ -214,12 +427,20 @@ mod tests {
             maximum_temperature: Temperature::new(22.0),
             turbo_temperature: Temperature::new(30.0),
             maximum_price: ElectricityPrice::new(0.30),
+            price_mode: PriceMode::Absolute,
+            upper_hysteresis: TemperatureDelta::new(0.0),
+            lower_hysteresis: TemperatureDelta::new(0.0),
         };
-        let result =
-            SetPoint::from_current_state(&settings, current_temperature, Some(electricity_price));
+        let result = SetPoint::from_current_state(
+            &settings,
+            current_temperature,
+            Some(electricity_price),
+            PowerState::Off,
+        );
         let expected = SetPoint {
             power: PowerState::Off,
             temperature: settings.minimum_temperature,
+            reason: SetPointReason::OverMaximum,
         };
         assert_eq!(result, expected);
     }
@@ -234,13 +455,409 @@ mod tests {
             maximum_temperature: Temperature::new(22.0),
             turbo_temperature: Temperature::new(30.0),
             maximum_price: ElectricityPrice::new(0.30),
+            price_mode: PriceMode::Absolute,
+            upper_hysteresis: TemperatureDelta::new(0.0),
+            lower_hysteresis: TemperatureDelta::new(0.0),
+        };
+        let result = SetPoint::from_current_state(
+            &settings,
+            current_temperature,
+            Some(electricity_price),
+            PowerState::Off,
+        );
+        let expected = SetPoint {
+            power: PowerState::Off,
+            temperature: settings.minimum_temperature,
+            reason: SetPointReason::OverMaximum,
+        };
+        assert_eq!(result, expected);
+    }
+
+    fn hysteresis_settings() -> CoreConfig {
+        CoreConfig {
+            minimum_temperature: Temperature::new(15.0),
+            fallback_minimum_temperature: Temperature::new(18.0),
+            maximum_temperature: Temperature::new(22.0),
+            turbo_temperature: Temperature::new(30.0),
+            maximum_price: ElectricityPrice::new(0.30),
+            price_mode: PriceMode::Absolute,
+            upper_hysteresis: TemperatureDelta::new(0.5),
+            lower_hysteresis: TemperatureDelta::new(0.5),
+        }
+    }
+
+    #[test]
+    fn test_hysteresis_maximum_rising() {
+        let settings = hysteresis_settings();
+        let electricity_price = Some(ElectricityPrice::new(0.05));
+
+        // Inside the band; heating stays on
+        let result = SetPoint::from_current_state(
+            &settings,
+            Temperature::new(22.3),
+            electricity_price,
+            PowerState::On,
+        );
+        assert_eq!(result.power, PowerState::On);
+
+        // Above the band; heating turns off
+        let result = SetPoint::from_current_state(
+            &settings,
+            Temperature::new(22.6),
+            electricity_price,
+            PowerState::On,
+        );
+        let expected = SetPoint {
+            power: PowerState::Off,
+            temperature: settings.minimum_temperature,
+            reason: SetPointReason::OverMaximum,
+        };
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_hysteresis_maximum_falling() {
+        let settings = hysteresis_settings();
+        let electricity_price = Some(ElectricityPrice::new(0.05));
+
+        // Inside the band; heating stays off
+        let result = SetPoint::from_current_state(
+            &settings,
+            Temperature::new(21.7),
+            electricity_price,
+            PowerState::Off,
+        );
+        let expected = SetPoint {
+            power: PowerState::Off,
+            temperature: settings.minimum_temperature,
+            reason: SetPointReason::OverMaximum,
         };
+        assert_eq!(result, expected);
+
+        // Below the band; heating turns back on
+        let result = SetPoint::from_current_state(
+            &settings,
+            Temperature::new(21.4),
+            electricity_price,
+            PowerState::Off,
+        );
+        assert_eq!(result.power, PowerState::On);
+    }
+
+    #[test]
+    fn test_hysteresis_minimum_falling() {
+        let settings = hysteresis_settings();
+        let electricity_price = Some(ElectricityPrice::new(1.20));
+
+        // Inside the band; price keeps heating off
+        let result = SetPoint::from_current_state(
+            &settings,
+            Temperature::new(14.7),
+            electricity_price,
+            PowerState::Off,
+        );
+        let expected = SetPoint {
+            power: PowerState::Off,
+            temperature: settings.minimum_temperature,
+            reason: SetPointReason::PriceAboveCap,
+        };
+        assert_eq!(result, expected);
+
+        // Below the band; turbo heating regardless of price
+        let result = SetPoint::from_current_state(
+            &settings,
+            Temperature::new(14.4),
+            electricity_price,
+            PowerState::Off,
+        );
+        let expected = SetPoint {
+            power: PowerState::On,
+            temperature: settings.turbo_temperature,
+            reason: SetPointReason::BelowMinimumTurbo,
+        };
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_hysteresis_minimum_rising() {
+        let settings = hysteresis_settings();
+        let electricity_price = Some(ElectricityPrice::new(1.20));
+
+        // Inside the band; turbo heating continues
+        let result = SetPoint::from_current_state(
+            &settings,
+            Temperature::new(15.3),
+            electricity_price,
+            PowerState::On,
+        );
+        let expected = SetPoint {
+            power: PowerState::On,
+            temperature: settings.turbo_temperature,
+            reason: SetPointReason::BelowMinimumTurbo,
+        };
+        assert_eq!(result, expected);
+
+        // Above the band; price turns heating off
+        let result = SetPoint::from_current_state(
+            &settings,
+            Temperature::new(15.6),
+            electricity_price,
+            PowerState::On,
+        );
+        let expected = SetPoint {
+            power: PowerState::Off,
+            temperature: settings.minimum_temperature,
+            reason: SetPointReason::PriceAboveCap,
+        };
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_hysteresis_fallback_falling() {
+        let settings = hysteresis_settings();
+
+        // Inside the band; heating stays off
+        let result =
+            SetPoint::from_current_state(&settings, Temperature::new(17.7), None, PowerState::Off);
+        let expected = SetPoint {
+            power: PowerState::Off,
+            temperature: settings.fallback_minimum_temperature,
+            reason: SetPointReason::NoPriceFallbackIdle,
+        };
+        assert_eq!(result, expected);
+
+        // Below the band; heating turns on
+        let result =
+            SetPoint::from_current_state(&settings, Temperature::new(17.4), None, PowerState::Off);
+        let expected = SetPoint {
+            power: PowerState::On,
+            temperature: settings.maximum_temperature,
+            reason: SetPointReason::NoPriceFallbackHeat,
+        };
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_hysteresis_fallback_rising() {
+        let settings = hysteresis_settings();
+
+        // Inside the band; heating stays on
+        let result =
+            SetPoint::from_current_state(&settings, Temperature::new(18.3), None, PowerState::On);
+        let expected = SetPoint {
+            power: PowerState::On,
+            temperature: settings.maximum_temperature,
+            reason: SetPointReason::NoPriceFallbackHeat,
+        };
+        assert_eq!(result, expected);
+
+        // Above the band; heating turns off
         let result =
-            SetPoint::from_current_state(&settings, current_temperature, Some(electricity_price));
+            SetPoint::from_current_state(&settings, Temperature::new(18.6), None, PowerState::On);
         let expected = SetPoint {
+            power: PowerState::Off,
+            temperature: settings.fallback_minimum_temperature,
+            reason: SetPointReason::NoPriceFallbackIdle,
+        };
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_from_plan() {
+        let settings = hysteresis_settings();
+        let planned = SetPoint {
+            power: PowerState::On,
+            temperature: settings.maximum_temperature,
+            reason: SetPointReason::Planned,
+        };
+
+        let result =
+            SetPoint::from_plan(&settings, Temperature::new(20.0), planned, PowerState::Off);
+        assert_eq!(result, planned);
+
+        // Temperature limits take priority over the plan
+        let result =
+            SetPoint::from_plan(&settings, Temperature::new(23.0), planned, PowerState::On);
+        let expected = SetPoint {
+            power: PowerState::Off,
+            temperature: settings.minimum_temperature,
+            reason: SetPointReason::OverMaximum,
+        };
+        assert_eq!(result, expected);
+
+        let planned = SetPoint {
             power: PowerState::Off,
             temperature: settings.minimum_temperature,
+            reason: SetPointReason::Planned,
+        };
+        let result =
+            SetPoint::from_plan(&settings, Temperature::new(14.0), planned, PowerState::Off);
+        let expected = SetPoint {
+            power: PowerState::On,
+            temperature: settings.turbo_temperature,
+            reason: SetPointReason::BelowMinimumTurbo,
         };
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_invalid_temperature_turns_heating_off() {
+        let settings = hysteresis_settings();
+        let expected = SetPoint::from_sensor_fault(&settings);
+        assert_eq!(expected.power, PowerState::Off);
+
+        for temperature in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            let result = SetPoint::from_current_state(
+                &settings,
+                Temperature::new(temperature),
+                Some(ElectricityPrice::new(0.0)),
+                PowerState::On,
+            );
+            assert_eq!(result, expected, "{:?}", temperature);
+
+            let result = SetPoint::from_current_state(
+                &settings,
+                Temperature::new(temperature),
+                None,
+                PowerState::Off,
+            );
+            assert_eq!(result, expected, "{:?}", temperature);
+        }
+
+        let planned = SetPoint {
+            power: PowerState::On,
+            temperature: settings.maximum_temperature,
+            reason: SetPointReason::Planned,
+        };
+        let result = SetPoint::from_plan(
+            &settings,
+            Temperature::new(f32::NAN),
+            planned,
+            PowerState::On,
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_get_desired_state_relative_price() {
+        let window = [0.05, 0.10, 0.20, 0.40, 0.80].map(ElectricityPrice::new);
+        let settings = CoreConfig {
+            price_mode: PriceMode::Relative {
+                hard_ceiling: false,
+            },
+            ..hysteresis_settings()
+        };
+
+        // Cheapest known price
+        let result = SetPoint::from_price_window(
+            &settings,
+            Temperature::new(18.0),
+            Some(ElectricityPrice::new(0.05)),
+            &window,
+            PowerState::Off,
+        );
+        let expected = SetPoint {
+            power: PowerState::On,
+            temperature: settings.maximum_temperature,
+            reason: SetPointReason::PriceScaled { fraction: 1.0 },
+        };
+        assert_eq!(result, expected);
+
+        // Above the maximum price, but not the most expensive hour
+        let result = SetPoint::from_price_window(
+            &settings,
+            Temperature::new(18.0),
+            Some(ElectricityPrice::new(0.40)),
+            &window,
+            PowerState::Off,
+        );
+        let expected = SetPoint {
+            power: PowerState::On,
+            temperature: Temperature::new(16.75),
+            reason: SetPointReason::PriceScaled { fraction: 0.25 },
+        };
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_get_desired_state_relative_price_ceiling() {
+        let window = [0.05, 0.10, 0.20, 0.40, 0.80].map(ElectricityPrice::new);
+        let settings = CoreConfig {
+            price_mode: PriceMode::Relative { hard_ceiling: true },
+            ..hysteresis_settings()
+        };
+
+        let result = SetPoint::from_price_window(
+            &settings,
+            Temperature::new(18.0),
+            Some(ElectricityPrice::new(0.40)),
+            &window,
+            PowerState::Off,
+        );
+        let expected = SetPoint {
+            power: PowerState::Off,
+            temperature: settings.minimum_temperature,
+            reason: SetPointReason::PriceAboveCap,
+        };
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_get_desired_state_relative_price_without_window() {
+        let settings = CoreConfig {
+            price_mode: PriceMode::Relative {
+                hard_ceiling: false,
+            },
+            ..hysteresis_settings()
+        };
+
+        // Falls back to scaling against the maximum price
+        let result = SetPoint::from_price_window(
+            &settings,
+            Temperature::new(18.0),
+            Some(ElectricityPrice::new(0.0)),
+            &[],
+            PowerState::Off,
+        );
+        let expected = SetPoint {
+            power: PowerState::On,
+            temperature: settings.maximum_temperature,
+            reason: SetPointReason::PriceScaled { fraction: 1.0 },
+        };
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_price_scaling_agrees_with_f32() {
+        let settings = crate::fixtures::settings();
+
+        // Today's prices from electricity-price/multiday.json
+        for price in crate::fixtures::today_prices() {
+            let set_point = SetPoint::from_current_state(
+                &settings,
+                Temperature::new(18.0),
+                Some(price),
+                PowerState::Off,
+            );
+            let price = f32::from(price);
+            if price > 30.0 {
+                assert_eq!(set_point.power, PowerState::Off, "{:?}", price);
+                continue;
+            }
+
+            // Same set point as the calculation in plain f32
+            let expected = 15.0 + (22.0 - 15.0) * (30.0 - price) / 30.0;
+            let expected = Temperature::new(expected);
+            assert_eq!(set_point.power, PowerState::On, "{:?}", price);
+            assert!(
+                set_point
+                    .temperature
+                    .approx_eq(expected, TemperatureDelta::new(0.001)),
+                "{:?}: {} {}",
+                price,
+                set_point.temperature,
+                expected
+            );
+        }
+    }
 }
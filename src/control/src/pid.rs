@@ -0,0 +1,282 @@
+use core::time::Duration;
+
+use crate::state::Temperature;
+
+const SECONDS_PER_HOUR: f32 = 3600.0;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PidConfig {
+    // Output per degree below the set point
+    pub proportional_gain: f32,
+
+    // Output per degree hour below the set point
+    pub integral_gain: f32,
+
+    // Output per degree per hour of falling temperature
+    pub derivative_gain: f32,
+
+    // Range the output is clamped to, e.g. an on-fraction of 0.0 to 1.0
+    pub output_minimum: f32,
+    pub output_maximum: f32,
+}
+
+// Discrete PID controller on the temperature.
+//
+// The derivative acts on the measurement rather than the error so that
+// a step in the set point does not kick the output, and a step in the
+// set point moves the integral term to cancel the jump in the
+// proportional term, so the output continues from where it was. After
+// a large step the integral may be outside the output range; only the
+// output is clamped, and integration never takes the integral further
+// outside the range.
+#[derive(Debug, Clone, Copy)]
+pub struct Pid {
+    config: PidConfig,
+    // Integral term, in output units
+    integral: f32,
+    // Set point and measurement of the previous update
+    previous: Option<(Temperature, Temperature)>,
+}
+
+impl Pid {
+    pub fn new(config: PidConfig) -> Pid {
+        Pid {
+            config,
+            integral: 0.0,
+            previous: None,
+        }
+    }
+
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    // Controller output after `elapsed` since the previous update
+    pub fn update(
+        &mut self,
+        set_point: Temperature,
+        measurement: Temperature,
+        elapsed: Duration,
+    ) -> f32 {
+        let minimum = self.config.output_minimum;
+        let maximum = self.config.output_maximum;
+        let hours = elapsed.as_secs_f32() / SECONDS_PER_HOUR;
+
+        let error = f32::from(set_point - measurement);
+        let proportional = self.config.proportional_gain * error;
+
+        let derivative = match self.previous {
+            Some((previous_set_point, previous_measurement)) => {
+                // Bumpless transfer across a step in the set point
+                let step = f32::from(set_point - previous_set_point);
+                self.integral -= self.config.proportional_gain * step;
+                if hours > 0.0 {
+                    let change = f32::from(measurement - previous_measurement);
+                    -self.config.derivative_gain * change / hours
+                } else {
+                    0.0
+                }
+            }
+            None => 0.0,
+        };
+        self.previous = Some((set_point, measurement));
+
+        // Anti-windup; the error is only integrated while the output is
+        // not saturated, or when it drives the output back out of
+        // saturation
+        let integral = self.integral + self.config.integral_gain * error * hours;
+        let output = proportional + integral + derivative;
+        if (minimum..=maximum).contains(&output)
+            || (output > maximum && error < 0.0)
+            || (output < minimum && error > 0.0)
+        {
+            self.integral = if integral > self.integral {
+                integral.min(maximum.max(self.integral))
+            } else {
+                integral.max(minimum.min(self.integral))
+            };
+        }
+
+        (proportional + self.integral + derivative).clamp(minimum, maximum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{PowerState, TemperatureDelta};
+    use crate::thermal::ThermalModel;
+
+    const STEP: Duration = Duration::from_secs(10 * 60);
+
+    fn config(integral_gain: f32, derivative_gain: f32) -> PidConfig {
+        PidConfig {
+            proportional_gain: 0.5,
+            integral_gain,
+            derivative_gain,
+            output_minimum: 0.0,
+            output_maximum: 1.0,
+        }
+    }
+
+    fn model() -> ThermalModel {
+        ThermalModel {
+            heating_rate: 2.0,
+            cooling_rate: 0.1,
+            ambient_temperature: Temperature::new(10.0),
+        }
+    }
+
+    // Advance the floor by one step, heating for the output fraction
+    // of the step
+    fn heat(model: &ThermalModel, temperature: Temperature, output: f32) -> Temperature {
+        let hours = STEP.as_secs_f32() / SECONDS_PER_HOUR;
+        let temperature = model.predict(temperature, PowerState::On, hours * output);
+        model.predict(temperature, PowerState::Off, hours * (1.0 - output))
+    }
+
+    fn target() -> Temperature {
+        Temperature::new(21.0)
+    }
+
+    fn tolerance() -> TemperatureDelta {
+        TemperatureDelta::new(0.05)
+    }
+
+    fn maximum_overshoot() -> Temperature {
+        target() + TemperatureDelta::new(0.5)
+    }
+
+    // Step response from `start` toward the target over `hours`,
+    // returning the peak and final temperatures
+    fn step_response(pid: &mut Pid, start: f32, hours: usize) -> (Temperature, Temperature) {
+        let model = model();
+        let mut temperature = Temperature::new(start);
+        let mut peak = temperature;
+        for _ in 0..hours * 6 {
+            let output = pid.update(target(), temperature, STEP);
+            assert!((0.0..=1.0).contains(&output), "{:?}", output);
+            temperature = heat(&model, temperature, output);
+            if temperature > peak {
+                peak = temperature;
+            }
+        }
+        (peak, temperature)
+    }
+
+    #[test]
+    fn test_proportional_step_response() {
+        let mut pid = Pid::new(config(0.0, 0.0));
+        let (_, last) = step_response(&mut pid, 18.0, 48);
+
+        // Holding 21 degrees needs an output of 0.55
+        assert!(last < target() - TemperatureDelta::new(0.5), "{}", last);
+    }
+
+    #[test]
+    fn test_pi_step_response() {
+        let mut pid = Pid::new(config(0.1, 0.0));
+        let (peak, last) = step_response(&mut pid, 18.0, 48);
+
+        assert!(last.approx_eq(target(), tolerance()), "{}", last);
+        assert!(peak < maximum_overshoot(), "{}", peak);
+    }
+
+    #[test]
+    fn test_pid_step_response() {
+        let mut pid = Pid::new(config(0.1, 0.1));
+        let (peak, last) = step_response(&mut pid, 18.0, 48);
+
+        assert!(last.approx_eq(target(), tolerance()), "{}", last);
+        assert!(peak < maximum_overshoot(), "{}", peak);
+    }
+
+    #[test]
+    fn test_anti_windup() {
+        let mut pid = Pid::new(config(0.1, 0.0));
+
+        // Saturated for around ten hours from a cold start
+        let (peak, last) = step_response(&mut pid, 5.0, 48);
+        assert!(last.approx_eq(target(), tolerance()), "{}", last);
+        assert!(peak < maximum_overshoot(), "{}", peak);
+        assert!(pid.integral() <= 1.0, "{:?}", pid.integral());
+    }
+
+    #[test]
+    fn test_derivative_on_measurement() {
+        let mut pid = Pid::new(PidConfig {
+            proportional_gain: 0.0,
+            integral_gain: 0.0,
+            derivative_gain: 1.0,
+            output_minimum: -10.0,
+            output_maximum: 10.0,
+        });
+        pid.update(Temperature::new(20.0), Temperature::new(20.0), STEP);
+
+        // No kick from a step in the set point
+        let output = pid.update(Temperature::new(22.0), Temperature::new(20.0), STEP);
+        assert_eq!(output, 0.0);
+
+        // Falling temperature increases the output
+        let output = pid.update(Temperature::new(22.0), Temperature::new(19.5), STEP);
+        assert!((output - 3.0).abs() < 0.001, "{:?}", output);
+    }
+
+    #[test]
+    fn test_bumpless_transfer() {
+        let mut pid = Pid::new(config(0.1, 0.0));
+        let (_, temperature) = step_response(&mut pid, 18.0, 48);
+        let steady = pid.update(target(), temperature, Duration::ZERO);
+
+        // A cheaper price raising the set point does not jump the
+        // output, and the integral takes over from there
+        let output = pid.update(Temperature::new(21.5), temperature, Duration::ZERO);
+        assert!((output - steady).abs() < 0.001, "{:?}", output);
+        let output = pid.update(Temperature::new(21.5), temperature, STEP);
+        assert!(output > steady, "{:?}", output);
+    }
+
+    #[test]
+    fn test_bumpless_transfer_large_step_down() {
+        let mut pid = Pid::new(config(0.1, 0.0));
+        let (_, temperature) = step_response(&mut pid, 18.0, 48);
+        let steady = pid.update(target(), temperature, Duration::ZERO);
+
+        // An expensive hour lowering the set point by two degrees moves
+        // the integral above the output range to keep the output
+        let lower = Temperature::new(19.0);
+        let output = pid.update(lower, temperature, Duration::ZERO);
+        assert!((output - steady).abs() < 0.001, "{:?}", output);
+        assert!(pid.integral() > 1.0, "{:?}", pid.integral());
+
+        // The output then falls away gradually
+        let model = model();
+        let mut temperature = temperature;
+        let mut previous = output;
+        for _ in 0..6 {
+            let output = pid.update(lower, temperature, STEP);
+            assert!(output <= previous, "{:?}", output);
+            assert!(previous - output < 0.2, "{:?} {:?}", previous, output);
+            temperature = heat(&model, temperature, output);
+            previous = output;
+        }
+
+        // And settles at the new set point
+        for _ in 0..48 * 6 {
+            let output = pid.update(lower, temperature, STEP);
+            temperature = heat(&model, temperature, output);
+        }
+        assert!(temperature.approx_eq(lower, tolerance()), "{}", temperature);
+        assert!(pid.integral() <= 1.0, "{:?}", pid.integral());
+    }
+
+    #[test]
+    fn test_output_clamped() {
+        let mut pid = Pid::new(config(0.1, 0.0));
+
+        let output = pid.update(Temperature::new(21.0), Temperature::new(10.0), STEP);
+        assert_eq!(output, 1.0);
+        let output = pid.update(Temperature::new(21.0), Temperature::new(30.0), STEP);
+        assert_eq!(output, 0.0);
+    }
+}
@@ -0,0 +1,258 @@
+#[allow(unused_imports)]
+use micromath::F32Ext; // Required for f32::ceil
+use time::{Duration, PrimitiveDateTime};
+
+use crate::config::CoreConfig;
+use crate::state::{PowerState, PriceSlot};
+use crate::{SetPoint, SetPointReason};
+
+// Enough for today and tomorrow's hourly prices, including the extra
+// hour on the day daylight saving time ends
+pub const MAX_PLAN_SLOTS: usize = 50;
+
+// Length of each planned slot; price data is hourly
+const SLOT_LENGTH: Duration = Duration::HOUR;
+
+// Amount of heating required each day
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum HeatingBudget {
+    Hours(u8),
+    // Energy in kWh delivered by heating elements rated in kW
+    Energy { energy: f32, heater_power: f32 },
+}
+
+impl HeatingBudget {
+    pub fn hours(&self) -> usize {
+        match *self {
+            HeatingBudget::Hours(hours) => hours as usize,
+            HeatingBudget::Energy {
+                energy,
+                heater_power,
+            } => {
+                if energy <= 0.0 || heater_power <= 0.0 {
+                    return 0;
+                }
+                (energy / heater_power).ceil() as usize
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PlannedSlot {
+    pub start: PrimitiveDateTime,
+    pub set_point: SetPoint,
+}
+
+// Set points planned ahead of time for each hour of the known price
+// curve
+#[derive(Debug, Clone, Copy)]
+pub struct HeatingPlan {
+    slots: [Option<PlannedSlot>; MAX_PLAN_SLOTS],
+    len: usize,
+}
+
+impl Default for HeatingPlan {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HeatingPlan {
+    pub fn new() -> Self {
+        Self {
+            slots: [None; MAX_PLAN_SLOTS],
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn slots(&self) -> impl Iterator<Item = &PlannedSlot> {
+        self.slots[..self.len].iter().flatten()
+    }
+
+    // Set point for the slot containing `time`, if it is planned
+    pub fn set_point_at(&self, time: PrimitiveDateTime) -> Option<SetPoint> {
+        self.slots()
+            .find(|slot| slot.start <= time && time < slot.start + SLOT_LENGTH)
+            .map(|slot| slot.set_point)
+    }
+
+    // Plan one day of hourly prices, heating during the cheapest hours
+    // to meet the budget.
+    //
+    // Hours above the price ceiling are never heated, even if that
+    // leaves the budget unmet. Slots that do not fit in the
+    // plan are ignored.
+    pub fn add_cheapest_hours(
+        &mut self,
+        config: &CoreConfig,
+        budget: HeatingBudget,
+        day: &[PriceSlot],
+    ) {
+        let day = &day[..day.len().min(MAX_PLAN_SLOTS - self.len)];
+
+        let mut order = [0usize; MAX_PLAN_SLOTS];
+        let order = &mut order[..day.len()];
+        for (index, item) in order.iter_mut().enumerate() {
+            *item = index;
+        }
+        order.sort_unstable_by(|a, b| {
+            day[*a]
+                .price
+                .partial_cmp(&day[*b].price)
+                .unwrap_or(core::cmp::Ordering::Equal)
+                .then(a.cmp(b))
+        });
+
+        let mut heat = [false; MAX_PLAN_SLOTS];
+        for index in order.iter().take(budget.hours()) {
+            if !config.is_above_price_ceiling(day[*index].price) {
+                heat[*index] = true;
+            }
+        }
+
+        for (index, slot) in day.iter().enumerate() {
+            let set_point = if heat[index] {
+                SetPoint {
+                    power: PowerState::On,
+                    temperature: config.maximum_temperature,
+                    reason: SetPointReason::Planned,
+                }
+            } else {
+                SetPoint {
+                    power: PowerState::Off,
+                    temperature: config.minimum_temperature,
+                    reason: SetPointReason::Planned,
+                }
+            };
+            self.slots[self.len] = Some(PlannedSlot {
+                start: slot.start,
+                set_point,
+            });
+            self.len += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{settings, today};
+    use alloc::vec::Vec;
+    use time::macros::datetime;
+
+    fn heated_hours(plan: &HeatingPlan) -> usize {
+        plan.slots()
+            .filter(|slot| slot.set_point.power == PowerState::On)
+            .count()
+    }
+
+    #[test]
+    fn test_budget_hours() {
+        assert_eq!(HeatingBudget::Hours(6).hours(), 6);
+        let budget = HeatingBudget::Energy {
+            energy: 10.0,
+            heater_power: 3.0,
+        };
+        assert_eq!(budget.hours(), 4);
+        let budget = HeatingBudget::Energy {
+            energy: 10.0,
+            heater_power: 0.0,
+        };
+        assert_eq!(budget.hours(), 0);
+    }
+
+    #[test]
+    fn test_cheapest_hours() {
+        let settings = settings();
+        let day = today();
+        let mut plan = HeatingPlan::new();
+        plan.add_cheapest_hours(&settings, HeatingBudget::Hours(4), &day);
+
+        assert_eq!(plan.len(), 24);
+        assert_eq!(heated_hours(&plan), 4);
+
+        let on = SetPoint {
+            power: PowerState::On,
+            temperature: settings.maximum_temperature,
+            reason: SetPointReason::Planned,
+        };
+        let off = SetPoint {
+            power: PowerState::Off,
+            temperature: settings.minimum_temperature,
+            reason: SetPointReason::Planned,
+        };
+        assert_eq!(plan.set_point_at(datetime!(2024-10-24 23:30)), Some(on));
+        assert_eq!(plan.set_point_at(datetime!(2024-10-25 00:00)), Some(on));
+        assert_eq!(plan.set_point_at(datetime!(2024-10-25 01:59)), Some(on));
+        assert_eq!(plan.set_point_at(datetime!(2024-10-25 02:00)), Some(on));
+        assert_eq!(plan.set_point_at(datetime!(2024-10-24 22:00)), Some(off));
+        assert_eq!(plan.set_point_at(datetime!(2024-10-25 06:00)), Some(off));
+        assert_eq!(plan.set_point_at(datetime!(2024-10-25 18:00)), Some(off));
+    }
+
+    #[test]
+    fn test_cheapest_hours_respects_maximum_price() {
+        let settings = settings();
+        let day = today();
+        let mut plan = HeatingPlan::new();
+        plan.add_cheapest_hours(&settings, HeatingBudget::Hours(22), &day);
+
+        // Four hours are above the maximum price of 30.0
+        assert_eq!(heated_hours(&plan), 20);
+        assert_eq!(
+            plan.set_point_at(datetime!(2024-10-25 06:00))
+                .unwrap()
+                .power,
+            PowerState::Off
+        );
+    }
+
+    #[test]
+    fn test_cheapest_hours_per_day() {
+        let settings = settings();
+        let today = today();
+        // Today's prices in reverse order
+        let tomorrow: Vec<PriceSlot> = today
+            .iter()
+            .zip(today.iter().rev())
+            .map(|(slot, reversed)| PriceSlot {
+                start: slot.start + Duration::DAY,
+                price: reversed.price,
+            })
+            .collect();
+
+        let mut plan = HeatingPlan::new();
+        plan.add_cheapest_hours(&settings, HeatingBudget::Hours(3), &today);
+        plan.add_cheapest_hours(&settings, HeatingBudget::Hours(3), &tomorrow);
+
+        assert_eq!(plan.len(), 48);
+        assert_eq!(heated_hours(&plan), 6);
+        assert_eq!(
+            plan.set_point_at(datetime!(2024-10-26 18:30))
+                .unwrap()
+                .power,
+            PowerState::On
+        );
+    }
+
+    #[test]
+    fn test_set_point_outside_plan() {
+        let settings = settings();
+        let day = today();
+        let mut plan = HeatingPlan::new();
+        assert_eq!(plan.set_point_at(datetime!(2024-10-24 22:00)), None);
+
+        plan.add_cheapest_hours(&settings, HeatingBudget::Hours(4), &day);
+        assert_eq!(plan.set_point_at(datetime!(2024-10-24 21:59)), None);
+        assert_eq!(plan.set_point_at(datetime!(2024-10-25 22:00)), None);
+    }
+}
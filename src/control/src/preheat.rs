@@ -0,0 +1,369 @@
+use time::{Duration, PrimitiveDateTime};
+
+use crate::config::{CoreConfig, PriceMode};
+use crate::plan::MAX_PLAN_SLOTS;
+use crate::rank::price_rank;
+use crate::state::{ElectricityPrice, PowerState, PriceSlot, Temperature};
+use crate::thermal::ThermalModel;
+use crate::{SetPoint, SetPointReason};
+
+// Length of each planned slot; price data is hourly
+const SLOT_LENGTH: Duration = Duration::HOUR;
+
+// In relative price mode, hours ranked at or above this within the plan
+// are expensive, i.e. the most expensive quarter
+const EXPENSIVE_RANK: f32 = 0.75;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PreheatAction {
+    // No constraint from the planner; normal control applies
+    Normal,
+    // Heat to store energy in the floor ahead of an expensive period
+    Charge,
+    // Expensive period; heating stays off and the floor cools
+    Coast,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PreheatSlot {
+    pub start: PrimitiveDateTime,
+    pub price: ElectricityPrice,
+    pub action: PreheatAction,
+    // Predicted temperature at the start of the slot
+    pub predicted_temperature: Temperature,
+}
+
+// Heating plan that charges the floor during cheap hours so that it
+// stays above the minimum temperature through expensive periods
+// without turning heating on at the expensive price.
+#[derive(Debug, Clone, Copy)]
+pub struct PreheatPlan {
+    slots: [Option<PreheatSlot>; MAX_PLAN_SLOTS],
+    len: usize,
+    // Predicted temperature at the end of the plan
+    final_temperature: Temperature,
+}
+
+impl PreheatPlan {
+    // Plan heating over the price curve, starting at the current
+    // temperature at the start of the first slot.
+    //
+    // Expensive hours are those above the price ceiling, and in relative
+    // price mode also the most expensive quarter of the plan. Prediction
+    // assumes heating is off outside of charging hours, and that
+    // charging stops at the maximum temperature. When not enough cheap
+    // hours precede an expensive period to charge the floor, the plan
+    // leaves the shortfall to the minimum temperature limit.
+    pub fn plan(
+        config: &CoreConfig,
+        model: &ThermalModel,
+        current_temperature: Temperature,
+        prices: &[PriceSlot],
+    ) -> PreheatPlan {
+        let prices = &prices[..prices.len().min(MAX_PLAN_SLOTS)];
+
+        let mut plan = PreheatPlan {
+            slots: [None; MAX_PLAN_SLOTS],
+            len: prices.len(),
+            final_temperature: current_temperature,
+        };
+        let mut window = [ElectricityPrice::new(0.0); MAX_PLAN_SLOTS];
+        for (known, slot) in window.iter_mut().zip(prices) {
+            *known = slot.price;
+        }
+        let window = &window[..prices.len()];
+
+        for (index, slot) in prices.iter().enumerate() {
+            let action = if is_expensive(config, slot.price, window) {
+                PreheatAction::Coast
+            } else {
+                PreheatAction::Normal
+            };
+            plan.slots[index] = Some(PreheatSlot {
+                start: slot.start,
+                price: slot.price,
+                action,
+                predicted_temperature: current_temperature,
+            });
+        }
+
+        loop {
+            plan.predict(config, model, current_temperature);
+
+            let Some(shortfall) = plan.first_shortfall(config, model) else {
+                break;
+            };
+            let Some(charge) = plan.cheapest_normal_slot_before(shortfall) else {
+                break;
+            };
+            if let Some(slot) = plan.slots[charge].as_mut() {
+                slot.action = PreheatAction::Charge;
+            }
+        }
+
+        plan
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn slots(&self) -> impl Iterator<Item = &PreheatSlot> {
+        self.slots[..self.len].iter().flatten()
+    }
+
+    pub fn final_temperature(&self) -> Temperature {
+        self.final_temperature
+    }
+
+    pub fn slot_at(&self, time: PrimitiveDateTime) -> Option<&PreheatSlot> {
+        self.slots()
+            .find(|slot| slot.start <= time && time < slot.start + SLOT_LENGTH)
+    }
+
+    // Set point for the slot containing `time`, if the planner
+    // constrains it
+    pub fn set_point_at(&self, config: &CoreConfig, time: PrimitiveDateTime) -> Option<SetPoint> {
+        match self.slot_at(time)?.action {
+            PreheatAction::Normal => None,
+            PreheatAction::Charge => Some(SetPoint {
+                power: PowerState::On,
+                temperature: config.maximum_temperature,
+                reason: SetPointReason::Planned,
+            }),
+            PreheatAction::Coast => Some(SetPoint {
+                power: PowerState::Off,
+                temperature: config.minimum_temperature,
+                reason: SetPointReason::Planned,
+            }),
+        }
+    }
+
+    fn predict(&mut self, config: &CoreConfig, model: &ThermalModel, start: Temperature) {
+        let mut temperature = start;
+        for slot in self.slots[..self.len].iter_mut().flatten() {
+            slot.predicted_temperature = temperature;
+            temperature = next_temperature(config, model, temperature, slot.action);
+        }
+        self.final_temperature = temperature;
+    }
+
+    // First expensive slot the floor is predicted to cool below the
+    // minimum temperature during
+    fn first_shortfall(&self, config: &CoreConfig, model: &ThermalModel) -> Option<usize> {
+        self.slots[..self.len].iter().flatten().position(|slot| {
+            slot.action == PreheatAction::Coast
+                && next_temperature(config, model, slot.predicted_temperature, slot.action)
+                    < config.minimum_temperature
+        })
+    }
+
+    fn cheapest_normal_slot_before(&self, end: usize) -> Option<usize> {
+        self.slots[..end]
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| slot.map(|slot| (index, slot)))
+            .filter(|(_, slot)| slot.action == PreheatAction::Normal)
+            .min_by(|(_, a), (_, b)| {
+                a.price
+                    .partial_cmp(&b.price)
+                    .unwrap_or(core::cmp::Ordering::Equal)
+            })
+            .map(|(index, _)| index)
+    }
+}
+
+fn is_expensive(config: &CoreConfig, price: ElectricityPrice, window: &[ElectricityPrice]) -> bool {
+    if config.is_above_price_ceiling(price) {
+        return true;
+    }
+    match config.price_mode {
+        PriceMode::Absolute => false,
+        PriceMode::Relative { .. } => {
+            price_rank(price, window).is_some_and(|rank| rank >= EXPENSIVE_RANK)
+        }
+    }
+}
+
+fn next_temperature(
+    config: &CoreConfig,
+    model: &ThermalModel,
+    temperature: Temperature,
+    action: PreheatAction,
+) -> Temperature {
+    match action {
+        PreheatAction::Charge => {
+            // Normal control turns heating off above the maximum
+            if temperature > config.maximum_temperature {
+                return model.predict(temperature, PowerState::Off, 1.0);
+            }
+            let heated = model.predict(temperature, PowerState::On, 1.0);
+            if heated > config.maximum_temperature {
+                config.maximum_temperature
+            } else {
+                heated
+            }
+        }
+        PreheatAction::Normal | PreheatAction::Coast => {
+            model.predict(temperature, PowerState::Off, 1.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::today;
+    use time::macros::datetime;
+
+    fn settings() -> CoreConfig {
+        CoreConfig {
+            minimum_temperature: Temperature::new(18.0),
+            fallback_minimum_temperature: Temperature::new(19.0),
+            maximum_temperature: Temperature::new(24.0),
+            ..crate::fixtures::settings()
+        }
+    }
+
+    fn model() -> ThermalModel {
+        ThermalModel {
+            heating_rate: 2.0,
+            cooling_rate: 0.1,
+            ambient_temperature: Temperature::new(10.0),
+        }
+    }
+
+    fn minimum_during_coast(plan: &PreheatPlan) -> Temperature {
+        let settings = settings();
+        let model = model();
+        plan.slots()
+            .filter(|slot| slot.action == PreheatAction::Coast)
+            .map(|slot| {
+                next_temperature(&settings, &model, slot.predicted_temperature, slot.action)
+            })
+            .fold(
+                settings.maximum_temperature,
+                |a, b| if b < a { b } else { a },
+            )
+    }
+
+    #[test]
+    fn test_expensive_hours_coast() {
+        let plan = PreheatPlan::plan(&settings(), &model(), Temperature::new(24.0), &today());
+        assert_eq!(plan.len(), 24);
+
+        let coasting: [bool; 24] = core::array::from_fn(|index| {
+            plan.slots().nth(index).unwrap().action == PreheatAction::Coast
+        });
+        // 05:00 to 08:00 on 2024-10-25 are above the maximum price
+        for (index, coast) in coasting.iter().enumerate() {
+            assert_eq!(*coast, (7..=10).contains(&index), "{:?}", index);
+        }
+    }
+
+    #[test]
+    fn test_relative_expensive_hours_coast() {
+        // Without a hard ceiling no price is above the maximum
+        let settings = CoreConfig {
+            price_mode: PriceMode::Relative {
+                hard_ceiling: false,
+            },
+            ..settings()
+        };
+        let plan = PreheatPlan::plan(&settings, &model(), Temperature::new(18.5), &today());
+
+        // The six most expensive hours, 05:00 to 10:00 on 2024-10-25
+        let coasting: [bool; 24] = core::array::from_fn(|index| {
+            plan.slots().nth(index).unwrap().action == PreheatAction::Coast
+        });
+        for (index, coast) in coasting.iter().enumerate() {
+            assert_eq!(*coast, (7..=12).contains(&index), "{:?}", index);
+        }
+
+        // Charged ahead of them
+        let mut charging = plan
+            .slots()
+            .filter(|slot| slot.action == PreheatAction::Charge)
+            .peekable();
+        assert!(charging.peek().is_some());
+        assert!(charging.all(|slot| slot.start < datetime!(2024-10-25 05:00)));
+    }
+
+    #[test]
+    fn test_charge_before_expensive_hours() {
+        let settings = settings();
+        let plan = PreheatPlan::plan(&settings, &model(), Temperature::new(18.5), &today());
+
+        // Without charging, the floor would cool below the minimum
+        // temperature during the expensive morning
+        assert!(minimum_during_coast(&plan) >= settings.minimum_temperature);
+
+        let charging = plan
+            .slots()
+            .filter(|slot| slot.action == PreheatAction::Charge);
+        let mut count = 0;
+        for slot in charging {
+            assert!(slot.start < datetime!(2024-10-25 05:00), "{:?}", slot);
+            count += 1;
+        }
+        assert!(count > 0);
+
+        // The cheapest hour of the night is used first
+        assert_eq!(
+            plan.slot_at(datetime!(2024-10-25 01:30)).unwrap().action,
+            PreheatAction::Charge
+        );
+
+        let set_point = plan.set_point_at(&settings, datetime!(2024-10-25 01:30));
+        let expected = SetPoint {
+            power: PowerState::On,
+            temperature: settings.maximum_temperature,
+            reason: SetPointReason::Planned,
+        };
+        assert_eq!(set_point, Some(expected));
+
+        let set_point = plan.set_point_at(&settings, datetime!(2024-10-25 06:30));
+        let expected = SetPoint {
+            power: PowerState::Off,
+            temperature: settings.minimum_temperature,
+            reason: SetPointReason::Planned,
+        };
+        assert_eq!(set_point, Some(expected));
+
+        // Afternoon hours are left to normal control
+        let set_point = plan.set_point_at(&settings, datetime!(2024-10-25 15:00));
+        assert_eq!(set_point, None);
+    }
+
+    #[test]
+    fn test_no_charge_when_well_insulated() {
+        let settings = settings();
+        let model = ThermalModel {
+            cooling_rate: 0.02,
+            ..model()
+        };
+        let plan = PreheatPlan::plan(&settings, &model, Temperature::new(24.0), &today());
+
+        assert!(minimum_during_coast(&plan) >= settings.minimum_temperature);
+        assert!(plan
+            .slots()
+            .all(|slot| slot.action != PreheatAction::Charge));
+    }
+
+    #[test]
+    fn test_shortfall_when_no_cheap_hours() {
+        let settings = settings();
+        let slots = today();
+        // Plan starts in the expensive period with a cold floor
+        let plan = PreheatPlan::plan(&settings, &model(), Temperature::new(18.2), &slots[7..]);
+
+        assert!(plan
+            .slots()
+            .all(|slot| slot.action != PreheatAction::Charge));
+        assert!(minimum_during_coast(&plan) < settings.minimum_temperature);
+    }
+}
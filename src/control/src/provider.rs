@@ -0,0 +1,295 @@
+use anyhow::{anyhow, bail, Result};
+use time::{Date, Duration, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
+
+use crate::clock::TimeZone;
+use crate::electricity_price::{JsonFeed, MultiDayElectricityPrice, PriceFetcher};
+use crate::series::PriceSeries;
+use crate::state::ElectricityPrice;
+use crate::unit::PriceUnit;
+
+mod awattar;
+mod entsoe;
+mod nordpool;
+mod octopus;
+
+pub use awattar::Awattar;
+pub use entsoe::Entsoe;
+pub use nordpool::NordPool;
+pub use octopus::Octopus;
+
+// Day-ahead markets in continental Europe trade days in central
+// European time, whatever the local time zone of the bidding zone
+pub const CENTRAL_EUROPEAN_TIME: &str = "CET-1CEST,M3.5.0,M10.5.0/3";
+
+// Fetches prices from one source and normalises them into today's and
+// tomorrow's price series, in the canonical unit
+pub trait PriceProvider {
+    fn fetch<F: PriceFetcher>(
+        &self,
+        fetcher: &mut F,
+        now: PrimitiveDateTime,
+    ) -> Result<MultiDayElectricityPrice>;
+
+    // Unit the source publishes prices in
+    fn unit(&self) -> PriceUnit;
+}
+
+// The configured source of electricity prices
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PriceSource {
+    Feed(JsonFeed),
+    Entsoe(Entsoe),
+    NordPool(NordPool),
+    Awattar(Awattar),
+    Octopus(Octopus),
+}
+
+impl PriceProvider for PriceSource {
+    fn fetch<F: PriceFetcher>(
+        &self,
+        fetcher: &mut F,
+        now: PrimitiveDateTime,
+    ) -> Result<MultiDayElectricityPrice> {
+        match self {
+            PriceSource::Feed(feed) => feed.fetch(fetcher, now),
+            PriceSource::Entsoe(entsoe) => entsoe.fetch(fetcher, now),
+            PriceSource::NordPool(nordpool) => nordpool.fetch(fetcher, now),
+            PriceSource::Awattar(awattar) => awattar.fetch(fetcher, now),
+            PriceSource::Octopus(octopus) => octopus.fetch(fetcher, now),
+        }
+    }
+
+    fn unit(&self) -> PriceUnit {
+        match self {
+            PriceSource::Feed(feed) => feed.unit(),
+            PriceSource::Entsoe(entsoe) => entsoe.unit(),
+            PriceSource::NordPool(nordpool) => nordpool.unit(),
+            PriceSource::Awattar(awattar) => awattar.unit(),
+            PriceSource::Octopus(octopus) => octopus.unit(),
+        }
+    }
+}
+
+// Price for one interval as published by a provider, in UTC
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct PriceInterval {
+    start: PrimitiveDateTime,
+    end: PrimitiveDateTime,
+    price: ElectricityPrice,
+}
+
+// The market day containing `now`, in UTC
+fn market_day(timezone: &TimeZone, now: PrimitiveDateTime) -> (PrimitiveDateTime, Date) {
+    let date = timezone.to_local(now).date();
+    (day_start(timezone, date), date)
+}
+
+fn day_start(timezone: &TimeZone, date: Date) -> PrimitiveDateTime {
+    timezone.to_utc(PrimitiveDateTime::new(date, Time::MIDNIGHT))
+}
+
+// Group prices into the market day containing `now` and the following
+// day. Prices outside those two days are ignored, and tomorrow is left
+// out until it is published.
+fn into_days(
+    intervals: &[PriceInterval],
+    timezone: &TimeZone,
+    now: PrimitiveDateTime,
+) -> Result<MultiDayElectricityPrice> {
+    let (_, today) = market_day(timezone, now);
+    let tomorrow = today
+        .next_day()
+        .ok_or_else(|| anyhow!("No day after {}", today))?;
+
+    let Some(today_prices) = day_series(intervals, timezone, today)? else {
+        bail!("No prices for {}", today);
+    };
+    let tomorrow_prices = day_series(intervals, timezone, tomorrow)?;
+    Ok(MultiDayElectricityPrice::new(
+        Some(today_prices),
+        tomorrow_prices,
+    ))
+}
+
+// Some documents publish a day at more than one resolution, e.g.
+// ENTSO-E areas with both 15 minute and hourly series; the finest is
+// used and the others are ignored, as are repeated intervals
+fn day_series(
+    intervals: &[PriceInterval],
+    timezone: &TimeZone,
+    date: Date,
+) -> Result<Option<PriceSeries>> {
+    let valid_from = day_start(timezone, date);
+    let next = date
+        .next_day()
+        .ok_or_else(|| anyhow!("No day after {}", date))?;
+    let valid_until = day_start(timezone, next);
+
+    let day = || {
+        intervals
+            .iter()
+            .filter(|interval| valid_from <= interval.start && interval.start < valid_until)
+    };
+    let Some(length) = day().map(|interval| interval.end - interval.start).min() else {
+        return Ok(None);
+    };
+
+    let mut series = PriceSeries::new(valid_from, valid_until, length)?;
+    for interval in day().filter(|interval| interval.end - interval.start == length) {
+        if series.price_at(interval.start).is_none() {
+            series.insert(interval.start, interval.price)?;
+        }
+    }
+    Ok(Some(series))
+}
+
+fn to_utc(time: OffsetDateTime) -> PrimitiveDateTime {
+    let utc = time.to_offset(UtcOffset::UTC);
+    PrimitiveDateTime::new(utc.date(), utc.time())
+}
+
+fn from_unix_millis(millis: i64) -> Result<PrimitiveDateTime> {
+    let time = OffsetDateTime::from_unix_timestamp(millis.div_euclid(1000))
+        .map_err(|_| anyhow!("Invalid timestamp {}", millis))?
+        + Duration::milliseconds(millis.rem_euclid(1000));
+    Ok(to_utc(time))
+}
+
+fn unix_millis(time: PrimitiveDateTime) -> i64 {
+    (time.assume_utc().unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::BTreeMap;
+    use alloc::string::{String, ToString};
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use time::macros::datetime;
+
+    // Serves canned responses by URL; an unknown URL is an error. The
+    // provider fixtures in electricity-price/ are written to each
+    // documented format, including mixed ENTSO-E resolutions, 15 minute
+    // Nord Pool MTUs and Octopus page links, rather than captured;
+    // `make fixtures` captures real responses to compare against
+    pub(super) struct CannedFetcher {
+        pub(super) responses: BTreeMap<String, &'static str>,
+        pub(super) requests: Vec<String>,
+    }
+
+    impl CannedFetcher {
+        pub(super) fn new(responses: &[(&str, &'static str)]) -> CannedFetcher {
+            CannedFetcher {
+                responses: responses
+                    .iter()
+                    .map(|(url, body)| (url.to_string(), *body))
+                    .collect(),
+                requests: Vec::new(),
+            }
+        }
+    }
+
+    impl PriceFetcher for CannedFetcher {
+        type Body = &'static str;
+
+        fn fetch(&mut self, url: &str) -> Result<&'static str> {
+            self.requests.push(url.to_string());
+            self.responses
+                .get(url)
+                .copied()
+                .ok_or_else(|| anyhow!("Not found: {}", url))
+        }
+    }
+
+    // Prices converted from other units agree to within rounding
+    pub(super) fn assert_same_prices(
+        prices: &MultiDayElectricityPrice,
+        expected: &MultiDayElectricityPrice,
+    ) {
+        let days: Vec<_> = prices.days().collect();
+        let expected_days: Vec<_> = expected.days().collect();
+        assert_eq!(days.len(), expected_days.len());
+        for (day, expected) in days.iter().zip(expected_days) {
+            assert_eq!(day.valid_from(), expected.valid_from());
+            assert_eq!(day.valid_until(), expected.valid_until());
+            assert_eq!(day.interval(), expected.interval());
+            assert_eq!(day.len(), expected.len());
+            for (slot, expected) in day.slots().zip(expected.slots()) {
+                assert_eq!(slot.start, expected.start);
+                let difference = f32::from(slot.price) - f32::from(expected.price);
+                assert!(difference.abs() < 1e-4, "{:?} {:?}", slot, expected);
+            }
+        }
+    }
+
+    fn hour(start: PrimitiveDateTime, price: f32) -> PriceInterval {
+        PriceInterval {
+            start,
+            end: start + Duration::HOUR,
+            price: ElectricityPrice::new(price),
+        }
+    }
+
+    #[test]
+    fn test_into_days() {
+        let timezone: TimeZone = CENTRAL_EUROPEAN_TIME.parse().unwrap();
+        let start = datetime!(2024-10-24 21:00);
+        let intervals: Vec<PriceInterval> = (0..75)
+            .map(|index| hour(start + Duration::hours(index), index as f32))
+            .collect();
+
+        let prices = into_days(&intervals, &timezone, datetime!(2024-10-25 12:00)).unwrap();
+        let today = prices.today().unwrap();
+        assert_eq!(today.valid_from(), datetime!(2024-10-24 22:00));
+        assert_eq!(today.valid_until(), datetime!(2024-10-25 22:00));
+        assert_eq!(today.len(), 24);
+        assert_eq!(
+            prices.price_at(datetime!(2024-10-24 22:30)),
+            Some(ElectricityPrice::new(1.0))
+        );
+        assert_eq!(prices.tomorrow().unwrap().len(), 24);
+
+        // Tomorrow's prices are not published yet
+        let prices = into_days(&intervals[..25], &timezone, datetime!(2024-10-25 12:00)).unwrap();
+        assert!(prices.tomorrow().is_none());
+
+        // The clocks go back, so the day has 25 hours
+        let prices = into_days(&intervals, &timezone, datetime!(2024-10-26 12:00)).unwrap();
+        let tomorrow = prices.tomorrow().unwrap();
+        assert_eq!(tomorrow.valid_from(), datetime!(2024-10-26 22:00));
+        assert_eq!(tomorrow.valid_until(), datetime!(2024-10-27 23:00));
+        assert_eq!(tomorrow.len(), 25);
+
+        assert!(into_days(&intervals, &timezone, datetime!(2024-10-29 12:00)).is_err());
+    }
+
+    #[test]
+    fn test_into_days_mixed_intervals() {
+        // The same hour at two resolutions, and a repeated quarter hour
+        let start = datetime!(2024-10-24 22:00);
+        let mut intervals = vec![hour(start, 4.0)];
+        for index in 0..4 {
+            let mut quarter = hour(start + Duration::minutes(15 * index), index as f32);
+            quarter.end = quarter.start + Duration::minutes(15);
+            intervals.push(quarter);
+        }
+        intervals.push(intervals[1]);
+
+        let prices = into_days(&intervals, &TimeZone::UTC, start).unwrap();
+        let today = prices.today().unwrap();
+        assert_eq!(today.interval(), Duration::minutes(15));
+        assert_eq!(today.len(), 4);
+        assert_eq!(
+            prices.price_at(start + Duration::minutes(50)),
+            Some(ElectricityPrice::new(3.0))
+        );
+    }
+
+    #[test]
+    fn test_unix_millis() {
+        let time = datetime!(2024-10-25 22:00);
+        assert_eq!(unix_millis(time), 1729893600000);
+        assert_eq!(from_unix_millis(1729893600000).unwrap(), time);
+    }
+}
@@ -0,0 +1,149 @@
+// aWATTar day-ahead market data for Austria and Germany
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use time::PrimitiveDateTime;
+
+use super::{
+    day_start, from_unix_millis, into_days, market_day, unix_millis, PriceInterval, PriceProvider,
+};
+use crate::clock::TimeZone;
+use crate::electricity_price::{MultiDayElectricityPrice, PriceFetcher};
+use crate::unit::{Currency, PriceUnit};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Awattar {
+    // "at" or "de"
+    pub country: &'static str,
+    // Time zone of the market day
+    pub timezone: TimeZone,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    data: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+    // Milliseconds since the Unix epoch
+    start_timestamp: i64,
+    end_timestamp: i64,
+    marketprice: f32,
+    unit: String,
+}
+
+impl Awattar {
+    fn url(&self, start: PrimitiveDateTime, end: PrimitiveDateTime) -> String {
+        format!(
+            "https://api.awattar.{}/v1/marketdata?start={}&end={}",
+            self.country,
+            unix_millis(start),
+            unix_millis(end)
+        )
+    }
+}
+
+impl PriceProvider for Awattar {
+    fn fetch<F: PriceFetcher>(
+        &self,
+        fetcher: &mut F,
+        now: PrimitiveDateTime,
+    ) -> Result<MultiDayElectricityPrice> {
+        let (start, today) = market_day(&self.timezone, now);
+        let end = today
+            .next_day()
+            .and_then(|tomorrow| tomorrow.next_day())
+            .map(|date| day_start(&self.timezone, date))
+            .ok_or_else(|| anyhow!("No day after {}", today))?;
+
+        let json = fetcher.fetch(&self.url(start, end))?;
+        into_days(
+            &parse(json.as_ref(), self.unit().currency)?,
+            &self.timezone,
+            now,
+        )
+    }
+
+    fn unit(&self) -> PriceUnit {
+        PriceUnit::EUR_PER_MWH
+    }
+}
+
+// Each price declares its unit, e.g. "Eur/MWh"
+fn parse(json: &str, currency: Currency) -> Result<Vec<PriceInterval>> {
+    let response: Response = serde_json::from_str(json)?;
+
+    response
+        .data
+        .iter()
+        .map(|entry| {
+            let unit: PriceUnit = entry.unit.parse()?;
+            unit.check_currency(currency)?;
+            Ok(PriceInterval {
+                start: from_unix_millis(entry.start_timestamp)?,
+                end: from_unix_millis(entry.end_timestamp)?,
+                price: unit.price(entry.marketprice),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::MULTI_DAY;
+    use crate::provider::tests::{assert_same_prices, CannedFetcher};
+    use crate::provider::CENTRAL_EUROPEAN_TIME;
+    use crate::state::ElectricityPrice;
+    use time::macros::datetime;
+
+    const AWATTAR: &str = include_str!("../../../../electricity-price/awattar.json");
+
+    fn awattar() -> Awattar {
+        Awattar {
+            country: "at",
+            timezone: CENTRAL_EUROPEAN_TIME.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_url() {
+        assert_eq!(
+            awattar().url(datetime!(2024-10-24 22:00), datetime!(2024-10-26 22:00)),
+            "https://api.awattar.at/v1/marketdata?start=1729807200000&end=1729980000000"
+        );
+    }
+
+    #[test]
+    fn test_fetch_matches_feed() {
+        let awattar = awattar();
+        let now = datetime!(2024-10-25 12:00);
+        let url = awattar.url(datetime!(2024-10-24 22:00), datetime!(2024-10-26 22:00));
+        let mut fetcher = CannedFetcher::new(&[(&url, AWATTAR)]);
+
+        let prices = awattar.fetch(&mut fetcher, now).unwrap();
+        let (expected, _) = MultiDayElectricityPrice::parse(MULTI_DAY, now).unwrap();
+        assert_same_prices(&prices, &expected);
+    }
+
+    #[test]
+    fn test_parse() {
+        let intervals = parse(AWATTAR, Currency::EUR).unwrap();
+        assert_eq!(intervals.len(), 48);
+        assert_eq!(intervals[0].start, datetime!(2024-10-24 22:00));
+        assert_eq!(intervals[0].end, datetime!(2024-10-24 23:00));
+
+        let per_kwh = r#"{"data": [{"start_timestamp": 1729807200000,
+            "end_timestamp": 1729810800000, "marketprice": 0.04, "unit": "Eur/kWh"}]}"#;
+        assert_eq!(
+            parse(per_kwh, Currency::EUR).unwrap()[0].price,
+            ElectricityPrice::new(4.0)
+        );
+        assert!(parse(&per_kwh.replace("Eur", "CHF"), Currency::EUR).is_err());
+        assert!(parse(&per_kwh.replace("Eur/kWh", "kWh"), Currency::EUR).is_err());
+        assert!(parse("{}", Currency::EUR).is_err());
+    }
+}
@@ -0,0 +1,372 @@
+// ENTSO-E Transparency Platform day-ahead prices, document type A44.
+//
+// https://transparency.entsoe.eu/content/static_content/Static%20content/web%20api/Guide.html
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use anyhow::{anyhow, bail, Result};
+use roxmltree::{Document, Node};
+use time::macros::format_description;
+use time::{Duration, PrimitiveDateTime};
+
+use super::{day_start, into_days, market_day, PriceInterval, PriceProvider};
+use crate::clock::TimeZone;
+use crate::electricity_price::{MultiDayElectricityPrice, PriceFetcher};
+use crate::unit::{Currency, PriceScale, PriceUnit};
+
+const API: &str = "https://web-api.tp.entsoe.eu/api";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Entsoe {
+    pub token: &'static str,
+    // EIC code of the bidding zone, e.g. "10YFI-1--------U"
+    pub area: &'static str,
+    // Time zone of the market day
+    pub timezone: TimeZone,
+}
+
+impl Entsoe {
+    fn url(&self, start: PrimitiveDateTime, end: PrimitiveDateTime) -> String {
+        format!(
+            "{}?securityToken={}&documentType=A44&in_Domain={}&out_Domain={}&periodStart={}&periodEnd={}",
+            API,
+            self.token,
+            self.area,
+            self.area,
+            period(start),
+            period(end)
+        )
+    }
+}
+
+impl PriceProvider for Entsoe {
+    fn fetch<F: PriceFetcher>(
+        &self,
+        fetcher: &mut F,
+        now: PrimitiveDateTime,
+    ) -> Result<MultiDayElectricityPrice> {
+        let (start, today) = market_day(&self.timezone, now);
+        let end = today
+            .next_day()
+            .and_then(|tomorrow| tomorrow.next_day())
+            .map(|date| day_start(&self.timezone, date))
+            .ok_or_else(|| anyhow!("No day after {}", today))?;
+
+        let xml = fetcher.fetch(&self.url(start, end))?;
+        into_days(
+            &parse(xml.as_ref(), self.unit().currency)?,
+            &self.timezone,
+            now,
+        )
+    }
+
+    // Day-ahead prices are always published in EUR/MWh
+    fn unit(&self) -> PriceUnit {
+        PriceUnit::EUR_PER_MWH
+    }
+}
+
+// Times in requests are yyyyMMddHHmm, in UTC
+fn period(time: PrimitiveDateTime) -> String {
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}",
+        time.year(),
+        time.month() as u8,
+        time.day(),
+        time.hour(),
+        time.minute()
+    )
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Result<Node<'a, 'input>> {
+    node.children()
+        .find(|child| child.has_tag_name(name))
+        .ok_or_else(|| anyhow!("Missing <{}> in <{}>", name, node.tag_name().name()))
+}
+
+fn text<'a>(node: Node<'a, '_>, name: &str) -> Result<&'a str> {
+    Ok(child(node, name)?.text().unwrap_or_default().trim())
+}
+
+// Interval ends are given to the minute, e.g. 2024-10-24T22:00Z
+fn timestamp(text: &str) -> Result<PrimitiveDateTime> {
+    let format = format_description!("[year]-[month]-[day]T[hour]:[minute]Z");
+    PrimitiveDateTime::parse(text, format).map_err(|_| anyhow!("Invalid time {:?}", text))
+}
+
+// ISO 8601 durations as used for resolutions, e.g. PT15M or PT60M, of
+// one minute to an hour
+fn resolution(text: &str) -> Result<Duration> {
+    let invalid = || anyhow!("Unsupported resolution {:?}", text);
+    let amount = text.strip_prefix("PT").ok_or_else(invalid)?;
+    let (number, unit) = amount.split_at(amount.len().saturating_sub(1));
+    let number: i64 = number.parse().map_err(|_| invalid())?;
+    let minutes = match unit {
+        "M" => number,
+        "H" => number.checked_mul(60).ok_or_else(invalid)?,
+        _ => return Err(invalid()),
+    };
+    if !(1..=60).contains(&minutes) {
+        return Err(invalid());
+    }
+    Ok(Duration::minutes(minutes))
+}
+
+// Units are declared for each series, e.g. EUR and MWH
+fn series_unit(series: Node) -> Result<PriceUnit> {
+    let currency = text(series, "currency_Unit.name")?.parse()?;
+    match text(series, "price_Measure_Unit.name")? {
+        "MWH" => Ok(PriceUnit::new(currency, PriceScale::PerMwh)),
+        unit => bail!("Unsupported price unit {:?}", unit),
+    }
+}
+
+fn parse(xml: &str, currency: Currency) -> Result<Vec<PriceInterval>> {
+    let document =
+        Document::parse(xml).map_err(|error| anyhow!("Invalid ENTSO-E document: {}", error))?;
+    let root = document.root_element();
+
+    // Errors, including no data yet, are acknowledgements with a reason
+    if root.has_tag_name("Acknowledgement_MarketDocument") {
+        let reason = child(root, "Reason").and_then(|reason| text(reason, "text"));
+        bail!("ENTSO-E: {}", reason.unwrap_or("no reason given"));
+    }
+    if !root.has_tag_name("Publication_MarketDocument") {
+        bail!("Unexpected ENTSO-E document <{}>", root.tag_name().name());
+    }
+
+    let mut intervals = Vec::new();
+    for series in root
+        .children()
+        .filter(|node| node.has_tag_name("TimeSeries"))
+    {
+        // A01 has a point for every interval; under A03 a missing
+        // point has the same price as the one before
+        let curve = series
+            .children()
+            .find(|node| node.has_tag_name("curveType"))
+            .and_then(|node| node.text());
+        let repeat = match curve {
+            Some("A03") => true,
+            Some("A01") | None => false,
+            Some(curve) => bail!("Unsupported curve type {:?}", curve),
+        };
+        let unit = series_unit(series)?;
+        unit.check_currency(currency)?;
+        for period in series.children().filter(|node| node.has_tag_name("Period")) {
+            parse_period(period, unit, repeat, &mut intervals)?;
+        }
+    }
+    Ok(intervals)
+}
+
+fn parse_period(
+    period: Node,
+    unit: PriceUnit,
+    repeat: bool,
+    intervals: &mut Vec<PriceInterval>,
+) -> Result<()> {
+    let time_interval = child(period, "timeInterval")?;
+    let start = timestamp(text(time_interval, "start")?)?;
+    let end = timestamp(text(time_interval, "end")?)?;
+    let resolution = resolution(text(period, "resolution")?)?;
+    let count = (end - start).whole_minutes() / resolution.whole_minutes();
+
+    let mut points = Vec::new();
+    for point in period.children().filter(|node| node.has_tag_name("Point")) {
+        let position: i64 = text(point, "position")?.parse()?;
+        let price: f32 = text(point, "price.amount")?.parse()?;
+        if position < 1 || position > count {
+            bail!("Price position {} outside 1 to {}", position, count);
+        }
+        points.push((position, price));
+    }
+    points.sort_by_key(|(position, _)| *position);
+
+    let mut points = points.into_iter().peekable();
+    let mut price = None;
+    for position in 1..=count {
+        match points.next_if(|(next, _)| *next == position) {
+            Some((_, next)) => price = Some(next),
+            None if repeat => {}
+            None => price = None,
+        }
+        let Some(price) = price else {
+            continue;
+        };
+        let start = start + resolution * (position - 1) as i32;
+        intervals.push(PriceInterval {
+            start,
+            end: start + resolution,
+            price: unit.price(price),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::MULTI_DAY;
+    use crate::provider::tests::{assert_same_prices, CannedFetcher};
+    use crate::provider::CENTRAL_EUROPEAN_TIME;
+    use crate::state::ElectricityPrice;
+    use time::macros::datetime;
+
+    const HOURLY: &str = include_str!("../../../../electricity-price/entsoe.xml");
+    const QUARTER_HOURLY: &str = include_str!("../../../../electricity-price/entsoe-15min.xml");
+    const NO_DATA: &str = include_str!("../../../../electricity-price/entsoe-no-data.xml");
+    const MIXED_RESOLUTION: &str =
+        include_str!("../../../../electricity-price/entsoe-mixed-resolution.xml");
+    const ZERO_RESOLUTION: &str =
+        include_str!("../../../../electricity-price/entsoe-zero-resolution.xml");
+    const OVERFLOW_RESOLUTION: &str =
+        include_str!("../../../../electricity-price/entsoe-overflow-resolution.xml");
+
+    fn entsoe() -> Entsoe {
+        Entsoe {
+            token: "token",
+            area: "10YFI-1--------U",
+            timezone: CENTRAL_EUROPEAN_TIME.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_url() {
+        assert_eq!(
+            entsoe().url(datetime!(2024-10-24 22:00), datetime!(2024-10-26 22:00)),
+            "https://web-api.tp.entsoe.eu/api?securityToken=token&documentType=A44\
+             &in_Domain=10YFI-1--------U&out_Domain=10YFI-1--------U\
+             &periodStart=202410242200&periodEnd=202410262200"
+        );
+    }
+
+    #[test]
+    fn test_fetch_matches_feed() {
+        let entsoe = entsoe();
+        let now = datetime!(2024-10-25 12:00);
+        let url = entsoe.url(datetime!(2024-10-24 22:00), datetime!(2024-10-26 22:00));
+        let mut fetcher = CannedFetcher::new(&[(&url, HOURLY)]);
+
+        let prices = entsoe.fetch(&mut fetcher, now).unwrap();
+        let (expected, _) = MultiDayElectricityPrice::parse(MULTI_DAY, now).unwrap();
+        assert_same_prices(&prices, &expected);
+    }
+
+    #[test]
+    fn test_parse_15_minutes() {
+        // The clocks go back, so the day has 100 quarter hours
+        let intervals = parse(QUARTER_HOURLY, Currency::EUR).unwrap();
+        assert_eq!(intervals.len(), 100);
+        assert_eq!(intervals[0].start, datetime!(2024-10-26 22:00));
+        assert_eq!(intervals[99].end, datetime!(2024-10-27 23:00));
+        assert_eq!(intervals[1].price, ElectricityPrice::new(1.25));
+
+        let prices =
+            into_days(&intervals, &entsoe().timezone, datetime!(2024-10-27 12:00)).unwrap();
+        let today = prices.today().unwrap();
+        assert_eq!(today.interval(), Duration::minutes(15));
+        assert_eq!(today.len(), 100);
+        assert_eq!(today.hourly().len(), 25);
+    }
+
+    #[test]
+    fn test_mixed_resolutions() {
+        // Some areas publish hourly series alongside the 15 minute ones
+        let intervals = parse(MIXED_RESOLUTION, Currency::EUR).unwrap();
+        assert_eq!(intervals.len(), 24 + 96);
+
+        let prices =
+            into_days(&intervals, &entsoe().timezone, datetime!(2025-10-01 12:00)).unwrap();
+        let today = prices.today().unwrap();
+        assert_eq!(today.interval(), Duration::minutes(15));
+        assert_eq!(today.len(), 96);
+        assert_eq!(
+            prices.price_at(datetime!(2025-09-30 22:20)),
+            Some(ElectricityPrice::new(5.321))
+        );
+    }
+
+    fn document(currency: &str, curve: &str) -> String {
+        format!(
+            "<Publication_MarketDocument>
+              <TimeSeries>
+                <currency_Unit.name>{}</currency_Unit.name>
+                <price_Measure_Unit.name>MWH</price_Measure_Unit.name>
+                <curveType>{}</curveType>
+                <Period>
+                  <timeInterval>
+                    <start>2024-10-24T22:00Z</start>
+                    <end>2024-10-25T02:00Z</end>
+                  </timeInterval>
+                  <resolution>PT60M</resolution>
+                  <Point><position>1</position><price.amount>10</price.amount></Point>
+                  <Point><position>3</position><price.amount>30</price.amount></Point>
+                </Period>
+              </TimeSeries>
+            </Publication_MarketDocument>",
+            currency, curve
+        )
+    }
+
+    #[test]
+    fn test_curve_types() {
+        // Missing points repeat the previous price, to the end of the period
+        let prices: Vec<f32> = parse(&document("EUR", "A03"), Currency::EUR)
+            .unwrap()
+            .iter()
+            .map(|interval| f32::from(interval.price))
+            .collect();
+        assert_eq!(prices, [1.0, 1.0, 3.0, 3.0]);
+
+        // Missing points have no price
+        let intervals = parse(&document("EUR", "A01"), Currency::EUR).unwrap();
+        assert_eq!(intervals.len(), 2);
+        assert_eq!(intervals[1].start, datetime!(2024-10-25 00:00));
+
+        assert!(parse(&document("EUR", "A02"), Currency::EUR).is_err());
+    }
+
+    #[test]
+    fn test_units() {
+        // Prices in another currency cannot be used
+        assert!(parse(&document("SEK", "A01"), Currency::EUR).is_err());
+        let prices = parse(&document("SEK", "A01"), "SEK".parse().unwrap()).unwrap();
+        assert_eq!(prices[0].price, ElectricityPrice::new(1.0));
+
+        let other_unit = document("EUR", "A01").replace(">MWH<", ">KWH<");
+        assert!(parse(&other_unit, Currency::EUR).is_err());
+    }
+
+    #[test]
+    fn test_no_data() {
+        let error = parse(NO_DATA, Currency::EUR).unwrap_err();
+        assert!(format!("{}", error).contains("No matching data found"));
+        assert!(parse("<html/>", Currency::EUR).is_err());
+        assert!(parse("not xml", Currency::EUR).is_err());
+    }
+
+    #[test]
+    fn test_resolution() {
+        assert_eq!(resolution("PT60M").unwrap(), Duration::HOUR);
+        assert_eq!(resolution("PT1H").unwrap(), Duration::HOUR);
+        assert_eq!(resolution("PT15M").unwrap(), Duration::minutes(15));
+        assert!(resolution("P1D").is_err());
+        assert!(resolution("PTM").is_err());
+        assert!(resolution("PT0M").is_err());
+        assert!(resolution("PT-15M").is_err());
+        assert!(resolution("PT0H").is_err());
+        assert!(resolution("PT2H").is_err());
+        assert!(resolution("PT9223372036854775807M").is_err());
+        assert!(resolution("PT9223372036854775807H").is_err());
+    }
+
+    #[test]
+    fn test_invalid_resolution() {
+        // Rejected rather than dividing by zero or overflowing
+        assert!(parse(ZERO_RESOLUTION, Currency::EUR).is_err());
+        assert!(parse(OVERFLOW_RESOLUTION, Currency::EUR).is_err());
+        let valid = ZERO_RESOLUTION.replace("PT0M", "PT60M");
+        assert_eq!(parse(&valid, Currency::EUR).unwrap().len(), 3);
+    }
+}
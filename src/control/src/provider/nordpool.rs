@@ -0,0 +1,210 @@
+// Nord Pool day-ahead prices from the data portal, one market day per
+// request
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use time::format_description::well_known::Rfc3339;
+use time::{Date, OffsetDateTime, PrimitiveDateTime};
+
+use super::{into_days, market_day, to_utc, PriceInterval, PriceProvider};
+use crate::clock::TimeZone;
+use crate::electricity_price::{MultiDayElectricityPrice, PriceFetcher};
+use crate::unit::{Currency, PriceScale, PriceUnit};
+
+const API: &str = "https://dataportal-api.nordpoolgroup.com/api/DayAheadPrices";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct NordPool {
+    // Delivery area, e.g. "FI" or "SE3"
+    pub area: &'static str,
+    pub currency: Currency,
+    // Time zone of the market day
+    pub timezone: TimeZone,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Response {
+    currency: String,
+    multi_area_entries: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    delivery_start: String,
+    delivery_end: String,
+    // Per MWh
+    entry_per_area: BTreeMap<String, f32>,
+}
+
+impl NordPool {
+    fn url(&self, date: Date) -> String {
+        format!(
+            "{}?date={}&market=DayAhead&deliveryArea={}&currency={}",
+            API, date, self.area, self.currency
+        )
+    }
+
+    // Prices for one market day; none before they are published
+    fn day<F: PriceFetcher>(&self, fetcher: &mut F, date: Date) -> Result<Vec<PriceInterval>> {
+        let json = fetcher.fetch(&self.url(date))?;
+        parse(json.as_ref(), self.area, self.unit())
+    }
+}
+
+impl PriceProvider for NordPool {
+    fn fetch<F: PriceFetcher>(
+        &self,
+        fetcher: &mut F,
+        now: PrimitiveDateTime,
+    ) -> Result<MultiDayElectricityPrice> {
+        let (_, today) = market_day(&self.timezone, now);
+        let tomorrow = today
+            .next_day()
+            .ok_or_else(|| anyhow!("No day after {}", today))?;
+
+        let mut intervals = self.day(fetcher, today)?;
+        intervals.extend(self.day(fetcher, tomorrow)?);
+        into_days(&intervals, &self.timezone, now)
+    }
+
+    fn unit(&self) -> PriceUnit {
+        PriceUnit::new(self.currency, PriceScale::PerMwh)
+    }
+}
+
+fn timestamp(text: &str) -> Result<PrimitiveDateTime> {
+    let time =
+        OffsetDateTime::parse(text, &Rfc3339).map_err(|_| anyhow!("Invalid time {:?}", text))?;
+    Ok(to_utc(time))
+}
+
+// Unpublished days are an empty response
+fn parse(json: &str, area: &str, unit: PriceUnit) -> Result<Vec<PriceInterval>> {
+    if json.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let response: Response = serde_json::from_str(json)?;
+    unit.check_currency(response.currency.parse()?)?;
+
+    response
+        .multi_area_entries
+        .iter()
+        .map(|entry| {
+            let price = entry
+                .entry_per_area
+                .get(area)
+                .ok_or_else(|| anyhow!("No price for {} at {}", area, entry.delivery_start))?;
+            Ok(PriceInterval {
+                start: timestamp(&entry.delivery_start)?,
+                end: timestamp(&entry.delivery_end)?,
+                price: unit.price(*price),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::MULTI_DAY;
+    use crate::provider::tests::{assert_same_prices, CannedFetcher};
+    use crate::provider::CENTRAL_EUROPEAN_TIME;
+    use crate::state::ElectricityPrice;
+    use time::macros::{date, datetime};
+    use time::Duration;
+
+    const FIRST_DAY: &str = include_str!("../../../../electricity-price/nordpool-2024-10-25.json");
+    const SECOND_DAY: &str = include_str!("../../../../electricity-price/nordpool-2024-10-26.json");
+    const SINGLE_DAY: &str = include_str!("../../../../electricity-price/singleday.json");
+    const QUARTER_HOURLY: &str = include_str!("../../../../electricity-price/nordpool-15min.json");
+
+    fn nordpool() -> NordPool {
+        NordPool {
+            area: "FI",
+            currency: Currency::EUR,
+            timezone: CENTRAL_EUROPEAN_TIME.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_url() {
+        assert_eq!(
+            nordpool().url(date!(2024 - 10 - 25)),
+            "https://dataportal-api.nordpoolgroup.com/api/DayAheadPrices\
+             ?date=2024-10-25&market=DayAhead&deliveryArea=FI&currency=EUR"
+        );
+    }
+
+    #[test]
+    fn test_fetch_matches_feed() {
+        let nordpool = nordpool();
+        let now = datetime!(2024-10-25 12:00);
+        let mut fetcher = CannedFetcher::new(&[
+            (&nordpool.url(date!(2024 - 10 - 25)), FIRST_DAY),
+            (&nordpool.url(date!(2024 - 10 - 26)), SECOND_DAY),
+        ]);
+
+        let prices = nordpool.fetch(&mut fetcher, now).unwrap();
+        let (expected, _) = MultiDayElectricityPrice::parse(MULTI_DAY, now).unwrap();
+        assert_same_prices(&prices, &expected);
+        assert_eq!(fetcher.requests.len(), 2);
+    }
+
+    #[test]
+    fn test_tomorrow_not_published() {
+        let nordpool = nordpool();
+        let now = datetime!(2024-10-25 08:00);
+        let mut fetcher = CannedFetcher::new(&[
+            (&nordpool.url(date!(2024 - 10 - 25)), FIRST_DAY),
+            (&nordpool.url(date!(2024 - 10 - 26)), ""),
+        ]);
+
+        let prices = nordpool.fetch(&mut fetcher, now).unwrap();
+        let (expected, _) = MultiDayElectricityPrice::parse(SINGLE_DAY, now).unwrap();
+        assert_same_prices(&prices, &expected);
+        assert!(prices.tomorrow().is_none());
+    }
+
+    #[test]
+    fn test_parse() {
+        let unit = nordpool().unit();
+        let intervals = parse(FIRST_DAY, "FI", unit).unwrap();
+        assert_eq!(intervals.len(), 24);
+        assert_eq!(intervals[0].start, datetime!(2024-10-24 22:00));
+        assert_eq!(intervals[0].end, datetime!(2024-10-24 23:00));
+
+        // Another area than the one requested
+        assert!(parse(FIRST_DAY, "SE3", unit).is_err());
+        assert!(parse("{}", "FI", unit).is_err());
+
+        // Prices in another currency than requested
+        let sek = PriceUnit::new("SEK".parse().unwrap(), PriceScale::PerMwh);
+        assert!(parse(FIRST_DAY, "FI", sek).is_err());
+    }
+
+    #[test]
+    fn test_15_minute_mtu() {
+        // Market time units are 15 minutes from 2025-10-01
+        let nordpool = nordpool();
+        let now = datetime!(2025-10-01 12:00);
+        let mut fetcher = CannedFetcher::new(&[
+            (&nordpool.url(date!(2025 - 10 - 01)), QUARTER_HOURLY),
+            (&nordpool.url(date!(2025 - 10 - 02)), ""),
+        ]);
+
+        let prices = nordpool.fetch(&mut fetcher, now).unwrap();
+        let today = prices.today().unwrap();
+        assert_eq!(today.valid_from(), datetime!(2025-09-30 22:00));
+        assert_eq!(today.interval(), Duration::minutes(15));
+        assert_eq!(today.len(), 96);
+        assert_eq!(
+            prices.price_at(datetime!(2025-09-30 22:20)),
+            Some(ElectricityPrice::new(5.321))
+        );
+    }
+}
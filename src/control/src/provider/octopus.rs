@@ -0,0 +1,200 @@
+// Octopus Energy unit rates, e.g. for the half hourly Agile tariff
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use time::format_description::well_known::Rfc3339;
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+use super::{day_start, into_days, market_day, to_utc, PriceInterval, PriceProvider};
+use crate::clock::TimeZone;
+use crate::electricity_price::{MultiDayElectricityPrice, PriceFetcher};
+use crate::unit::PriceUnit;
+
+const API: &str = "https://api.octopus.energy/v1/products";
+
+// Rates for two days fit on one page; a few more allow for smaller pages
+const MAX_PAGES: usize = 4;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Octopus {
+    // e.g. "AGILE-24-10-01"
+    pub product: &'static str,
+    // e.g. "E-1R-AGILE-24-10-01-C" for the region
+    pub tariff: &'static str,
+    // Time zone of the tariff day, "GMT0BST,M3.5.0/1,M10.5.0"
+    pub timezone: TimeZone,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    next: Option<String>,
+    results: Vec<Rate>,
+}
+
+#[derive(Deserialize)]
+struct Rate {
+    // Pence per kWh
+    value_exc_vat: f32,
+    valid_from: String,
+    valid_to: Option<String>,
+}
+
+impl Octopus {
+    fn url(&self, start: PrimitiveDateTime, end: PrimitiveDateTime) -> String {
+        format!(
+            "{}/{}/electricity-tariffs/{}/standard-unit-rates/?period_from={}&period_to={}",
+            API,
+            self.product,
+            self.tariff,
+            timestamp(start),
+            timestamp(end)
+        )
+    }
+}
+
+impl PriceProvider for Octopus {
+    fn fetch<F: PriceFetcher>(
+        &self,
+        fetcher: &mut F,
+        now: PrimitiveDateTime,
+    ) -> Result<MultiDayElectricityPrice> {
+        let (start, today) = market_day(&self.timezone, now);
+        let end = today
+            .next_day()
+            .and_then(|tomorrow| tomorrow.next_day())
+            .map(|date| day_start(&self.timezone, date))
+            .ok_or_else(|| anyhow!("No day after {}", today))?;
+
+        let mut intervals = Vec::new();
+        let mut url = Some(self.url(start, end));
+        for _ in 0..MAX_PAGES {
+            let Some(page) = url.take() else {
+                break;
+            };
+            let json = fetcher.fetch(&page)?;
+            url = parse(json.as_ref(), &mut intervals)?;
+        }
+        if url.is_some() {
+            bail!("More than {} pages of rates", MAX_PAGES);
+        }
+        into_days(&intervals, &self.timezone, now)
+    }
+
+    fn unit(&self) -> PriceUnit {
+        PriceUnit::GBP_PENCE_PER_KWH
+    }
+}
+
+fn timestamp(time: PrimitiveDateTime) -> String {
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}Z",
+        time.year(),
+        time.month() as u8,
+        time.day(),
+        time.hour(),
+        time.minute()
+    )
+}
+
+fn parse_timestamp(text: &str) -> Result<PrimitiveDateTime> {
+    let time =
+        OffsetDateTime::parse(text, &Rfc3339).map_err(|_| anyhow!("Invalid time {:?}", text))?;
+    Ok(to_utc(time))
+}
+
+// Adds one page of rates, newest first, and returns the next page.
+// Rates are in pence per kWh.
+fn parse(json: &str, intervals: &mut Vec<PriceInterval>) -> Result<Option<String>> {
+    let response: Response = serde_json::from_str(json)?;
+
+    for rate in response.results.iter().rev() {
+        let Some(valid_to) = &rate.valid_to else {
+            bail!("Open ended rate from {}", rate.valid_from);
+        };
+        intervals.push(PriceInterval {
+            start: parse_timestamp(&rate.valid_from)?,
+            end: parse_timestamp(valid_to)?,
+            price: PriceUnit::GBP_PENCE_PER_KWH.price(rate.value_exc_vat),
+        });
+    }
+    Ok(response.next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::tests::CannedFetcher;
+    use crate::state::ElectricityPrice;
+    use time::macros::datetime;
+    use time::Duration;
+
+    const FIRST_PAGE: &str = include_str!("../../../../electricity-price/octopus-agile.json");
+    const SECOND_PAGE: &str =
+        include_str!("../../../../electricity-price/octopus-agile-page-2.json");
+
+    fn octopus() -> Octopus {
+        Octopus {
+            product: "AGILE-24-10-01",
+            tariff: "E-1R-AGILE-24-10-01-C",
+            timezone: "GMT0BST,M3.5.0/1,M10.5.0".parse().unwrap(),
+        }
+    }
+
+    fn first_page_url() -> String {
+        octopus().url(datetime!(2024-10-24 23:00), datetime!(2024-10-26 23:00))
+    }
+
+    #[test]
+    fn test_url() {
+        assert_eq!(
+            first_page_url(),
+            "https://api.octopus.energy/v1/products/AGILE-24-10-01/electricity-tariffs/\
+             E-1R-AGILE-24-10-01-C/standard-unit-rates/\
+             ?period_from=2024-10-24T23:00Z&period_to=2024-10-26T23:00Z"
+        );
+    }
+
+    #[test]
+    fn test_fetch() {
+        let octopus = octopus();
+        let now = datetime!(2024-10-25 17:00);
+        let url = first_page_url();
+        // Pages link onwards with their query re-encoded and sorted
+        let second_url = "https://api.octopus.energy/v1/products/AGILE-24-10-01/\
+                          electricity-tariffs/E-1R-AGILE-24-10-01-C/standard-unit-rates/\
+                          ?page=2&period_from=2024-10-24T23%3A00Z&period_to=2024-10-26T23%3A00Z";
+        let mut fetcher = CannedFetcher::new(&[(&url, FIRST_PAGE), (second_url, SECOND_PAGE)]);
+
+        let prices = octopus.fetch(&mut fetcher, now).unwrap();
+        assert_eq!(fetcher.requests, [url.as_str(), second_url]);
+
+        let today = prices.today().unwrap();
+        assert_eq!(today.valid_from(), datetime!(2024-10-24 23:00));
+        assert_eq!(today.interval(), Duration::minutes(30));
+        assert_eq!(today.len(), 48);
+        assert_eq!(prices.tomorrow().unwrap().len(), 48);
+        assert_eq!(
+            prices.price_at(datetime!(2024-10-24 23:15)),
+            Some(ElectricityPrice::new(14.28))
+        );
+        assert_eq!(
+            prices.price_at(datetime!(2024-10-26 22:45)),
+            Some(ElectricityPrice::new(15.75))
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut intervals = Vec::new();
+        let next = parse(SECOND_PAGE, &mut intervals).unwrap();
+        assert_eq!(next, None);
+        // Oldest first
+        assert!(intervals[0].start < intervals[1].start);
+
+        let open_ended = r#"{"next": null, "results": [{"value_exc_vat": 24.5,
+            "valid_from": "2024-10-01T00:00:00Z", "valid_to": null}]}"#;
+        assert!(parse(open_ended, &mut intervals).is_err());
+    }
+}
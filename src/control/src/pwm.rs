@@ -0,0 +1,398 @@
+use anyhow::{bail, Result};
+use core::time::Duration;
+
+use crate::pid::{Pid, PidConfig};
+use crate::relay::RelayConfig;
+use crate::state::{PowerState, Temperature};
+use crate::SetPoint;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PwmConfig {
+    // Length of each on/off cycle
+    pub cycle_period: Duration,
+
+    // Controller for the on-fraction of each cycle, with an output
+    // range within 0.0 to 1.0
+    pub pid: PidConfig,
+
+    // Shortest on or off period within a cycle; shorter pulses are
+    // dropped so the relay is not switched needlessly
+    pub minimum_pulse: Duration,
+}
+
+impl PwmConfig {
+    // Every planned pulse must be long enough for the relay to follow;
+    // otherwise the relay holds its state past the planned transition
+    // and the heating applied differs from the controller output
+    pub fn validate(&self, relay: &RelayConfig) -> Result<()> {
+        let hold = relay.minimum_on_time.max(relay.minimum_off_time);
+        if self.minimum_pulse < hold {
+            bail!(
+                "Minimum pulse of {:?} is shorter than the relay minimum on or off time of {:?}",
+                self.minimum_pulse,
+                hold
+            );
+        }
+        if self.cycle_period < self.minimum_pulse * 2 {
+            bail!(
+                "Cycle period of {:?} is too short for an on and an off pulse of {:?}",
+                self.cycle_period,
+                self.minimum_pulse
+            );
+        }
+        Ok(())
+    }
+}
+
+// One cycle of time-proportional heating: on from `start` until
+// `switch_off`, then off until `end`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PwmCycle {
+    pub start: Duration,
+    pub switch_off: Duration,
+    pub end: Duration,
+}
+
+impl PwmCycle {
+    pub fn power_at(&self, now: Duration) -> PowerState {
+        if now < self.switch_off {
+            PowerState::On
+        } else {
+            PowerState::Off
+        }
+    }
+
+    // Time of the next relay transition after `now`, if any
+    pub fn next_transition(&self, now: Duration) -> Duration {
+        if now < self.switch_off {
+            self.switch_off
+        } else {
+            self.end
+        }
+    }
+}
+
+// Time-proportional control of the relay, tracking the set point
+// temperature with an on-fraction over each cycle rather than
+// switching on whenever the temperature is in range
+#[derive(Debug, Clone, Copy)]
+pub struct TimeProportional {
+    config: PwmConfig,
+    pid: Pid,
+    cycle: Option<PwmCycle>,
+    previous_update: Option<Duration>,
+}
+
+impl TimeProportional {
+    pub fn new(config: PwmConfig) -> TimeProportional {
+        TimeProportional {
+            config,
+            pid: Pid::new(config.pid),
+            cycle: None,
+            previous_update: None,
+        }
+    }
+
+    pub fn cycle(&self) -> Option<PwmCycle> {
+        self.cycle
+    }
+
+    // Relay state at the monotonic time `now`; a new cycle starts when
+    // the previous one has ended, with the on-fraction from the
+    // controller at that point
+    pub fn update(
+        &mut self,
+        now: Duration,
+        temperature: Temperature,
+        set_point: &SetPoint,
+    ) -> PowerState {
+        // Heating is not wanted at all, e.g. above the price ceiling
+        if set_point.power == PowerState::Off {
+            self.cycle = None;
+            return PowerState::Off;
+        }
+
+        let cycle = match self.cycle {
+            Some(cycle) if now < cycle.end => cycle,
+            _ => {
+                let elapsed = match self.previous_update {
+                    Some(previous) => now.saturating_sub(previous),
+                    None => self.config.cycle_period,
+                };
+                self.previous_update = Some(now);
+                let on_fraction = self.pid.update(set_point.temperature, temperature, elapsed);
+                let cycle = self.start_cycle(now, on_fraction);
+                self.cycle = Some(cycle);
+                cycle
+            }
+        };
+        cycle.power_at(now)
+    }
+
+    fn start_cycle(&self, now: Duration, on_fraction: f32) -> PwmCycle {
+        let period = self.config.cycle_period;
+        let mut on_time = period.mul_f32(on_fraction.clamp(0.0, 1.0));
+
+        let minimum_pulse = self.config.minimum_pulse;
+        if on_time < minimum_pulse {
+            on_time = Duration::ZERO;
+        } else if period.saturating_sub(on_time) < minimum_pulse {
+            on_time = period;
+        }
+
+        PwmCycle {
+            start: now,
+            switch_off: now + on_time,
+            end: now + period,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::relay::{RelayProtection, SwitchPriority};
+    use crate::state::TemperatureDelta;
+    use crate::thermal::ThermalModel;
+    use crate::SetPointReason;
+
+    const STEP: Duration = Duration::from_secs(60);
+
+    fn config(integral_gain: f32) -> PwmConfig {
+        PwmConfig {
+            cycle_period: Duration::from_secs(30 * 60),
+            pid: PidConfig {
+                proportional_gain: 0.5,
+                integral_gain,
+                derivative_gain: 0.0,
+                output_minimum: 0.0,
+                output_maximum: 1.0,
+            },
+            minimum_pulse: Duration::from_secs(120),
+        }
+    }
+
+    fn model() -> ThermalModel {
+        ThermalModel {
+            heating_rate: 2.0,
+            cooling_rate: 0.1,
+            ambient_temperature: Temperature::new(10.0),
+        }
+    }
+
+    fn set_point(temperature: f32) -> SetPoint {
+        SetPoint {
+            power: PowerState::On,
+            temperature: Temperature::new(temperature),
+            reason: SetPointReason::PriceScaled { fraction: 1.0 },
+        }
+    }
+
+    // Simulate the floor for `hours`, returning the mean temperature
+    // over the final six hours and the number of relay switches
+    fn simulate(controller: &mut TimeProportional, start: f32, hours: u64) -> (Temperature, usize) {
+        let model = model();
+        let target = set_point(21.0);
+        let steps = hours * 60;
+
+        let mut temperature = Temperature::new(start);
+        let mut previous = PowerState::Off;
+        let mut switches = 0;
+        let mut total = TemperatureDelta::ZERO;
+        let mut count = 0;
+        for step in 0..steps {
+            let now = STEP * step as u32;
+            let power = controller.update(now, temperature, &target);
+            if power != previous {
+                switches += 1;
+                previous = power;
+            }
+            temperature = model.predict(temperature, power, STEP.as_secs_f32() / 3600.0);
+            if step >= steps - 6 * 60 {
+                total = total + (temperature - target.temperature);
+                count += 1;
+            }
+        }
+        (target.temperature + total / count as f32, switches)
+    }
+
+    #[test]
+    fn test_cycle_transitions() {
+        let mut controller = TimeProportional::new(config(0.0));
+        let start = Duration::from_secs(1000);
+
+        // One degree below the set point is half on
+        let power = controller.update(start, Temperature::new(20.0), &set_point(21.0));
+        assert_eq!(power, PowerState::On);
+        let cycle = controller.cycle().unwrap();
+        assert_eq!(cycle.start, start);
+        assert_eq!(cycle.switch_off, start + Duration::from_secs(15 * 60));
+        assert_eq!(cycle.end, start + Duration::from_secs(30 * 60));
+        assert_eq!(cycle.next_transition(start), cycle.switch_off);
+        assert_eq!(cycle.next_transition(cycle.switch_off), cycle.end);
+
+        // The cycle continues regardless of the temperature
+        let now = start + Duration::from_secs(14 * 60);
+        let power = controller.update(now, Temperature::new(25.0), &set_point(21.0));
+        assert_eq!(power, PowerState::On);
+        let now = start + Duration::from_secs(15 * 60);
+        let power = controller.update(now, Temperature::new(25.0), &set_point(21.0));
+        assert_eq!(power, PowerState::Off);
+
+        // A new cycle starts at the end of the previous one
+        let now = cycle.end;
+        let power = controller.update(now, Temperature::new(25.0), &set_point(21.0));
+        assert_eq!(power, PowerState::Off);
+        assert_eq!(controller.cycle().unwrap().start, now);
+    }
+
+    #[test]
+    fn test_saturation_and_minimum_pulse() {
+        let mut controller = TimeProportional::new(config(0.0));
+
+        // Far below the set point is fully on
+        controller.update(Duration::ZERO, Temperature::new(15.0), &set_point(21.0));
+        let cycle = controller.cycle().unwrap();
+        assert_eq!(cycle.switch_off, cycle.end);
+
+        // A one minute pulse is dropped
+        let mut controller = TimeProportional::new(config(0.0));
+        let power = controller.update(Duration::ZERO, Temperature::new(20.93), &set_point(21.0));
+        assert_eq!(power, PowerState::Off);
+        let cycle = controller.cycle().unwrap();
+        assert_eq!(cycle.switch_off, cycle.start);
+
+        // As is a one minute gap
+        let mut controller = TimeProportional::new(config(0.0));
+        controller.update(Duration::ZERO, Temperature::new(19.07), &set_point(21.0));
+        let cycle = controller.cycle().unwrap();
+        assert_eq!(cycle.switch_off, cycle.end);
+    }
+
+    #[test]
+    fn test_set_point_off() {
+        let mut controller = TimeProportional::new(config(0.1));
+        controller.update(Duration::ZERO, Temperature::new(20.0), &set_point(21.0));
+
+        let off = SetPoint {
+            power: PowerState::Off,
+            temperature: Temperature::new(21.0),
+            reason: SetPointReason::PriceAboveCap,
+        };
+        let power = controller.update(Duration::from_secs(60), Temperature::new(20.0), &off);
+        assert_eq!(power, PowerState::Off);
+        assert_eq!(controller.cycle(), None);
+    }
+
+    #[test]
+    fn test_proportional_only_steady_state_error() {
+        let mut controller = TimeProportional::new(config(0.0));
+        let (mean, _) = simulate(&mut controller, 18.0, 48);
+
+        // Holding 21 degrees needs 55% on, so proportional control
+        // settles below the set point
+        let target = Temperature::new(20.0);
+        assert!(
+            mean.approx_eq(target, TemperatureDelta::new(0.5)),
+            "{}",
+            mean
+        );
+    }
+
+    #[test]
+    fn test_integral_removes_steady_state_error() {
+        let mut controller = TimeProportional::new(config(0.1));
+        let (mean, switches) = simulate(&mut controller, 18.0, 48);
+
+        let target = Temperature::new(21.0);
+        assert!(
+            mean.approx_eq(target, TemperatureDelta::new(0.15)),
+            "{}",
+            mean
+        );
+        // At most one on and one off transition per cycle
+        assert!(switches <= 2 * 48 * 2, "{:?}", switches);
+    }
+
+    #[test]
+    fn test_anti_windup() {
+        let mut controller = TimeProportional::new(config(0.1));
+
+        // A long cold start saturates the output for around ten hours;
+        // without anti-windup the accumulated error overshoots
+        let (mean, _) = simulate(&mut controller, 5.0, 36);
+        let target = Temperature::new(21.0);
+        assert!(
+            mean.approx_eq(target, TemperatureDelta::new(0.15)),
+            "{}",
+            mean
+        );
+        assert!(controller.pid.integral() <= 1.0);
+    }
+
+    #[test]
+    fn test_validate() {
+        let relay = RelayConfig {
+            minimum_on_time: Duration::from_secs(600),
+            minimum_off_time: Duration::from_secs(300),
+        };
+        let config = PwmConfig {
+            minimum_pulse: Duration::from_secs(600),
+            ..config(0.1)
+        };
+        config.validate(&relay).unwrap();
+
+        let config = PwmConfig {
+            minimum_pulse: Duration::from_secs(300),
+            ..config
+        };
+        assert!(config.validate(&relay).is_err());
+
+        let config = PwmConfig {
+            cycle_period: Duration::from_secs(900),
+            minimum_pulse: Duration::from_secs(600),
+            ..config
+        };
+        assert!(config.validate(&relay).is_err());
+    }
+
+    #[test]
+    fn test_relay_protection_in_loop() {
+        let relay = RelayConfig {
+            minimum_on_time: Duration::from_secs(600),
+            minimum_off_time: Duration::from_secs(600),
+        };
+        let config = PwmConfig {
+            minimum_pulse: Duration::from_secs(600),
+            ..config(0.1)
+        };
+        config.validate(&relay).unwrap();
+
+        let model = model();
+        let target = set_point(21.0);
+        let mut controller = TimeProportional::new(config);
+        let mut protection = RelayProtection::new(relay, PowerState::Off);
+        let mut temperature = Temperature::new(18.0);
+        let mut total = TemperatureDelta::ZERO;
+        let steps = 48 * 60;
+        for step in 0..steps {
+            let now = STEP * step as u32;
+            let requested = controller.update(now, temperature, &target);
+            let applied = protection.request(now, requested, SwitchPriority::Normal);
+            // The relay follows every planned transition
+            assert_eq!(applied, requested, "{:?}", now);
+
+            temperature = model.predict(temperature, applied, STEP.as_secs_f32() / 3600.0);
+            if step >= steps - 6 * 60 {
+                total = total + (temperature - target.temperature);
+            }
+        }
+
+        let mean = target.temperature + total / (6 * 60) as f32;
+        assert!(
+            mean.approx_eq(target.temperature, TemperatureDelta::new(0.15)),
+            "{}",
+            mean
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use crate::config::CoreConfig;
+use crate::state::{ElectricityPrice, Temperature};
+
+// Rank of a price within the known price window, from 0.0 when no
+// known price is cheaper to 1.0 when every other price is cheaper.
+//
+// Returns None when the window is too small to rank against.
+pub fn price_rank(price: ElectricityPrice, window: &[ElectricityPrice]) -> Option<f32> {
+    if window.len() < 2 {
+        return None;
+    }
+
+    let cheaper = window.iter().filter(|known| **known < price).count();
+    let rank = cheaper as f32 / (window.len() - 1) as f32;
+
+    Some(rank.clamp(0.0, 1.0))
+}
+
+// Map a price rank onto the configured temperature range; the cheapest
+// hours select the maximum temperature
+pub fn select_temperature_for_rank(config: &CoreConfig, rank: f32) -> Temperature {
+    let temperature_range = config.maximum_temperature - config.minimum_temperature;
+    let temperature_delta = temperature_range * (1.0 - rank);
+
+    config.minimum_temperature + temperature_delta
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::config::PriceMode;
+    use crate::fixtures::price_window;
+
+    fn settings() -> CoreConfig {
+        CoreConfig {
+            price_mode: PriceMode::Relative { hard_ceiling: true },
+            ..crate::fixtures::settings()
+        }
+    }
+
+    #[test]
+    fn test_price_rank_extremes() {
+        let window = price_window();
+        assert_eq!(window.len(), 48);
+
+        let rank = price_rank(ElectricityPrice::new(2.0), &window);
+        assert_eq!(rank, Some(0.0));
+
+        let rank = price_rank(ElectricityPrice::new(147.28), &window);
+        assert_eq!(rank, Some(1.0));
+
+        // Prices outside the window are clamped
+        let rank = price_rank(ElectricityPrice::new(-5.0), &window);
+        assert_eq!(rank, Some(0.0));
+        let rank = price_rank(ElectricityPrice::new(500.0), &window);
+        assert_eq!(rank, Some(1.0));
+    }
+
+    #[test]
+    fn test_price_rank_morning_peak() {
+        let window = price_window();
+
+        // 2024-10-25 06:00:00; 36 of the other 47 hours are cheaper
+        let rank = price_rank(ElectricityPrice::new(40.17), &window).unwrap();
+        assert!(rank > 0.765, "{:?}", rank);
+        assert!(rank < 0.767, "{:?}", rank);
+
+        // 2024-10-25 04:00:00 is the median
+        let rank = price_rank(ElectricityPrice::new(22.06), &window).unwrap();
+        assert!(rank > 0.48, "{:?}", rank);
+        assert!(rank < 0.5, "{:?}", rank);
+    }
+
+    #[test]
+    fn test_price_rank_small_window() {
+        let price = ElectricityPrice::new(2.0);
+        assert_eq!(price_rank(price, &[]), None);
+        assert_eq!(price_rank(price, &[price]), None);
+    }
+
+    #[test]
+    fn test_select_temperature_for_rank() {
+        let settings = settings();
+
+        let temperature = select_temperature_for_rank(&settings, 0.0);
+        assert_eq!(temperature, settings.maximum_temperature);
+
+        let temperature = select_temperature_for_rank(&settings, 1.0);
+        assert_eq!(temperature, settings.minimum_temperature);
+
+        let temperature = select_temperature_for_rank(&settings, 0.5);
+        assert_eq!(temperature, Temperature::new(18.5));
+    }
+}
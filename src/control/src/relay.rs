@@ -0,0 +1,201 @@
+use core::time::Duration;
+
+use crate::state::PowerState;
+
+#[derive(Copy, Clone, Debug)]
+pub struct RelayConfig {
+    // Heating must stay on for at least this long once switched on
+    pub minimum_on_time: Duration,
+
+    // Heating must stay off for at least this long once switched off
+    pub minimum_off_time: Duration,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SwitchPriority {
+    // Respect the minimum on and off times
+    Normal,
+    // Switch immediately, e.g. when the floor is over temperature
+    Safety,
+}
+
+// Protects the heating relay and elements from short cycling.
+//
+// Timestamps are monotonic durations from an arbitrary starting point
+// (e.g. time since boot) supplied by the caller.
+#[derive(Debug, Clone, Copy)]
+pub struct RelayProtection {
+    config: RelayConfig,
+    state: PowerState,
+    last_change: Option<Duration>,
+}
+
+impl RelayProtection {
+    pub fn new(config: RelayConfig, state: PowerState) -> Self {
+        Self {
+            config,
+            state,
+            last_change: None,
+        }
+    }
+
+    pub fn state(&self) -> PowerState {
+        self.state
+    }
+
+    // Time remaining until the output is allowed to change state
+    pub fn remaining_hold(&self, now: Duration) -> Duration {
+        let Some(last_change) = self.last_change else {
+            return Duration::ZERO;
+        };
+        let minimum = match self.state {
+            PowerState::On => self.config.minimum_on_time,
+            PowerState::Off => self.config.minimum_off_time,
+        };
+        let elapsed = now.saturating_sub(last_change);
+        minimum.saturating_sub(elapsed)
+    }
+
+    // Request a new output state, returning the state the relay should
+    // actually be in.
+    pub fn request(
+        &mut self,
+        now: Duration,
+        requested: PowerState,
+        priority: SwitchPriority,
+    ) -> PowerState {
+        if requested == self.state {
+            return self.state;
+        }
+
+        if priority == SwitchPriority::Normal && !self.remaining_hold(now).is_zero() {
+            return self.state;
+        }
+
+        self.state = requested;
+        self.last_change = Some(now);
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> RelayConfig {
+        RelayConfig {
+            minimum_on_time: Duration::from_secs(600),
+            minimum_off_time: Duration::from_secs(900),
+        }
+    }
+
+    #[test]
+    fn test_first_switch_is_immediate() {
+        let mut relay = RelayProtection::new(config(), PowerState::Off);
+        let state = relay.request(
+            Duration::from_secs(5),
+            PowerState::On,
+            SwitchPriority::Normal,
+        );
+        assert_eq!(state, PowerState::On);
+    }
+
+    #[test]
+    fn test_minimum_on_time() {
+        let mut relay = RelayProtection::new(config(), PowerState::Off);
+        relay.request(
+            Duration::from_secs(0),
+            PowerState::On,
+            SwitchPriority::Normal,
+        );
+
+        let state = relay.request(
+            Duration::from_secs(300),
+            PowerState::Off,
+            SwitchPriority::Normal,
+        );
+        assert_eq!(state, PowerState::On);
+        assert_eq!(
+            relay.remaining_hold(Duration::from_secs(300)),
+            Duration::from_secs(300)
+        );
+
+        let state = relay.request(
+            Duration::from_secs(600),
+            PowerState::Off,
+            SwitchPriority::Normal,
+        );
+        assert_eq!(state, PowerState::Off);
+    }
+
+    #[test]
+    fn test_minimum_off_time() {
+        let mut relay = RelayProtection::new(config(), PowerState::On);
+        relay.request(
+            Duration::from_secs(0),
+            PowerState::Off,
+            SwitchPriority::Normal,
+        );
+
+        let state = relay.request(
+            Duration::from_secs(899),
+            PowerState::On,
+            SwitchPriority::Normal,
+        );
+        assert_eq!(state, PowerState::Off);
+
+        let state = relay.request(
+            Duration::from_secs(900),
+            PowerState::On,
+            SwitchPriority::Normal,
+        );
+        assert_eq!(state, PowerState::On);
+    }
+
+    #[test]
+    fn test_repeated_request_does_not_extend_hold() {
+        let mut relay = RelayProtection::new(config(), PowerState::Off);
+        relay.request(
+            Duration::from_secs(0),
+            PowerState::On,
+            SwitchPriority::Normal,
+        );
+        relay.request(
+            Duration::from_secs(300),
+            PowerState::On,
+            SwitchPriority::Normal,
+        );
+
+        let state = relay.request(
+            Duration::from_secs(600),
+            PowerState::Off,
+            SwitchPriority::Normal,
+        );
+        assert_eq!(state, PowerState::Off);
+    }
+
+    #[test]
+    fn test_safety_overrides_minimum_on_time() {
+        let mut relay = RelayProtection::new(config(), PowerState::Off);
+        relay.request(
+            Duration::from_secs(0),
+            PowerState::On,
+            SwitchPriority::Normal,
+        );
+
+        let state = relay.request(
+            Duration::from_secs(10),
+            PowerState::Off,
+            SwitchPriority::Safety,
+        );
+        assert_eq!(state, PowerState::Off);
+
+        // The safety switch starts a new minimum off period
+        let state = relay.request(
+            Duration::from_secs(20),
+            PowerState::On,
+            SwitchPriority::Normal,
+        );
+        assert_eq!(state, PowerState::Off);
+    }
+}
@@ -0,0 +1,395 @@
+use anyhow::{anyhow, bail, Result};
+use core::str::FromStr;
+use time::{PrimitiveDateTime, Time, Weekday};
+
+use crate::config::CoreConfig;
+use crate::state::Temperature;
+
+// Maximum number of time blocks in each day's profile
+pub const MAX_SCHEDULE_BLOCKS: usize = 8;
+
+// Temperature limits overridden while a schedule block is active
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TemperatureRange {
+    pub minimum_temperature: Temperature,
+    pub fallback_minimum_temperature: Temperature,
+    pub maximum_temperature: Temperature,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ScheduleBlock {
+    // Local time of day at which the block becomes active
+    pub start: Time,
+    pub range: TemperatureRange,
+}
+
+// Time blocks over one day; each block is active from its start until
+// the start of the next block, and the last block continues past
+// midnight until the first block of the following day.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DayProfile {
+    blocks: [Option<ScheduleBlock>; MAX_SCHEDULE_BLOCKS],
+    len: usize,
+}
+
+impl DayProfile {
+    pub fn new(blocks: &[ScheduleBlock]) -> Result<DayProfile> {
+        if blocks.len() > MAX_SCHEDULE_BLOCKS {
+            bail!("At most {} schedule blocks per day", MAX_SCHEDULE_BLOCKS);
+        }
+
+        let mut profile = DayProfile::default();
+        for block in blocks {
+            profile.blocks[profile.len] = Some(*block);
+            profile.len += 1;
+        }
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let mut previous: Option<Time> = None;
+        for block in self.blocks() {
+            if previous.is_some_and(|previous| block.start <= previous) {
+                bail!("Schedule blocks must be in order of start time");
+            }
+            if block.range.minimum_temperature > block.range.maximum_temperature {
+                bail!("Schedule block minimum temperature is above its maximum");
+            }
+            previous = Some(block.start);
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &ScheduleBlock> {
+        self.blocks[..self.len].iter().flatten()
+    }
+
+    // Latest block starting at or before `time` on this day
+    fn block_at(&self, time: Time) -> Option<&ScheduleBlock> {
+        self.blocks().filter(|block| block.start <= time).last()
+    }
+
+    fn last_block(&self) -> Option<&ScheduleBlock> {
+        self.blocks().last()
+    }
+}
+
+impl Default for DayProfile {
+    fn default() -> Self {
+        DayProfile {
+            blocks: [None; MAX_SCHEDULE_BLOCKS],
+            len: 0,
+        }
+    }
+}
+
+// Parse a profile of blocks separated by ';', each block written as
+// "HH:MM minimum fallback_minimum maximum", e.g.
+//
+//   "06:00 19.0 20.0 23.0; 22:00 16.0 17.0 19.0"
+impl FromStr for DayProfile {
+    type Err = anyhow::Error;
+
+    fn from_str(profile: &str) -> Result<Self> {
+        let mut parsed = DayProfile::default();
+        for block in profile.split(';').map(str::trim).filter(|b| !b.is_empty()) {
+            if parsed.len == MAX_SCHEDULE_BLOCKS {
+                bail!("At most {} schedule blocks per day", MAX_SCHEDULE_BLOCKS);
+            }
+            parsed.blocks[parsed.len] = Some(parse_block(block)?);
+            parsed.len += 1;
+        }
+        parsed.validate()?;
+        Ok(parsed)
+    }
+}
+
+fn parse_block(block: &str) -> Result<ScheduleBlock> {
+    let mut fields = block.split_whitespace();
+    let mut next_field = || {
+        fields
+            .next()
+            .ok_or_else(|| anyhow!("Incomplete schedule block {:?}", block))
+    };
+
+    let start = parse_time(next_field()?)?;
+    let mut temperatures = [Temperature::new(0.0); 3];
+    for temperature in temperatures.iter_mut() {
+        let field = next_field()?;
+        let value = f32::from_str(field)
+            .map_err(|_| anyhow!("Invalid temperature {:?} in schedule", field))?;
+        *temperature = Temperature::new(value);
+    }
+    if fields.next().is_some() {
+        bail!("Unexpected fields in schedule block {:?}", block);
+    }
+
+    let [minimum_temperature, fallback_minimum_temperature, maximum_temperature] = temperatures;
+    Ok(ScheduleBlock {
+        start,
+        range: TemperatureRange {
+            minimum_temperature,
+            fallback_minimum_temperature,
+            maximum_temperature,
+        },
+    })
+}
+
+fn parse_time(time: &str) -> Result<Time> {
+    let invalid = || anyhow!("Invalid time {:?} in schedule", time);
+    let (hour, minute) = time.split_once(':').ok_or_else(invalid)?;
+    let hour = u8::from_str(hour).map_err(|_| invalid())?;
+    let minute = u8::from_str(minute).map_err(|_| invalid())?;
+    Time::from_hms(hour, minute, 0).map_err(|_| invalid())
+}
+
+// Weekly comfort schedule with separate weekday and weekend profiles.
+// The active block's temperature range replaces the configured range,
+// and price based control operates within it.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct ComfortSchedule {
+    pub weekday: DayProfile,
+    pub weekend: DayProfile,
+}
+
+impl ComfortSchedule {
+    pub fn is_empty(&self) -> bool {
+        self.weekday.is_empty() && self.weekend.is_empty()
+    }
+
+    fn profile(&self, weekday: Weekday) -> &DayProfile {
+        match weekday {
+            Weekday::Saturday | Weekday::Sunday => &self.weekend,
+            _ => &self.weekday,
+        }
+    }
+
+    // Temperature range active at a local time, if any block applies
+    pub fn range_at(&self, time: PrimitiveDateTime) -> Option<TemperatureRange> {
+        let mut weekday = time.weekday();
+        if let Some(block) = self.profile(weekday).block_at(time.time()) {
+            return Some(block.range);
+        }
+
+        // Before the first block of the day, the last block of the most
+        // recent day with any blocks continues
+        for _ in 0..7 {
+            weekday = weekday.previous();
+            if let Some(block) = self.profile(weekday).last_block() {
+                return Some(block.range);
+            }
+        }
+        None
+    }
+
+    // Configuration with the temperature range active at a local time
+    pub fn apply(&self, config: &CoreConfig, time: PrimitiveDateTime) -> CoreConfig {
+        match self.range_at(time) {
+            Some(range) => with_range(config, range),
+            None => *config,
+        }
+    }
+
+    // Check the configuration is consistent with each block's range
+    pub fn validate(&self, config: &CoreConfig) -> Result<()> {
+        for block in self.weekday.blocks().chain(self.weekend.blocks()) {
+            with_range(config, block.range).validate().map_err(|err| {
+                anyhow!(
+                    "Schedule block at {:02}:{:02}: {}",
+                    block.start.hour(),
+                    block.start.minute(),
+                    err
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+fn with_range(config: &CoreConfig, range: TemperatureRange) -> CoreConfig {
+    CoreConfig {
+        minimum_temperature: range.minimum_temperature,
+        fallback_minimum_temperature: range.fallback_minimum_temperature,
+        maximum_temperature: range.maximum_temperature,
+        ..*config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::fixtures::settings;
+    use crate::state::ElectricityPrice;
+    use crate::{select_temperature, SetPoint, SetPointReason};
+    use std::format;
+    use time::macros::{datetime, time};
+
+    fn range(minimum: f32, fallback_minimum: f32, maximum: f32) -> TemperatureRange {
+        TemperatureRange {
+            minimum_temperature: Temperature::new(minimum),
+            fallback_minimum_temperature: Temperature::new(fallback_minimum),
+            maximum_temperature: Temperature::new(maximum),
+        }
+    }
+
+    fn schedule() -> ComfortSchedule {
+        ComfortSchedule {
+            weekday: "06:00 19 20 23; 08:30 17 18 21; 16:00 19 20 23; 22:00 16 17 19"
+                .parse()
+                .unwrap(),
+            weekend: "08:00 19 20 23; 23:00 16 17 19".parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        let profile: DayProfile = "06:00 19.0 20.0 23.5;22:30 16 17 19".parse().unwrap();
+        assert_eq!(profile.len(), 2);
+
+        let blocks: [ScheduleBlock; 2] =
+            core::array::from_fn(|i| *profile.blocks().nth(i).unwrap());
+        assert_eq!(blocks[0].start, time!(06:00));
+        assert_eq!(blocks[0].range, range(19.0, 20.0, 23.5));
+        assert_eq!(blocks[1].start, time!(22:30));
+        assert_eq!(blocks[1].range, range(16.0, 17.0, 19.0));
+
+        let profile: DayProfile = "".parse().unwrap();
+        assert!(profile.is_empty());
+    }
+
+    #[test]
+    fn test_parse_invalid_profile() {
+        let invalid = [
+            "06:00 19 20",
+            "06:00 19 20 23 24",
+            "6 19 20 23",
+            "25:00 19 20 23",
+            "06:00 warm 20 23",
+            "08:00 19 20 23; 06:00 16 17 19",
+            "06:00 19 20 23; 06:00 16 17 19",
+            "06:00 23 20 19",
+            "00:00 1 1 1; 01:00 1 1 1; 02:00 1 1 1; 03:00 1 1 1; 04:00 1 1 1; \
+             05:00 1 1 1; 06:00 1 1 1; 07:00 1 1 1; 08:00 1 1 1",
+        ];
+        for profile in invalid {
+            assert!(profile.parse::<DayProfile>().is_err(), "{:?}", profile);
+        }
+    }
+
+    #[test]
+    fn test_weekday_blocks() {
+        let schedule = schedule();
+
+        // 2024-10-23 is a Wednesday
+        let active = schedule.range_at(datetime!(2024-10-23 07:00));
+        assert_eq!(active, Some(range(19.0, 20.0, 23.0)));
+
+        let active = schedule.range_at(datetime!(2024-10-23 08:30));
+        assert_eq!(active, Some(range(17.0, 18.0, 21.0)));
+
+        let active = schedule.range_at(datetime!(2024-10-23 23:59));
+        assert_eq!(active, Some(range(16.0, 17.0, 19.0)));
+
+        // The night setback continues past midnight
+        let active = schedule.range_at(datetime!(2024-10-24 03:00));
+        assert_eq!(active, Some(range(16.0, 17.0, 19.0)));
+    }
+
+    #[test]
+    fn test_weekend_blocks() {
+        let schedule = schedule();
+
+        // Saturday morning follows Friday's night setback
+        let active = schedule.range_at(datetime!(2024-10-26 07:00));
+        assert_eq!(active, Some(range(16.0, 17.0, 19.0)));
+
+        let active = schedule.range_at(datetime!(2024-10-26 09:00));
+        assert_eq!(active, Some(range(19.0, 20.0, 23.0)));
+
+        // Monday morning follows Sunday's last block
+        let active = schedule.range_at(datetime!(2024-10-28 05:59));
+        assert_eq!(active, Some(range(16.0, 17.0, 19.0)));
+    }
+
+    #[test]
+    fn test_weekday_only_schedule() {
+        let schedule = ComfortSchedule {
+            weekday: "06:00 19 20 23; 22:00 16 17 19".parse().unwrap(),
+            ..ComfortSchedule::default()
+        };
+
+        // Friday's last block continues through the weekend
+        let active = schedule.range_at(datetime!(2024-10-27 12:00));
+        assert_eq!(active, Some(range(16.0, 17.0, 19.0)));
+
+        let empty = ComfortSchedule::default();
+        assert!(empty.is_empty());
+        assert_eq!(empty.range_at(datetime!(2024-10-27 12:00)), None);
+    }
+
+    #[test]
+    fn test_price_control_within_block() {
+        let settings = settings();
+        let schedule = schedule();
+
+        let unchanged = ComfortSchedule::default().apply(&settings, datetime!(2024-10-23 12:00));
+        assert_eq!(unchanged.maximum_temperature, settings.maximum_temperature);
+
+        // Daytime setback on a weekday
+        let config = schedule.apply(&settings, datetime!(2024-10-23 12:00));
+        assert_eq!(config.minimum_temperature, Temperature::new(17.0));
+        assert_eq!(config.fallback_minimum_temperature, Temperature::new(18.0));
+        assert_eq!(config.maximum_temperature, Temperature::new(21.0));
+        assert_eq!(config.turbo_temperature, settings.turbo_temperature);
+
+        let temperature = select_temperature(&config, ElectricityPrice::new(15.0));
+        assert_eq!(temperature, Temperature::new(19.0));
+
+        let set_point = SetPoint::from_current_state(
+            &config,
+            Temperature::new(16.5),
+            Some(ElectricityPrice::new(15.0)),
+            crate::PowerState::Off,
+        );
+        let expected = SetPoint {
+            power: crate::PowerState::On,
+            temperature: config.turbo_temperature,
+            reason: SetPointReason::BelowMinimumTurbo,
+        };
+        assert_eq!(set_point, expected);
+    }
+
+    #[test]
+    fn test_validate_against_config() {
+        schedule().validate(&settings()).unwrap();
+
+        // A block whose maximum is above the turbo temperature
+        let invalid = ComfortSchedule {
+            weekday: "06:00 19 20 23; 22:00 16 17 31".parse().unwrap(),
+            ..schedule()
+        };
+        let err = invalid.validate(&settings()).unwrap_err();
+        assert_eq!(
+            format!("{}", err),
+            "Schedule block at 22:00: Turbo temperature 30 °C is below the maximum temperature 31 °C"
+        );
+
+        // A fallback minimum outside the block's range
+        let invalid = ComfortSchedule {
+            weekend: "08:00 19 24 23".parse().unwrap(),
+            ..schedule()
+        };
+        assert!(invalid.validate(&settings()).is_err());
+    }
+}
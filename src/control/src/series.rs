@@ -0,0 +1,467 @@
+use alloc::vec::Vec;
+use anyhow::{bail, Result};
+use core::fmt;
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::ser::{SerializeStruct, Serializer};
+use serde::{Deserialize, Serialize};
+use time::{Duration, PrimitiveDateTime};
+
+use crate::state::{ElectricityPrice, PriceSlot};
+
+// Enough for a day of 15 minute prices, including the extra hour on
+// the day daylight saving time ends; a bound on what a fetched
+// document can make the device allocate
+pub const MAX_SERIES_SLOTS: usize = 100;
+
+// Electricity prices for consecutive intervals of the same length, e.g.
+// a day of hourly or 15 minute day-ahead prices
+#[derive(Debug, PartialEq, Clone)]
+pub struct PriceSeries {
+    // Validity of this data, in UTC
+    valid_from: PrimitiveDateTime,
+    valid_until: PrimitiveDateTime,
+    interval: Duration,
+    // Sorted by the start of each interval
+    slots: Vec<PriceSlot>,
+}
+
+impl PriceSeries {
+    // An empty series; the interval must divide an hour evenly
+    pub fn new(
+        valid_from: PrimitiveDateTime,
+        valid_until: PrimitiveDateTime,
+        interval: Duration,
+    ) -> Result<PriceSeries> {
+        let minutes = interval.whole_minutes();
+        if interval != Duration::minutes(minutes) || minutes <= 0 || 60 % minutes != 0 {
+            bail!("Price interval {} must divide an hour evenly", interval);
+        }
+        if valid_until <= valid_from {
+            bail!(
+                "Prices valid until {} before they are valid from {}",
+                valid_until,
+                valid_from
+            );
+        }
+        Ok(PriceSeries {
+            valid_from,
+            valid_until,
+            interval,
+            slots: Vec::new(),
+        })
+    }
+
+    pub fn valid_from(&self) -> PrimitiveDateTime {
+        self.valid_from
+    }
+
+    pub fn valid_until(&self) -> PrimitiveDateTime {
+        self.valid_until
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn is_valid_at(&self, time: PrimitiveDateTime) -> bool {
+        self.valid_from <= time && time < self.valid_until
+    }
+
+    pub fn slots(&self) -> impl Iterator<Item = &PriceSlot> {
+        self.slots.iter()
+    }
+
+    // Add the price for the interval starting at `start`, which must be
+    // a whole number of intervals after the start of the validity
+    pub fn insert(&mut self, start: PrimitiveDateTime, price: ElectricityPrice) -> Result<()> {
+        if !self.is_valid_at(start) {
+            bail!(
+                "Price at {} is outside {} to {}",
+                start,
+                self.valid_from,
+                self.valid_until
+            );
+        }
+        let offset = start - self.valid_from;
+        if offset.whole_seconds() % self.interval.whole_seconds() != 0
+            || offset.subsec_nanoseconds() != 0
+        {
+            bail!("Price at {} is not on a {} interval", start, self.interval);
+        }
+        if self.slots.len() == MAX_SERIES_SLOTS {
+            bail!("Too many prices; at most {} fit", MAX_SERIES_SLOTS);
+        }
+
+        match self.slots.binary_search_by_key(&start, |slot| slot.start) {
+            Ok(_) => bail!("Duplicate price at {}", start),
+            Err(index) => self.slots.insert(index, PriceSlot { start, price }),
+        }
+        Ok(())
+    }
+
+    // The slot whose interval contains `time`
+    pub fn slot_at(&self, time: PrimitiveDateTime) -> Option<&PriceSlot> {
+        self.slots()
+            .find(|slot| slot.start <= time && time < slot.start + self.interval)
+    }
+
+    pub fn price_at(&self, time: PrimitiveDateTime) -> Option<ElectricityPrice> {
+        self.slot_at(time).map(|slot| slot.price)
+    }
+
+    // The mean price of each hour, for planning in whole hours. Hours
+    // with missing intervals average the known ones.
+    pub fn hourly(&self) -> PriceSeries {
+        let mut hourly = PriceSeries {
+            interval: Duration::HOUR,
+            slots: Vec::new(),
+            ..*self
+        };
+
+        let mut slots = self.slots().peekable();
+        while let Some(first) = slots.next() {
+            let hours = (first.start - self.valid_from).whole_hours();
+            let hour = self.valid_from + Duration::hours(hours);
+            let mut total = f32::from(first.price);
+            let mut count = 1.0;
+            while let Some(slot) = slots.next_if(|slot| slot.start < hour + Duration::HOUR) {
+                total += f32::from(slot.price);
+                count += 1.0;
+            }
+            hourly.slots.push(PriceSlot {
+                start: hour,
+                price: ElectricityPrice::new(total / count),
+            });
+        }
+        hourly
+    }
+
+    // The same intervals with each price replaced
+    pub fn map_prices(&self, f: impl Fn(&PriceSlot) -> ElectricityPrice) -> PriceSeries {
+        let mut mapped = self.clone();
+        for slot in mapped.slots.iter_mut() {
+            slot.price = f(slot);
+        }
+        mapped
+    }
+}
+
+// Interval length assumed for feeds that have a single price
+const DEFAULT_INTERVAL: Duration = Duration::HOUR;
+
+// The shortest gap between consecutive prices
+fn infer_interval(prices: &PriceMap) -> Duration {
+    prices
+        .0
+        .windows(2)
+        .map(|pair| pair[1].start - pair[0].start)
+        .min()
+        .unwrap_or(DEFAULT_INTERVAL)
+}
+
+// Prices keyed by the start of each interval, sorted by the start
+struct PriceMap(Vec<PriceSlot>);
+
+impl<'de> Deserialize<'de> for PriceMap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PriceMapVisitor;
+
+        impl<'de> Visitor<'de> for PriceMapVisitor {
+            type Value = PriceMap;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a map of interval start times to prices")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<PriceMap, A::Error> {
+                let mut slots = Vec::new();
+                while let Some((start, price)) = map.next_entry()? {
+                    if slots.len() == MAX_SERIES_SLOTS {
+                        return Err(de::Error::invalid_length(
+                            slots.len() + 1,
+                            &"at most 100 prices",
+                        ));
+                    }
+                    slots.push(PriceSlot { start, price });
+                }
+                slots.sort_unstable_by_key(|slot: &PriceSlot| slot.start);
+                Ok(PriceMap(slots))
+            }
+        }
+
+        deserializer.deserialize_map(PriceMapVisitor)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "snake_case")]
+enum Field {
+    ValidFrom,
+    ValidUntil,
+    IntervalMinutes,
+    // Hourly feeds name the prices `hourly_price`
+    #[serde(alias = "hourly_price")]
+    Prices,
+    #[serde(other)]
+    Ignored,
+}
+
+// Accepts both the hourly feed
+//
+//   {"valid_from": .., "valid_until": .., "hourly_price": {..}}
+//
+// and feeds at other intervals, which may state the interval length.
+// Without `interval_minutes`, the interval is the shortest gap between
+// prices.
+impl<'de> Deserialize<'de> for PriceSeries {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PriceSeriesVisitor;
+
+        impl<'de> Visitor<'de> for PriceSeriesVisitor {
+            type Value = PriceSeries;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("electricity prices with their validity")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<PriceSeries, A::Error> {
+                let mut valid_from = None;
+                let mut valid_until = None;
+                let mut interval_minutes: Option<i64> = None;
+                let mut prices: Option<PriceMap> = None;
+                while let Some(field) = map.next_key()? {
+                    match field {
+                        Field::ValidFrom => valid_from = Some(map.next_value()?),
+                        Field::ValidUntil => valid_until = Some(map.next_value()?),
+                        Field::IntervalMinutes => interval_minutes = Some(map.next_value()?),
+                        Field::Prices => prices = Some(map.next_value()?),
+                        Field::Ignored => {
+                            map.next_value::<de::IgnoredAny>()?;
+                        }
+                    }
+                }
+                let valid_from =
+                    valid_from.ok_or_else(|| de::Error::missing_field("valid_from"))?;
+                let valid_until =
+                    valid_until.ok_or_else(|| de::Error::missing_field("valid_until"))?;
+                let prices = prices.ok_or_else(|| de::Error::missing_field("prices"))?;
+
+                let interval = match interval_minutes {
+                    // Range checked before `Duration::minutes`, which
+                    // panics on overflow
+                    Some(minutes) if !(1..=60).contains(&minutes) => {
+                        return Err(de::Error::custom(format_args!(
+                            "Price interval of {} minutes must divide an hour evenly",
+                            minutes
+                        )));
+                    }
+                    Some(minutes) => Duration::minutes(minutes),
+                    None => infer_interval(&prices),
+                };
+                let mut series = PriceSeries::new(valid_from, valid_until, interval)
+                    .map_err(de::Error::custom)?;
+                for slot in &prices.0 {
+                    series
+                        .insert(slot.start, slot.price)
+                        .map_err(de::Error::custom)?;
+                }
+                Ok(series)
+            }
+        }
+
+        const FIELDS: &[&str] = &["valid_from", "valid_until", "interval_minutes", "prices"];
+        deserializer.deserialize_struct("PriceSeries", FIELDS, PriceSeriesVisitor)
+    }
+}
+
+impl Serialize for PriceSeries {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        struct Prices<'a>(&'a PriceSeries);
+
+        impl Serialize for Prices<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_map(self.0.slots().map(|slot| (slot.start, slot.price)))
+            }
+        }
+
+        let mut state = serializer.serialize_struct("PriceSeries", 4)?;
+        state.serialize_field("valid_from", &self.valid_from)?;
+        state.serialize_field("valid_until", &self.valid_until)?;
+        state.serialize_field("interval_minutes", &self.interval.whole_minutes())?;
+        state.serialize_field("prices", &Prices(self))?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::PriceDelta;
+    use alloc::format;
+    use serde::Deserialize;
+    use time::macros::datetime;
+
+    #[derive(Deserialize)]
+    struct SingleDayPrices {
+        today: PriceSeries,
+    }
+
+    // electricity-price/singleday.json
+    fn hourly() -> PriceSeries {
+        let json = include_str!("../../../electricity-price/singleday.json");
+        let prices: SingleDayPrices = serde_json::from_str(json).unwrap();
+        prices.today
+    }
+
+    // electricity-price/quarterhourly.json; the same day as
+    // singleday.json with four prices around each hourly price
+    fn quarter_hourly() -> PriceSeries {
+        let json = include_str!("../../../electricity-price/quarterhourly.json");
+        let prices: SingleDayPrices = serde_json::from_str(json).unwrap();
+        prices.today
+    }
+
+    #[test]
+    fn test_hourly_feed() {
+        let series = hourly();
+        assert_eq!(series.interval(), Duration::HOUR);
+        assert_eq!(series.len(), 24);
+        assert_eq!(series.valid_from(), datetime!(2024-10-24 22:00));
+        assert_eq!(series.valid_until(), datetime!(2024-10-25 22:00));
+
+        // Lookup by the hour containing the time
+        let morning = Some(ElectricityPrice::new(40.17));
+        assert_eq!(series.price_at(datetime!(2024-10-25 06:00)), morning);
+        assert_eq!(series.price_at(datetime!(2024-10-25 06:45)), morning);
+        assert_eq!(series.price_at(datetime!(2024-10-25 06:59:59)), morning);
+        assert_eq!(
+            series.price_at(datetime!(2024-10-25 07:00)),
+            Some(ElectricityPrice::new(34.51))
+        );
+
+        assert_eq!(series.price_at(datetime!(2024-10-24 21:59)), None);
+        assert_eq!(series.price_at(datetime!(2024-10-25 22:00)), None);
+    }
+
+    #[test]
+    fn test_quarter_hourly_feed() {
+        let series = quarter_hourly();
+        assert_eq!(series.interval(), Duration::minutes(15));
+        assert_eq!(series.len(), 96);
+
+        assert_eq!(
+            series.price_at(datetime!(2024-10-25 06:00)),
+            Some(ElectricityPrice::new(39.87))
+        );
+        assert_eq!(
+            series.price_at(datetime!(2024-10-25 06:20)),
+            Some(ElectricityPrice::new(40.07))
+        );
+        let slot = series.slot_at(datetime!(2024-10-25 06:59)).unwrap();
+        assert_eq!(slot.start, datetime!(2024-10-25 06:45));
+        assert_eq!(slot.price, ElectricityPrice::new(40.47));
+    }
+
+    #[test]
+    fn test_interval_inferred() {
+        let json = include_str!("../../../electricity-price/quarterhourly.json")
+            .replace("\"interval_minutes\": 15,", "");
+        let prices: SingleDayPrices = serde_json::from_str(&json).unwrap();
+        assert_eq!(prices.today, quarter_hourly());
+    }
+
+    #[test]
+    fn test_hourly_mean() {
+        let hourly = hourly();
+        let resampled = quarter_hourly().hourly();
+        assert_eq!(resampled.interval(), Duration::HOUR);
+        assert_eq!(resampled.len(), hourly.len());
+
+        for (expected, slot) in hourly.slots().zip(resampled.slots()) {
+            assert_eq!(slot.start, expected.start);
+            assert!(
+                slot.price.approx_eq(expected.price, PriceDelta::new(0.001)),
+                "{:?} {:?}",
+                slot,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut series = PriceSeries::new(
+            datetime!(2024-10-24 22:00),
+            datetime!(2024-10-25 22:00),
+            Duration::minutes(15),
+        )
+        .unwrap();
+        let price = ElectricityPrice::new(2.0);
+
+        // Kept in order of the start
+        series.insert(datetime!(2024-10-25 01:15), price).unwrap();
+        series.insert(datetime!(2024-10-24 22:00), price).unwrap();
+        series.insert(datetime!(2024-10-25 00:30), price).unwrap();
+        let starts: [PrimitiveDateTime; 3] =
+            core::array::from_fn(|index| series.slots().nth(index).unwrap().start);
+        assert_eq!(
+            starts,
+            [
+                datetime!(2024-10-24 22:00),
+                datetime!(2024-10-25 00:30),
+                datetime!(2024-10-25 01:15)
+            ]
+        );
+
+        assert!(series.insert(datetime!(2024-10-25 00:30), price).is_err());
+        assert!(series.insert(datetime!(2024-10-25 00:20), price).is_err());
+        assert!(series.insert(datetime!(2024-10-24 21:45), price).is_err());
+        assert!(series.insert(datetime!(2024-10-25 22:00), price).is_err());
+        assert_eq!(series.len(), 3);
+    }
+
+    #[test]
+    fn test_invalid_interval() {
+        let from = datetime!(2024-10-24 22:00);
+        let until = datetime!(2024-10-25 22:00);
+        assert!(PriceSeries::new(from, until, Duration::minutes(30)).is_ok());
+        assert!(PriceSeries::new(from, until, Duration::minutes(25)).is_err());
+        assert!(PriceSeries::new(from, until, Duration::ZERO).is_err());
+        assert!(PriceSeries::new(from, until, Duration::HOUR * 2).is_err());
+        assert!(PriceSeries::new(until, from, Duration::HOUR).is_err());
+
+        // Prices off the stated interval are rejected
+        let json = include_str!("../../../electricity-price/quarterhourly.json")
+            .replace("\"interval_minutes\": 15", "\"interval_minutes\": 60");
+        assert!(serde_json::from_str::<SingleDayPrices>(&json).is_err());
+
+        // Intervals out of range are rejected rather than overflowing
+        for minutes in ["0", "-15", "9223372036854775807", "-9223372036854775808"] {
+            let json = include_str!("../../../electricity-price/quarterhourly.json").replace(
+                "\"interval_minutes\": 15",
+                &format!("\"interval_minutes\": {}", minutes),
+            );
+            assert!(
+                serde_json::from_str::<SingleDayPrices>(&json).is_err(),
+                "{}",
+                minutes
+            );
+        }
+    }
+
+    #[test]
+    fn test_serialize() {
+        let series = quarter_hourly();
+        let json = serde_json::to_string(&series).unwrap();
+        let parsed: PriceSeries = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, series);
+    }
+}
@@ -1,40 +1,398 @@
+use core::fmt;
+use core::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
 use serde::{Deserialize, Serialize};
+use time::PrimitiveDateTime;
+
+use crate::value::{self, Value};
 
 // Temperature in degrees celcius
 #[derive(Debug, PartialEq, PartialOrd, Clone, Copy, Deserialize, Serialize)]
-pub struct Temperature(f32);
+pub struct Temperature(#[serde(with = "value")] Value);
 
 impl Temperature {
     pub fn new(value: f32) -> Self {
-        Self(value)
+        Self(value::from_f32(value))
+    }
+
+    pub fn is_finite(self) -> bool {
+        value::is_finite(self.0)
+    }
+
+    pub fn clamp(self, minimum: Temperature, maximum: Temperature) -> Temperature {
+        Self(value::clamp(self.0, minimum.0, maximum.0))
+    }
+
+    // Equal to within `tolerance`
+    pub fn approx_eq(self, other: Temperature, tolerance: TemperatureDelta) -> bool {
+        (self - other).abs() <= tolerance
     }
 }
 
 impl From<Temperature> for f32 {
     fn from(value: Temperature) -> f32 {
-        value.0
+        value::to_f32(value.0)
+    }
+}
+
+impl fmt::Display for Temperature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&value::to_f32(self.0), f)?;
+        f.write_str(" °C")
+    }
+}
+
+// Difference between two temperatures in degrees celcius
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct TemperatureDelta(#[serde(with = "value")] Value);
+
+impl TemperatureDelta {
+    pub const ZERO: TemperatureDelta = TemperatureDelta(value::ZERO);
+
+    pub fn new(value: f32) -> Self {
+        Self(value::from_f32(value))
+    }
+
+    pub fn is_finite(self) -> bool {
+        value::is_finite(self.0)
+    }
+
+    pub fn abs(self) -> TemperatureDelta {
+        Self(value::abs(self.0))
+    }
+}
+
+impl From<TemperatureDelta> for f32 {
+    fn from(value: TemperatureDelta) -> f32 {
+        value::to_f32(value.0)
+    }
+}
+
+impl fmt::Display for TemperatureDelta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&value::to_f32(self.0), f)?;
+        f.write_str(" K")
+    }
+}
+
+impl Sub for Temperature {
+    type Output = TemperatureDelta;
+
+    fn sub(self, other: Temperature) -> TemperatureDelta {
+        TemperatureDelta(value::sub(self.0, other.0))
+    }
+}
+
+impl Add<TemperatureDelta> for Temperature {
+    type Output = Temperature;
+
+    fn add(self, delta: TemperatureDelta) -> Temperature {
+        Temperature(value::add(self.0, delta.0))
+    }
+}
+
+impl Sub<TemperatureDelta> for Temperature {
+    type Output = Temperature;
+
+    fn sub(self, delta: TemperatureDelta) -> Temperature {
+        Temperature(value::sub(self.0, delta.0))
+    }
+}
+
+impl AddAssign<TemperatureDelta> for Temperature {
+    fn add_assign(&mut self, delta: TemperatureDelta) {
+        self.0 = value::add(self.0, delta.0);
+    }
+}
+
+impl SubAssign<TemperatureDelta> for Temperature {
+    fn sub_assign(&mut self, delta: TemperatureDelta) {
+        self.0 = value::sub(self.0, delta.0);
+    }
+}
+
+impl Add for TemperatureDelta {
+    type Output = TemperatureDelta;
+
+    fn add(self, other: TemperatureDelta) -> TemperatureDelta {
+        TemperatureDelta(value::add(self.0, other.0))
+    }
+}
+
+impl Sub for TemperatureDelta {
+    type Output = TemperatureDelta;
+
+    fn sub(self, other: TemperatureDelta) -> TemperatureDelta {
+        TemperatureDelta(value::sub(self.0, other.0))
+    }
+}
+
+impl Neg for TemperatureDelta {
+    type Output = TemperatureDelta;
+
+    fn neg(self) -> TemperatureDelta {
+        TemperatureDelta(value::neg(self.0))
     }
 }
 
-// Electricity price in cents per kWh
+impl Mul<f32> for TemperatureDelta {
+    type Output = TemperatureDelta;
+
+    fn mul(self, factor: f32) -> TemperatureDelta {
+        TemperatureDelta(value::mul(self.0, factor))
+    }
+}
+
+impl Div<f32> for TemperatureDelta {
+    type Output = TemperatureDelta;
+
+    fn div(self, divisor: f32) -> TemperatureDelta {
+        TemperatureDelta(value::div(self.0, divisor))
+    }
+}
+
+// Ratio between two temperature differences
+impl Div for TemperatureDelta {
+    type Output = f32;
+
+    fn div(self, other: TemperatureDelta) -> f32 {
+        value::ratio(self.0, other.0)
+    }
+}
+
+// Electricity price in cents per kWh, or the hundredths of another
+// currency; see `unit` for conversion from other units
 // Can be negative .. that happens, sometimes
 #[derive(Debug, PartialEq, PartialOrd, Clone, Copy, Deserialize, Serialize)]
-pub struct ElectricityPrice(f32);
+pub struct ElectricityPrice(#[serde(with = "value")] Value);
 
 impl ElectricityPrice {
     pub fn new(value: f32) -> Self {
-        Self(value)
+        Self(value::from_f32(value))
+    }
+
+    pub fn is_finite(self) -> bool {
+        value::is_finite(self.0)
+    }
+
+    pub fn clamp(self, minimum: ElectricityPrice, maximum: ElectricityPrice) -> ElectricityPrice {
+        Self(value::clamp(self.0, minimum.0, maximum.0))
+    }
+
+    // Equal to within `tolerance`
+    pub fn approx_eq(self, other: ElectricityPrice, tolerance: PriceDelta) -> bool {
+        (self - other).abs() <= tolerance
+    }
+
+    // The price as a difference from a price of zero
+    pub fn as_delta(self) -> PriceDelta {
+        PriceDelta(self.0)
+    }
+
+    // The price that is `delta` above a price of zero
+    pub fn from_delta(delta: PriceDelta) -> ElectricityPrice {
+        Self(delta.0)
     }
 }
 
 impl From<ElectricityPrice> for f32 {
     fn from(value: ElectricityPrice) -> f32 {
-        value.0
+        value::to_f32(value.0)
+    }
+}
+
+// Without a unit, as the currency is that of the configured provider
+impl fmt::Display for ElectricityPrice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&value::to_f32(self.0), f)
+    }
+}
+
+// Difference between two electricity prices in cents per kWh
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct PriceDelta(#[serde(with = "value")] Value);
+
+impl PriceDelta {
+    pub const ZERO: PriceDelta = PriceDelta(value::ZERO);
+
+    pub fn new(value: f32) -> Self {
+        Self(value::from_f32(value))
+    }
+
+    pub fn abs(self) -> PriceDelta {
+        Self(value::abs(self.0))
+    }
+}
+
+impl From<PriceDelta> for f32 {
+    fn from(value: PriceDelta) -> f32 {
+        value::to_f32(value.0)
+    }
+}
+
+impl fmt::Display for PriceDelta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&value::to_f32(self.0), f)
+    }
+}
+
+impl Sub for ElectricityPrice {
+    type Output = PriceDelta;
+
+    fn sub(self, other: ElectricityPrice) -> PriceDelta {
+        PriceDelta(value::sub(self.0, other.0))
+    }
+}
+
+impl Add<PriceDelta> for ElectricityPrice {
+    type Output = ElectricityPrice;
+
+    fn add(self, delta: PriceDelta) -> ElectricityPrice {
+        ElectricityPrice(value::add(self.0, delta.0))
+    }
+}
+
+impl Sub<PriceDelta> for ElectricityPrice {
+    type Output = ElectricityPrice;
+
+    fn sub(self, delta: PriceDelta) -> ElectricityPrice {
+        ElectricityPrice(value::sub(self.0, delta.0))
+    }
+}
+
+impl Add for PriceDelta {
+    type Output = PriceDelta;
+
+    fn add(self, other: PriceDelta) -> PriceDelta {
+        PriceDelta(value::add(self.0, other.0))
     }
 }
 
+impl Sub for PriceDelta {
+    type Output = PriceDelta;
+
+    fn sub(self, other: PriceDelta) -> PriceDelta {
+        PriceDelta(value::sub(self.0, other.0))
+    }
+}
+
+impl Neg for PriceDelta {
+    type Output = PriceDelta;
+
+    fn neg(self) -> PriceDelta {
+        PriceDelta(value::neg(self.0))
+    }
+}
+
+impl Mul<f32> for PriceDelta {
+    type Output = PriceDelta;
+
+    fn mul(self, factor: f32) -> PriceDelta {
+        PriceDelta(value::mul(self.0, factor))
+    }
+}
+
+// Ratio between two price differences
+impl Div for PriceDelta {
+    type Output = f32;
+
+    fn div(self, other: PriceDelta) -> f32 {
+        value::ratio(self.0, other.0)
+    }
+}
+
+// Electricity price for the period starting at `start`, in UTC
 #[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PriceSlot {
+    pub start: PrimitiveDateTime,
+    pub price: ElectricityPrice,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize, Serialize)]
 pub enum PowerState {
     On,
     Off,
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::format;
+
+    #[test]
+    fn test_temperature_arithmetic() {
+        let minimum = Temperature::new(15.0);
+        let maximum = Temperature::new(22.0);
+
+        let range = maximum - minimum;
+        assert_eq!(range, TemperatureDelta::new(7.0));
+        assert_eq!(minimum + range, maximum);
+        assert_eq!(maximum - range, minimum);
+        assert_eq!(minimum + range * 0.5, Temperature::new(18.5));
+        assert_eq!(range / 2.0, TemperatureDelta::new(3.5));
+        assert_eq!(TemperatureDelta::new(3.5) / range, 0.5);
+        assert_eq!(-range, TemperatureDelta::new(-7.0));
+        assert_eq!((minimum - maximum).abs(), range);
+        assert_eq!(range + range - range, range);
+
+        let mut temperature = minimum;
+        temperature += TemperatureDelta::new(1.5);
+        assert_eq!(temperature, Temperature::new(16.5));
+        temperature -= TemperatureDelta::new(0.5);
+        assert_eq!(temperature, Temperature::new(16.0));
+    }
+
+    #[test]
+    fn test_temperature_clamp_and_tolerance() {
+        let minimum = Temperature::new(15.0);
+        let maximum = Temperature::new(22.0);
+        assert_eq!(Temperature::new(30.0).clamp(minimum, maximum), maximum);
+        assert_eq!(Temperature::new(10.0).clamp(minimum, maximum), minimum);
+        assert_eq!(
+            Temperature::new(20.0).clamp(minimum, maximum),
+            Temperature::new(20.0)
+        );
+
+        let tolerance = TemperatureDelta::new(0.1);
+        assert!(Temperature::new(20.05).approx_eq(Temperature::new(20.0), tolerance));
+        assert!(Temperature::new(19.95).approx_eq(Temperature::new(20.0), tolerance));
+        assert!(!Temperature::new(20.2).approx_eq(Temperature::new(20.0), tolerance));
+        assert!(!Temperature::new(f32::NAN).approx_eq(Temperature::new(20.0), tolerance));
+    }
+
+    #[test]
+    fn test_price_arithmetic() {
+        let cheap = ElectricityPrice::new(2.0);
+        let expensive = ElectricityPrice::new(30.0);
+
+        let difference = expensive - cheap;
+        assert_eq!(difference, PriceDelta::new(28.0));
+        assert_eq!(cheap + difference, expensive);
+        assert_eq!(expensive - difference, cheap);
+        assert_eq!(difference * 0.5 / difference, 0.5);
+        assert_eq!(-difference, cheap - expensive);
+        assert_eq!((cheap - expensive).abs(), difference);
+
+        let negative = ElectricityPrice::new(-1.0);
+        assert_eq!(negative.clamp(cheap, expensive), cheap);
+        assert!(cheap.approx_eq(ElectricityPrice::new(2.04), PriceDelta::new(0.05)));
+
+        assert_eq!(expensive.as_delta(), PriceDelta::new(30.0));
+        assert_eq!(
+            ElectricityPrice::from_delta(difference),
+            ElectricityPrice::new(28.0)
+        );
+    }
+
+    #[test]
+    fn test_display_with_units() {
+        assert_eq!(format!("{}", Temperature::new(21.5)), "21.5 °C");
+        assert_eq!(format!("{:.1}", Temperature::new(21.0)), "21.0 °C");
+        assert_eq!(format!("{}", TemperatureDelta::new(-0.5)), "-0.5 K");
+
+        // Prices are in hundredths of whichever currency is configured
+        assert_eq!(format!("{:.2}", ElectricityPrice::new(22.06)), "22.06");
+        assert_eq!(format!("{}", PriceDelta::new(1.5)), "1.5");
+    }
+}
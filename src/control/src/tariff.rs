@@ -0,0 +1,410 @@
+use anyhow::{anyhow, bail, Result};
+use core::str::FromStr;
+use time::{PrimitiveDateTime, Time, Weekday};
+
+use crate::clock::TimeZone;
+use crate::electricity_price::MultiDayElectricityPrice;
+use crate::state::{ElectricityPrice, PriceDelta};
+use crate::unit::PriceUnit;
+
+// Maximum number of network fee windows across the week
+pub const MAX_FEE_WINDOWS: usize = 16;
+
+// Network fee from a local time of day on some days of the week
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FeeWindow {
+    // Bit n set for n days from Monday
+    days: u8,
+    pub start: Time,
+    pub fee: PriceDelta,
+}
+
+impl FeeWindow {
+    pub fn new(days: &[Weekday], start: Time, fee: PriceDelta) -> FeeWindow {
+        let days = days.iter().fold(0, |mask, day| mask | day_bit(*day));
+        FeeWindow { days, start, fee }
+    }
+
+    pub fn applies_on(&self, day: Weekday) -> bool {
+        self.days & day_bit(day) != 0
+    }
+}
+
+fn day_bit(day: Weekday) -> u8 {
+    1 << day.number_days_from_monday()
+}
+
+// Time of use network fees. As with the comfort schedule, each window
+// applies from its start until the next window on the same day, and
+// the last window of a day continues past midnight until the first
+// window of a following day.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct NetworkFees {
+    windows: [Option<FeeWindow>; MAX_FEE_WINDOWS],
+    len: usize,
+}
+
+impl NetworkFees {
+    pub fn new(windows: &[FeeWindow]) -> Result<NetworkFees> {
+        let mut fees = NetworkFees::default();
+        for window in windows {
+            fees.push(*window)?;
+        }
+        Ok(fees)
+    }
+
+    fn push(&mut self, window: FeeWindow) -> Result<()> {
+        if self.len == MAX_FEE_WINDOWS {
+            bail!("At most {} network fee windows", MAX_FEE_WINDOWS);
+        }
+        if window.days == 0 {
+            bail!("Network fee window at {} applies on no days", window.start);
+        }
+        if self
+            .windows()
+            .any(|other| other.start == window.start && other.days & window.days != 0)
+        {
+            bail!("Overlapping network fee windows at {}", window.start);
+        }
+        self.windows[self.len] = Some(window);
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn windows(&self) -> impl Iterator<Item = &FeeWindow> {
+        self.windows[..self.len].iter().flatten()
+    }
+
+    // Latest window on `day` starting at or before `time`
+    fn window_at(&self, day: Weekday, time: Time) -> Option<&FeeWindow> {
+        self.windows()
+            .filter(|window| window.applies_on(day) && window.start <= time)
+            .max_by_key(|window| window.start)
+    }
+
+    fn last_window(&self, day: Weekday) -> Option<&FeeWindow> {
+        self.windows()
+            .filter(|window| window.applies_on(day))
+            .max_by_key(|window| window.start)
+    }
+
+    // Fees read as plain numbers in `unit`, in the canonical unit
+    pub fn in_unit(&self, unit: &PriceUnit) -> NetworkFees {
+        let mut converted = *self;
+        for window in converted.windows[..self.len].iter_mut().flatten() {
+            window.fee = unit.delta(f32::from(window.fee));
+        }
+        converted
+    }
+
+    // Network fee at a local time; none when no window applies
+    pub fn fee_at(&self, time: PrimitiveDateTime) -> PriceDelta {
+        let mut day = time.weekday();
+        if let Some(window) = self.window_at(day, time.time()) {
+            return window.fee;
+        }
+
+        // Before the first window of the day, the last window of the
+        // most recent day with any windows continues
+        for _ in 0..7 {
+            day = day.previous();
+            if let Some(window) = self.last_window(day) {
+                return window.fee;
+            }
+        }
+        PriceDelta::ZERO
+    }
+}
+
+impl Default for NetworkFees {
+    fn default() -> Self {
+        NetworkFees {
+            windows: [None; MAX_FEE_WINDOWS],
+            len: 0,
+        }
+    }
+}
+
+// Parse windows separated by ';', each written as "days HH:MM fee",
+// where days are "mon" to "sun", a range such as "mon-fri", a list such
+// as "sat,sun", or "*" for every day, e.g.
+//
+//   "mon-fri 07:00 4.5; mon-fri 22:00 2.5; sat,sun 00:00 2.5"
+impl FromStr for NetworkFees {
+    type Err = anyhow::Error;
+
+    fn from_str(fees: &str) -> Result<Self> {
+        let mut parsed = NetworkFees::default();
+        for window in fees.split(';').map(str::trim).filter(|w| !w.is_empty()) {
+            parsed.push(parse_window(window)?)?;
+        }
+        Ok(parsed)
+    }
+}
+
+fn parse_window(window: &str) -> Result<FeeWindow> {
+    let mut fields = window.split_whitespace();
+    let mut next_field = || {
+        fields
+            .next()
+            .ok_or_else(|| anyhow!("Incomplete network fee window {:?}", window))
+    };
+
+    let days = parse_days(next_field()?)?;
+    let start = parse_time(next_field()?)?;
+    let field = next_field()?;
+    let fee = f32::from_str(field).map_err(|_| anyhow!("Invalid network fee {:?}", field))?;
+    if fields.next().is_some() {
+        bail!("Unexpected fields in network fee window {:?}", window);
+    }
+
+    Ok(FeeWindow {
+        days,
+        start,
+        fee: PriceDelta::new(fee),
+    })
+}
+
+fn parse_days(days: &str) -> Result<u8> {
+    if days == "*" {
+        return Ok(0x7f);
+    }
+    let mut mask = 0;
+    for part in days.split(',') {
+        mask |= match part.split_once('-') {
+            Some((first, last)) => {
+                let (mut day, last) = (parse_day(first)?, parse_day(last)?);
+                let mut range = day_bit(day);
+                while day != last {
+                    day = day.next();
+                    range |= day_bit(day);
+                }
+                range
+            }
+            None => day_bit(parse_day(part)?),
+        };
+    }
+    Ok(mask)
+}
+
+const DAYS: [(&str, Weekday); 7] = [
+    ("mon", Weekday::Monday),
+    ("tue", Weekday::Tuesday),
+    ("wed", Weekday::Wednesday),
+    ("thu", Weekday::Thursday),
+    ("fri", Weekday::Friday),
+    ("sat", Weekday::Saturday),
+    ("sun", Weekday::Sunday),
+];
+
+fn parse_day(day: &str) -> Result<Weekday> {
+    DAYS.iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(day))
+        .map(|(_, weekday)| *weekday)
+        .ok_or_else(|| anyhow!("Invalid day {:?} in network fees", day))
+}
+
+fn parse_time(time: &str) -> Result<Time> {
+    let invalid = || anyhow!("Invalid time {:?} in network fees", time);
+    let (hour, minute) = time.split_once(':').ok_or_else(invalid)?;
+    let hour = u8::from_str(hour).map_err(|_| invalid())?;
+    let minute = u8::from_str(minute).map_err(|_| invalid())?;
+    Time::from_hms(hour, minute, 0).map_err(|_| invalid())
+}
+
+// What electricity actually costs, from the spot price:
+//
+//   (spot * (1 + markup) + fixed fee + network fee) * (1 + VAT)
+//
+// Planning and price based control use the effective price, so that
+// cheap hours are those that are cheap after time of use network fees.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Tariff {
+    // Supplier margin, as a percentage of the spot price
+    pub markup_percent: f32,
+    // Added to every price, e.g. energy tax and supplier fees
+    pub fixed_fee: PriceDelta,
+    pub network_fees: NetworkFees,
+    pub vat_percent: f32,
+}
+
+impl Tariff {
+    // Effective price of `spot` at a local time
+    pub fn effective(&self, spot: ElectricityPrice, local: PrimitiveDateTime) -> ElectricityPrice {
+        let spot = spot.as_delta();
+        let before_vat = spot * (1.0 + self.markup_percent / 100.0)
+            + self.fixed_fee
+            + self.network_fees.fee_at(local);
+        ElectricityPrice::from_delta(before_vat * (1.0 + self.vat_percent / 100.0))
+    }
+
+    // Effective prices of all known prices, keyed in UTC as the spot
+    // prices are
+    pub fn effective_prices(
+        &self,
+        prices: &MultiDayElectricityPrice,
+        timezone: &TimeZone,
+    ) -> MultiDayElectricityPrice {
+        prices.map_prices(|slot| self.effective(slot.price, timezone.to_local(slot.start)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::{datetime, time};
+
+    use crate::fixtures::MULTI_DAY;
+
+    // Finnish energy tax, day and night network fees except on Sundays,
+    // and VAT
+    fn tariff() -> Tariff {
+        Tariff {
+            markup_percent: 5.0,
+            fixed_fee: PriceDelta::new(2.79),
+            network_fees: "mon-sat 07:00 4.0; mon-sat 22:00 2.0; sun 00:00 2.0"
+                .parse()
+                .unwrap(),
+            vat_percent: 25.5,
+        }
+    }
+
+    fn assert_price(price: ElectricityPrice, expected: f32) {
+        assert!(
+            price.approx_eq(ElectricityPrice::new(expected), PriceDelta::new(0.001)),
+            "{:?} {:?}",
+            price,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_effective_price() {
+        let tariff = tariff();
+        let spot = ElectricityPrice::new(10.0);
+        // Friday daytime; (10 * 1.05 + 2.79 + 4.0) * 1.255
+        assert_price(
+            tariff.effective(spot, datetime!(2024-10-25 12:00)),
+            21.69895,
+        );
+        // Friday night; (10 * 1.05 + 2.79 + 2.0) * 1.255
+        assert_price(
+            tariff.effective(spot, datetime!(2024-10-25 23:00)),
+            19.18895,
+        );
+        // Saturday before 07:00 continues Friday night
+        assert_price(
+            tariff.effective(spot, datetime!(2024-10-26 03:00)),
+            19.18895,
+        );
+        // Sundays are at the night fee all day
+        assert_price(
+            tariff.effective(spot, datetime!(2024-10-27 12:00)),
+            19.18895,
+        );
+        // Monday before 07:00 continues Sunday
+        assert_price(
+            tariff.effective(spot, datetime!(2024-10-28 03:00)),
+            19.18895,
+        );
+        // Negative spot prices are marked up too; (-2.1 + 6.79) * 1.255
+        assert_price(
+            tariff.effective(ElectricityPrice::new(-2.0), datetime!(2024-10-25 12:00)),
+            5.88595,
+        );
+
+        // No tariff leaves the spot price
+        let now = datetime!(2024-10-25 12:00);
+        assert_eq!(Tariff::default().effective(spot, now), spot);
+    }
+
+    #[test]
+    fn test_network_fees_change_cheapest_hour() {
+        let tariff = tariff();
+        let day = tariff.effective(ElectricityPrice::new(5.0), datetime!(2024-10-25 21:00));
+        let night = tariff.effective(ElectricityPrice::new(6.0), datetime!(2024-10-25 22:00));
+        // (5.25 + 6.79) * 1.255 and (6.3 + 4.79) * 1.255
+        assert_price(day, 15.1102);
+        assert_price(night, 13.91795);
+        assert!(night < day);
+    }
+
+    #[test]
+    fn test_effective_prices() {
+        let timezone: TimeZone = "EET-2EEST,M3.5.0/3,M10.5.0/4".parse().unwrap();
+        let now = datetime!(2024-10-25 12:00);
+        let (prices, _) = MultiDayElectricityPrice::parse(MULTI_DAY, now).unwrap();
+
+        let effective = tariff().effective_prices(&prices, &timezone);
+        assert_eq!(effective.known_prices().count(), 48);
+        // 01:00 on Friday, local time; (4 * 1.05 + 2.79 + 2.0) * 1.255
+        assert_price(
+            effective.price_at(datetime!(2024-10-24 22:00)).unwrap(),
+            11.28245,
+        );
+        // 07:00; (22.06 * 1.05 + 2.79 + 4.0) * 1.255
+        assert_price(
+            effective.price_at(datetime!(2024-10-25 04:00)).unwrap(),
+            37.591015,
+        );
+        // The raw prices are unchanged
+        assert_eq!(
+            prices.price_at(datetime!(2024-10-25 04:00)),
+            Some(ElectricityPrice::new(22.06))
+        );
+    }
+
+    #[test]
+    fn test_parse_network_fees() {
+        let fees: NetworkFees = "* 00:00 1.0; fri-mon 17:00 3.0".parse().unwrap();
+        assert_eq!(fees.windows().count(), 2);
+        let window = fees.windows().nth(1).unwrap();
+        assert_eq!(window.start, time!(17:00));
+        for day in [Weekday::Friday, Weekday::Sunday, Weekday::Monday] {
+            assert!(window.applies_on(day));
+        }
+        assert!(!window.applies_on(Weekday::Wednesday));
+        assert_eq!(
+            fees.fee_at(datetime!(2024-10-27 18:00)),
+            PriceDelta::new(3.0)
+        );
+        assert_eq!(
+            fees.fee_at(datetime!(2024-10-23 18:00)),
+            PriceDelta::new(1.0)
+        );
+
+        let built = NetworkFees::new(&[FeeWindow::new(
+            &[Weekday::Saturday, Weekday::Sunday],
+            time!(00:00),
+            PriceDelta::new(2.0),
+        )])
+        .unwrap();
+        assert_eq!(built, "sat,sun 00:00 2.0".parse().unwrap());
+
+        // EUR/kWh into c/kWh
+        let unit: PriceUnit = "EUR/kWh".parse().unwrap();
+        let converted = fees.in_unit(&unit);
+        let error = converted.fee_at(datetime!(2024-10-27 18:00)) - PriceDelta::new(300.0);
+        assert!(error.abs() <= PriceDelta::new(0.001));
+
+        assert!("".parse::<NetworkFees>().unwrap().is_empty());
+        assert_eq!(
+            NetworkFees::default().fee_at(datetime!(2024-10-25 12:00)),
+            PriceDelta::ZERO
+        );
+        for invalid in [
+            "mon 07:00",
+            "mon 07:00 4.0 extra",
+            "monday 07:00 4.0",
+            "mon 7 4.0",
+            "mon 07:00 cheap",
+            "mon-fri 07:00 4.0; fri 07:00 2.0",
+        ] {
+            assert!(invalid.parse::<NetworkFees>().is_err(), "{:?}", invalid);
+        }
+    }
+}
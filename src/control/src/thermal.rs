@@ -0,0 +1,242 @@
+#[allow(unused_imports)]
+use micromath::F32Ext; // Required for f32::exp and f32::ln
+use serde::{Deserialize, Serialize};
+
+use crate::state::{PowerState, Temperature, TemperatureDelta};
+
+// Lumped RC thermal model of the floor:
+//
+//   dT/dt = heating_rate * P - cooling_rate * (T - ambient_temperature)
+//
+// where P is 1 while heating is on and 0 otherwise.
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize, Serialize)]
+pub struct ThermalModel {
+    // Temperature rise in degrees per hour from heating, before losses
+    pub heating_rate: f32,
+
+    // Fraction of the difference to ambient lost per hour (1 / RC)
+    pub cooling_rate: f32,
+
+    // Temperature the floor settles to with heating off
+    pub ambient_temperature: Temperature,
+}
+
+impl ThermalModel {
+    // Predicted temperature after `hours` with constant heating power
+    pub fn predict(&self, temperature: Temperature, power: PowerState, hours: f32) -> Temperature {
+        let heating = match power {
+            PowerState::On => self.heating_rate,
+            PowerState::Off => 0.0,
+        };
+        if self.cooling_rate <= 0.0 {
+            return temperature + TemperatureDelta::new(heating * hours);
+        }
+
+        //   T(t) = T_eq + (T_0 - T_eq) * e^(-cooling_rate * t)
+        let equilibrium =
+            self.ambient_temperature + TemperatureDelta::new(heating / self.cooling_rate);
+        let decay = (-self.cooling_rate * hours).exp();
+
+        equilibrium + (temperature - equilibrium) * decay
+    }
+
+    // Hours of heating needed to raise the temperature to `target`, or
+    // None if heating cannot reach it
+    pub fn hours_to_reach(&self, temperature: Temperature, target: Temperature) -> Option<f32> {
+        if target <= temperature {
+            return Some(0.0);
+        }
+        if self.heating_rate <= 0.0 {
+            return None;
+        }
+
+        if self.cooling_rate <= 0.0 {
+            return Some(f32::from(target - temperature) / self.heating_rate);
+        }
+
+        let equilibrium =
+            self.ambient_temperature + TemperatureDelta::new(self.heating_rate / self.cooling_rate);
+        if target >= equilibrium {
+            return None;
+        }
+
+        //   t = -ln((T - T_eq) / (T_0 - T_eq)) / cooling_rate
+        Some(-((target - equilibrium) / (temperature - equilibrium)).ln() / self.cooling_rate)
+    }
+
+    // Fraction of the time heating must be on to hold `temperature`,
+    // from 0.0 to 1.0
+    pub fn holding_fraction(&self, temperature: Temperature) -> f32 {
+        if self.heating_rate <= 0.0 {
+            return 1.0;
+        }
+        //   heating_rate * P = cooling_rate * (T - ambient_temperature)
+        let losses = self.cooling_rate * f32::from(temperature - self.ambient_temperature);
+        (losses / self.heating_rate).clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> ThermalModel {
+        ThermalModel {
+            heating_rate: 2.0,
+            cooling_rate: 0.1,
+            ambient_temperature: Temperature::new(10.0),
+        }
+    }
+
+    #[test]
+    fn test_predict_cooling() {
+        let model = model();
+        let start = Temperature::new(20.0);
+
+        let temperature = model.predict(start, PowerState::Off, 0.0);
+        assert_eq!(temperature, start);
+
+        // e^-1 of the difference remains after one time constant
+        let temperature = model.predict(start, PowerState::Off, 10.0);
+        assert!(
+            temperature.approx_eq(Temperature::new(13.68), TemperatureDelta::new(0.01)),
+            "{}",
+            temperature
+        );
+
+        // Settles at ambient
+        let temperature = model.predict(start, PowerState::Off, 200.0);
+        assert!(
+            temperature.approx_eq(model.ambient_temperature, TemperatureDelta::new(0.01)),
+            "{}",
+            temperature
+        );
+    }
+
+    #[test]
+    fn test_predict_heating() {
+        let model = model();
+        let start = Temperature::new(20.0);
+
+        // Slightly less than the heating rate due to losses
+        let temperature = model.predict(start, PowerState::On, 1.0);
+        assert!(
+            temperature.approx_eq(Temperature::new(20.95), TemperatureDelta::new(0.05)),
+            "{}",
+            temperature
+        );
+
+        // Settles where heating balances losses
+        let temperature = model.predict(start, PowerState::On, 200.0);
+        assert!(
+            temperature.approx_eq(Temperature::new(30.0), TemperatureDelta::new(0.01)),
+            "{}",
+            temperature
+        );
+    }
+
+    #[test]
+    fn test_hours_to_reach() {
+        let model = model();
+        let start = Temperature::new(20.0);
+
+        assert_eq!(
+            model.hours_to_reach(start, Temperature::new(18.0)),
+            Some(0.0)
+        );
+
+        // Halfway to equilibrium takes ln(2) time constants
+        let hours = model.hours_to_reach(start, Temperature::new(25.0)).unwrap();
+        assert!(hours > 6.93, "{:?}", hours);
+        assert!(hours < 6.94, "{:?}", hours);
+
+        let predicted = model.predict(start, PowerState::On, hours);
+        let target = Temperature::new(25.0);
+        assert!(
+            predicted.approx_eq(target, TemperatureDelta::new(0.01)),
+            "{}",
+            predicted
+        );
+
+        // Above the equilibrium temperature
+        assert_eq!(model.hours_to_reach(start, Temperature::new(31.0)), None);
+
+        let model = ThermalModel {
+            cooling_rate: 0.0,
+            ..model
+        };
+        assert_eq!(
+            model.hours_to_reach(start, Temperature::new(23.0)),
+            Some(1.5)
+        );
+    }
+
+    #[test]
+    fn test_holding_fraction() {
+        let model = model();
+        assert_eq!(model.holding_fraction(Temperature::new(8.0)), 0.0);
+        assert_eq!(model.holding_fraction(Temperature::new(15.0)), 0.25);
+        assert_eq!(model.holding_fraction(Temperature::new(40.0)), 1.0);
+
+        // Holding the temperature neither warms nor cools the floor
+        let start = Temperature::new(15.0);
+        let temperature = model.predict(start, PowerState::On, 0.25);
+        let temperature = model.predict(temperature, PowerState::Off, 0.75);
+        assert!(
+            temperature.approx_eq(start, TemperatureDelta::new(0.05)),
+            "{}",
+            temperature
+        );
+    }
+
+    #[test]
+    fn test_predict_without_losses() {
+        let model = ThermalModel {
+            cooling_rate: 0.0,
+            ..model()
+        };
+        let start = Temperature::new(20.0);
+
+        let temperature = model.predict(start, PowerState::On, 1.5);
+        assert_eq!(temperature, Temperature::new(23.0));
+
+        let temperature = model.predict(start, PowerState::Off, 1.5);
+        assert_eq!(temperature, start);
+    }
+
+    #[test]
+    fn test_simulation_agrees_with_f32() {
+        let model = model();
+        let step = 10.0 / 60.0;
+
+        // Two days of heating for an hour in every three, against the
+        // model in plain f32
+        let mut temperature = Temperature::new(18.0);
+        let mut reference: f32 = 18.0;
+        for index in 0..48 * 6 {
+            let power = if index % 18 < 6 {
+                PowerState::On
+            } else {
+                PowerState::Off
+            };
+            temperature = model.predict(temperature, power, step);
+
+            let heating = match power {
+                PowerState::On => model.heating_rate,
+                PowerState::Off => 0.0,
+            };
+            let equilibrium = 10.0 + heating / model.cooling_rate;
+            reference =
+                equilibrium + (reference - equilibrium) * (-model.cooling_rate * step).exp();
+
+            let expected = Temperature::new(reference);
+            assert!(
+                temperature.approx_eq(expected, TemperatureDelta::new(0.01)),
+                "{}: {} {}",
+                index,
+                temperature,
+                expected
+            );
+        }
+    }
+}
@@ -1,45 +1,145 @@
+use anyhow::{bail, Result};
 #[allow(unused_imports)]
 use micromath::F32Ext; // Required for f32::ln
+use serde::{Deserialize, Serialize};
 
-static KELVIN_OFFSET: f32 = 273.15;
+pub(crate) static KELVIN_OFFSET: f32 = 273.15;
 
+// Fraction of the supply voltage within which the thermistor reads as
+// an open circuit (near the supply) or a short circuit (near ground)
+const FAULT_MARGIN: f32 = 0.005;
+
+// Rated range of common NTC thermistors
+const MINIMUM_TEMPERATURE: f32 = -40.0;
+const MAXIMUM_TEMPERATURE: f32 = 125.0;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SensorFault {
+    // Thermistor disconnected; the sample is close to the supply voltage
+    OpenCircuit,
+    // Thermistor shorted; the sample is close to ground
+    ShortCircuit,
+    // Invalid supply or sample voltage, or a temperature outside of the
+    // thermistor's rated range
+    OutOfRange,
+    // The sensor could not be read at all
+    Unreadable,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize, Serialize)]
+pub enum ThermistorModel {
+    // Resistance `r1` at temperature `t1` (Celsius), with the beta
+    // value from the datasheet
+    Beta { beta: f32, r1: f32, t1: f32 },
+    // Steinhart-Hart coefficients, with T in Kelvin:
+    //
+    //   1 / T = a + b * ln(R) + c * ln(R)^3
+    SteinhartHart { a: f32, b: f32, c: f32 },
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub struct ThermistorProperties {
-    beta: f32,
-    r1: f32,
-    t1: f32,
+    // Fixed resistor in series with the thermistor, between the
+    // thermistor and the supply
+    pub reference_resistance: f32,
+    pub model: ThermistorModel,
 }
 
-static THERMISTOR_PROPERTIES: ThermistorProperties = ThermistorProperties {
-    beta: 3750.0,
-    r1: 12000.0,
-    t1: 25.0,
-};
+impl ThermistorModel {
+    // Check the parameters describe an NTC thermistor, i.e. that the
+    // resistance falls as the temperature rises
+    pub fn validate(&self) -> Result<()> {
+        match *self {
+            ThermistorModel::Beta { beta, r1, t1 } => {
+                if !beta.is_finite() || beta <= 0.0 {
+                    bail!("Thermistor beta {} must be positive", beta);
+                }
+                if !r1.is_finite() || r1 <= 0.0 {
+                    bail!("Thermistor nominal resistance {} must be positive", r1);
+                }
+                if !t1.is_finite() || t1 <= -KELVIN_OFFSET {
+                    bail!("Thermistor nominal temperature {} is invalid", t1);
+                }
+            }
+            ThermistorModel::SteinhartHart { a, b, c } => {
+                if !a.is_finite() || !b.is_finite() || !c.is_finite() {
+                    bail!("Steinhart-Hart coefficients must be numbers");
+                }
+                if b <= 0.0 {
+                    bail!("Steinhart-Hart coefficient b {} must be positive", b);
+                }
+            }
+        }
+        Ok(())
+    }
+}
 
-#[allow(dead_code)]
-pub fn temperature_from_resistance(r2: f32) -> f32 {
-    //                     1
-    // t2 =  ------------------------------
-    //           ln(rNtc / r1)        1
-    //           -------------   +  ----
-    //               beta            t1
+impl ThermistorProperties {
+    // Check the model, and that it gives a temperature in the rated
+    // range when the thermistor matches the reference resistor
+    pub fn validate(&self) -> Result<()> {
+        let reference = self.reference_resistance;
+        if !reference.is_finite() || reference <= 0.0 {
+            bail!("Reference resistance {} must be positive", reference);
+        }
+        self.model.validate()?;
 
-    let r1 = THERMISTOR_PROPERTIES.r1;
+        let temperature = temperature_from_resistance(&self.model, reference);
+        if !(MINIMUM_TEMPERATURE..=MAXIMUM_TEMPERATURE).contains(&temperature) {
+            bail!(
+                "Thermistor model gives {} degrees at the reference resistance of {} ohms",
+                temperature,
+                reference
+            );
+        }
+        Ok(())
+    }
+}
 
-    if r1 == r2 {
-        return THERMISTOR_PROPERTIES.t1;
+impl Default for ThermistorProperties {
+    // 12k NTC with beta 3750 and a matching 12k reference resistor
+    fn default() -> Self {
+        ThermistorProperties {
+            reference_resistance: 12000.0,
+            model: ThermistorModel::Beta {
+                beta: 3750.0,
+                r1: 12000.0,
+                t1: 25.0,
+            },
+        }
     }
+}
+
+pub fn temperature_from_resistance(model: &ThermistorModel, r2: f32) -> f32 {
+    match *model {
+        ThermistorModel::Beta { beta, r1, t1 } => {
+            //                     1
+            // t2 =  ------------------------------
+            //           ln(rNtc / r1)        1
+            //           -------------   +  ----
+            //               beta            t1
+
+            if r1 == r2 {
+                return t1;
+            }
 
-    let beta = THERMISTOR_PROPERTIES.beta;
-    let t1: f32 = THERMISTOR_PROPERTIES.t1 + KELVIN_OFFSET;
+            let t1: f32 = t1 + KELVIN_OFFSET;
 
-    let resistance_ratio = r2 / r1;
+            let resistance_ratio = r2 / r1;
 
-    let t2 = 1.0 / ((resistance_ratio.ln() / beta) + (1.0 / t1));
+            let t2 = 1.0 / ((resistance_ratio.ln() / beta) + (1.0 / t1));
 
-    t2 - KELVIN_OFFSET
+            t2 - KELVIN_OFFSET
+        }
+        ThermistorModel::SteinhartHart { a, b, c } => {
+            let ln_r = r2.ln();
+            let t2 = 1.0 / (a + b * ln_r + c * ln_r * ln_r * ln_r);
+
+            t2 - KELVIN_OFFSET
+        }
+    }
 }
 
-#[allow(dead_code)]
 pub fn voltage_to_resistance(v_supply: f32, sample: f32, reference_reistance: f32) -> f32 {
     //
     // Vcc *--
@@ -69,64 +169,239 @@ pub fn voltage_to_resistance(v_supply: f32, sample: f32, reference_reistance: f3
     (sample * reference_reistance) / (v_supply - sample)
 }
 
-pub fn temperature_from_voltage(v_supply: f32, sample: f32) -> f32 {
-    // NTC temperature (Kelvin) given resistance and beta value
-    //
-    //                     1
-    // t2 =  ------------------------------
-    //           ln(rNtc / R1)        1
-    //           --------------  +  ----
-    //               beta            T1
-
-    // Voltage divider for resistance from voltage
-    // rNtc = R2 = (Vr2 * R1) / (Vcc - Vr2)
-
-    // Substuting
-
-    //                     1
-    // t2 =  ---------------------------------------------------
-    //           ln((Vr2 * R1) / (Vcc - Vr2) / R1)       1
-    //           ----------------------------------  +  ----
-    //               beta                                T1
-
-    //                     1
-    // t2 =  ------------------------------------
-    //           ln(Vr2 / (Vcc - Vr2))       1
-    //           ----------------------  +  ----
-    //               beta                    t1
-
-    let a = sample / (v_supply - sample);
-    let b = a.ln() / THERMISTOR_PROPERTIES.beta;
-    let c = b + (1.0 / (THERMISTOR_PROPERTIES.t1 + KELVIN_OFFSET));
-    (1.0 / c) - KELVIN_OFFSET
+// Temperature from the thermistor voltage, or the fault when the
+// reading cannot be a working thermistor
+pub fn temperature_from_voltage(
+    properties: &ThermistorProperties,
+    v_supply: f32,
+    sample: f32,
+) -> Result<f32, SensorFault> {
+    if v_supply.is_nan() || v_supply <= 0.0 || !sample.is_finite() {
+        return Err(SensorFault::OutOfRange);
+    }
+
+    let ratio = sample / v_supply;
+    if ratio >= 1.0 - FAULT_MARGIN {
+        return Err(SensorFault::OpenCircuit);
+    }
+    if ratio <= FAULT_MARGIN {
+        return Err(SensorFault::ShortCircuit);
+    }
+
+    let temperature = convert_voltage(properties, v_supply, sample);
+    if !(MINIMUM_TEMPERATURE..=MAXIMUM_TEMPERATURE).contains(&temperature) {
+        return Err(SensorFault::OutOfRange);
+    }
+    Ok(temperature)
+}
+
+fn convert_voltage(properties: &ThermistorProperties, v_supply: f32, sample: f32) -> f32 {
+    match properties.model {
+        ThermistorModel::Beta { beta, r1, t1 } if r1 == properties.reference_resistance => {
+            // NTC temperature (Kelvin) given resistance and beta value
+            //
+            //                     1
+            // t2 =  ------------------------------
+            //           ln(rNtc / R1)        1
+            //           --------------  +  ----
+            //               beta            T1
+
+            // Voltage divider for resistance from voltage
+            // rNtc = R2 = (Vr2 * R1) / (Vcc - Vr2)
+
+            // Substuting
+
+            //                     1
+            // t2 =  ---------------------------------------------------
+            //           ln((Vr2 * R1) / (Vcc - Vr2) / R1)       1
+            //           ----------------------------------  +  ----
+            //               beta                                T1
+
+            //                     1
+            // t2 =  ------------------------------------
+            //           ln(Vr2 / (Vcc - Vr2))       1
+            //           ----------------------  +  ----
+            //               beta                    t1
+
+            let a = sample / (v_supply - sample);
+            let b = a.ln() / beta;
+            let c = b + (1.0 / (t1 + KELVIN_OFFSET));
+            (1.0 / c) - KELVIN_OFFSET
+        }
+        _ => {
+            let resistance =
+                voltage_to_resistance(v_supply, sample, properties.reference_resistance);
+            temperature_from_resistance(&properties.model, resistance)
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn beta_12k() -> ThermistorModel {
+        ThermistorProperties::default().model
+    }
+
+    // YSI 44006 10k NTC
+    fn steinhart_hart_10k() -> ThermistorModel {
+        ThermistorModel::SteinhartHart {
+            a: 1.129148e-3,
+            b: 2.34125e-4,
+            c: 8.76741e-8,
+        }
+    }
+
+    fn assert_close(value: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (value - expected).abs() < tolerance,
+            "{:?} != {:?}",
+            value,
+            expected
+        );
+    }
+
     #[test]
     fn test_temperature_from_ntc_resistance() {
-        let t2 = temperature_from_resistance(12_000.0);
+        let model = beta_12k();
+        let t2 = temperature_from_resistance(&model, 12_000.0);
         assert_eq!(t2, 25.0);
 
-        let t2 = temperature_from_resistance(12_001.0);
+        let t2 = temperature_from_resistance(&model, 12_001.0);
         assert!(t2 < 25.0);
         assert!(t2 > 24.95);
 
-        let t2 = temperature_from_resistance(11_999.0);
+        let t2 = temperature_from_resistance(&model, 11_999.0);
         assert!(t2 > 25.0);
         assert!(t2 < 25.05);
 
-        let t2 = temperature_from_resistance(13_050.0);
+        let t2 = temperature_from_resistance(&model, 13_050.0);
         assert!(t2 > 23.0);
         assert!(t2 < 23.05);
 
-        let t2 = temperature_from_resistance(958.0);
+        let t2 = temperature_from_resistance(&model, 958.0);
         assert!(t2 > 99.95);
         assert!(t2 < 100.0);
     }
 
+    #[test]
+    fn test_beta_12k_curve() {
+        let model = beta_12k();
+        // Points on the beta curve of a 12k NTC with B25/50 3750
+        let curve = [
+            (0.0, 37_942.0),
+            (25.0, 12_000.0),
+            (50.0, 4_535.2),
+            (100.0, 957.84),
+        ];
+        for (temperature, resistance) in curve {
+            assert_close(
+                temperature_from_resistance(&model, resistance),
+                temperature,
+                0.01,
+            );
+        }
+    }
+
+    #[test]
+    fn test_beta_against_published_table() {
+        // YSI 44006 resistance table
+        let table = [
+            (0.0, 32_650.0),
+            (25.0, 10_000.0),
+            (50.0, 3_602.0),
+            (100.0, 678.3),
+        ];
+        // B25/50 from the table, as a datasheet would give it
+        let beta = (10_000.0f32 / 3_602.0).ln()
+            / (1.0 / (25.0 + KELVIN_OFFSET) - 1.0 / (50.0 + KELVIN_OFFSET));
+        let model = ThermistorModel::Beta {
+            beta,
+            r1: 10_000.0,
+            t1: 25.0,
+        };
+
+        // The beta model only fits the real curve between its two
+        // reference temperatures; it is within half a degree over the
+        // range of a floor, and further off beyond it
+        for (temperature, resistance) in table {
+            let tolerance = if temperature <= 50.0 { 0.5 } else { 1.5 };
+            assert_close(
+                temperature_from_resistance(&model, resistance),
+                temperature,
+                tolerance,
+            );
+        }
+        assert!(temperature_from_resistance(&model, 678.3) > 101.0);
+    }
+
+    #[test]
+    fn test_validate() {
+        ThermistorProperties::default().validate().unwrap();
+        let properties = ThermistorProperties {
+            reference_resistance: 10_000.0,
+            model: steinhart_hart_10k(),
+        };
+        properties.validate().unwrap();
+
+        // Unconfigured Steinhart-Hart coefficients
+        let model = ThermistorModel::SteinhartHart {
+            a: 0.0,
+            b: 0.0,
+            c: 0.0,
+        };
+        assert!(model.validate().is_err());
+        let model = ThermistorModel::SteinhartHart {
+            a: f32::NAN,
+            b: 2.34125e-4,
+            c: 8.76741e-8,
+        };
+        assert!(model.validate().is_err());
+        let model = ThermistorModel::Beta {
+            beta: 0.0,
+            r1: 12_000.0,
+            t1: 25.0,
+        };
+        assert!(model.validate().is_err());
+
+        let properties = ThermistorProperties {
+            reference_resistance: 0.0,
+            ..ThermistorProperties::default()
+        };
+        assert!(properties.validate().is_err());
+
+        // Coefficients far off for this thermistor
+        let properties = ThermistorProperties {
+            reference_resistance: 10_000.0,
+            model: ThermistorModel::SteinhartHart {
+                a: 1.0e-2,
+                b: 2.34125e-4,
+                c: 8.76741e-8,
+            },
+        };
+        assert!(properties.validate().is_err());
+    }
+
+    #[test]
+    fn test_steinhart_hart_10k_table() {
+        let model = steinhart_hart_10k();
+        // YSI 44006 resistance table
+        let table = [
+            (0.0, 32_650.0),
+            (25.0, 10_000.0),
+            (50.0, 3_602.0),
+            (100.0, 678.3),
+        ];
+        for (temperature, resistance) in table {
+            assert_close(
+                temperature_from_resistance(&model, resistance),
+                temperature,
+                0.05,
+            );
+        }
+    }
+
     #[test]
     fn test_voltage_to_resistance() {
         let vcc = 5000.0;
@@ -144,28 +419,129 @@ mod tests {
 
     #[test]
     fn test_voltage_to_temperature() {
+        let model = beta_12k();
         let vcc = 5000.0;
         let r1 = 12_000.0;
         let r2 = voltage_to_resistance(vcc, 1000.0, r1);
-        let temperature = temperature_from_resistance(r2);
+        let temperature = temperature_from_resistance(&model, r2);
         assert!(temperature > 61.9);
         assert!(temperature < 61.95);
 
         let r2 = voltage_to_resistance(vcc, 4000.0, r1);
-        let temperature = temperature_from_resistance(r2);
+        let temperature = temperature_from_resistance(&model, r2);
         assert!(temperature < 4.575);
         assert!(temperature > -4.625);
     }
 
     #[test]
     fn test_voltage_to_temperature_direct() {
+        let properties = ThermistorProperties::default();
         let vcc = 5000.0;
-        let temperature = temperature_from_voltage(vcc, 1000.0);
+        let temperature = temperature_from_voltage(&properties, vcc, 1000.0).unwrap();
         assert!(temperature > 61.9);
         assert!(temperature < 61.95);
 
-        let temperature = temperature_from_voltage(vcc, 4000.0);
+        let temperature = temperature_from_voltage(&properties, vcc, 4000.0).unwrap();
         assert!(temperature < 4.575);
         assert!(temperature > -4.625);
     }
+
+    #[test]
+    fn test_voltage_to_temperature_steinhart_hart() {
+        let properties = ThermistorProperties {
+            reference_resistance: 10_000.0,
+            model: steinhart_hart_10k(),
+        };
+        let vcc = 3300.0;
+
+        let temperature = temperature_from_voltage(&properties, vcc, 1650.0).unwrap();
+        assert_close(temperature, 25.0, 0.01);
+
+        // 3602 ohms at 50 degrees
+        let sample = vcc * 3602.0 / (10_000.0 + 3602.0);
+        let temperature = temperature_from_voltage(&properties, vcc, sample).unwrap();
+        assert_close(temperature, 50.0, 0.05);
+    }
+
+    #[test]
+    fn test_voltage_to_temperature_reference_mismatch() {
+        // 12k NTC measured against a 10k reference resistor
+        let properties = ThermistorProperties {
+            reference_resistance: 10_000.0,
+            ..ThermistorProperties::default()
+        };
+        let vcc = 3300.0;
+        let sample = vcc * 12_000.0 / (10_000.0 + 12_000.0);
+        let temperature = temperature_from_voltage(&properties, vcc, sample).unwrap();
+        assert_close(temperature, 25.0, 0.01);
+    }
+
+    #[test]
+    fn test_open_circuit() {
+        let properties = ThermistorProperties::default();
+        let vcc = 3300.0;
+
+        let result = temperature_from_voltage(&properties, vcc, vcc);
+        assert_eq!(result, Err(SensorFault::OpenCircuit));
+
+        let result = temperature_from_voltage(&properties, vcc, vcc * 0.995);
+        assert_eq!(result, Err(SensorFault::OpenCircuit));
+
+        // Just inside the boundary is below the rated range
+        let result = temperature_from_voltage(&properties, vcc, vcc * 0.994);
+        assert_eq!(result, Err(SensorFault::OutOfRange));
+    }
+
+    #[test]
+    fn test_short_circuit() {
+        let properties = ThermistorProperties::default();
+        let vcc = 3300.0;
+
+        let result = temperature_from_voltage(&properties, vcc, 0.0);
+        assert_eq!(result, Err(SensorFault::ShortCircuit));
+
+        let result = temperature_from_voltage(&properties, vcc, vcc * 0.005);
+        assert_eq!(result, Err(SensorFault::ShortCircuit));
+
+        let result = temperature_from_voltage(&properties, vcc, vcc * 0.006);
+        assert_eq!(result, Err(SensorFault::OutOfRange));
+    }
+
+    #[test]
+    fn test_rated_range() {
+        let properties = ThermistorProperties::default();
+        let vcc = 3300.0;
+        let sample = |resistance: f32| vcc * resistance / (12_000.0 + resistance);
+
+        // Limits of -40 and 125 degrees for the 12k beta 3750 thermistor
+        let result = temperature_from_voltage(&properties, vcc, sample(390_000.0));
+        assert!(result.is_ok_and(|t| t > -39.7 && t < -39.6), "{:?}", result);
+
+        let result = temperature_from_voltage(&properties, vcc, sample(520.0));
+        assert!(result.is_ok_and(|t| t > 124.1 && t < 124.2), "{:?}", result);
+
+        let result = temperature_from_voltage(&properties, vcc, sample(420_000.0));
+        assert_eq!(result, Err(SensorFault::OutOfRange));
+
+        let result = temperature_from_voltage(&properties, vcc, sample(490.0));
+        assert_eq!(result, Err(SensorFault::OutOfRange));
+    }
+
+    #[test]
+    fn test_invalid_voltages() {
+        let properties = ThermistorProperties::default();
+
+        let result = temperature_from_voltage(&properties, 0.0, 0.0);
+        assert_eq!(result, Err(SensorFault::OutOfRange));
+
+        let result = temperature_from_voltage(&properties, -3300.0, 1650.0);
+        assert_eq!(result, Err(SensorFault::OutOfRange));
+
+        let result = temperature_from_voltage(&properties, 3300.0, f32::NAN);
+        assert_eq!(result, Err(SensorFault::OutOfRange));
+
+        // Sample above the supply voltage
+        let result = temperature_from_voltage(&properties, 3300.0, 3400.0);
+        assert_eq!(result, Err(SensorFault::OpenCircuit));
+    }
 }
@@ -0,0 +1,285 @@
+// Units of electricity prices.
+//
+// Prices are held in one canonical unit, hundredths of the currency per
+// kWh, e.g. c/kWh for EUR or p/kWh for GBP. Providers and configured
+// values declare their own units and are converted on the way in.
+// There are no exchange rates; every price must be in one currency.
+use alloc::vec::Vec;
+use anyhow::{anyhow, bail, Result};
+use core::fmt;
+use core::str::FromStr;
+
+use crate::state::{ElectricityPrice, PriceDelta};
+
+// A configured threshold this many times larger or smaller than typical
+// prices is most likely in another unit
+const SCALE_MISMATCH: f32 = 20.0;
+
+// ISO 4217 currency code
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub const EUR: Currency = Currency(*b"EUR");
+    pub const GBP: Currency = Currency(*b"GBP");
+
+    pub fn as_str(&self) -> &str {
+        // Only ever constructed from ASCII letters
+        core::str::from_utf8(&self.0).unwrap_or("???")
+    }
+}
+
+impl FromStr for Currency {
+    type Err = anyhow::Error;
+
+    fn from_str(code: &str) -> Result<Self> {
+        let invalid = || anyhow!("Invalid currency {:?}", code);
+        let bytes: [u8; 3] = code.as_bytes().try_into().map_err(|_| invalid())?;
+        if !bytes.iter().all(u8::is_ascii_alphabetic) {
+            return Err(invalid());
+        }
+        Ok(Currency(bytes.map(|byte| byte.to_ascii_uppercase())))
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PriceScale {
+    // Wholesale prices, e.g. EUR/MWh
+    PerMwh,
+    // e.g. EUR/kWh
+    PerKwh,
+    // Hundredths of the currency, e.g. c/kWh or p/kWh; the canonical
+    // scale
+    CentsPerKwh,
+}
+
+impl PriceScale {
+    // Factor into cents per kWh
+    fn factor(self) -> f32 {
+        match self {
+            PriceScale::PerMwh => 0.1,
+            PriceScale::PerKwh => 100.0,
+            PriceScale::CentsPerKwh => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PriceUnit {
+    pub currency: Currency,
+    pub scale: PriceScale,
+}
+
+impl PriceUnit {
+    pub const EUR_PER_MWH: PriceUnit = PriceUnit {
+        currency: Currency::EUR,
+        scale: PriceScale::PerMwh,
+    };
+    pub const EUR_CENTS_PER_KWH: PriceUnit = PriceUnit {
+        currency: Currency::EUR,
+        scale: PriceScale::CentsPerKwh,
+    };
+    pub const GBP_PENCE_PER_KWH: PriceUnit = PriceUnit {
+        currency: Currency::GBP,
+        scale: PriceScale::CentsPerKwh,
+    };
+
+    pub fn new(currency: Currency, scale: PriceScale) -> PriceUnit {
+        PriceUnit { currency, scale }
+    }
+
+    // A price in this unit, in the canonical unit
+    pub fn price(&self, value: f32) -> ElectricityPrice {
+        ElectricityPrice::new(value * self.scale.factor())
+    }
+
+    pub fn delta(&self, value: f32) -> PriceDelta {
+        PriceDelta::new(value * self.scale.factor())
+    }
+
+    // Fails for prices in another currency, which cannot be converted
+    pub fn check_currency(&self, currency: Currency) -> Result<()> {
+        if self.currency != currency {
+            bail!(
+                "Prices in {} cannot be compared with prices in {}",
+                self.currency,
+                currency
+            );
+        }
+        Ok(())
+    }
+}
+
+// Parse a unit written as "EUR/MWh", "EUR/kWh" or "EUR cents/kWh";
+// "c/kWh" and "p/kWh" are accepted for cents and pence, e.g.
+// "GBP p/kWh". Units are not case sensitive.
+impl FromStr for PriceUnit {
+    type Err = anyhow::Error;
+
+    fn from_str(unit: &str) -> Result<Self> {
+        let invalid = || anyhow!("Invalid price unit {:?}", unit);
+        let (amount, energy) = unit.trim().split_once('/').ok_or_else(invalid)?;
+        let (currency, cents) = match amount.split_once(' ') {
+            Some((currency, minor)) => {
+                let minor = minor.trim();
+                if !["cents", "c", "pence", "p"]
+                    .iter()
+                    .any(|name| name.eq_ignore_ascii_case(minor))
+                {
+                    return Err(invalid());
+                }
+                (currency, true)
+            }
+            None => (amount, false),
+        };
+
+        let scale = match (energy.trim(), cents) {
+            (energy, false) if energy.eq_ignore_ascii_case("MWh") => PriceScale::PerMwh,
+            (energy, false) if energy.eq_ignore_ascii_case("kWh") => PriceScale::PerKwh,
+            (energy, true) if energy.eq_ignore_ascii_case("kWh") => PriceScale::CentsPerKwh,
+            _ => return Err(invalid()),
+        };
+        Ok(PriceUnit {
+            currency: currency.parse()?,
+            scale,
+        })
+    }
+}
+
+impl fmt::Display for PriceUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.scale {
+            PriceScale::PerMwh => write!(f, "{}/MWh", self.currency),
+            PriceScale::PerKwh => write!(f, "{}/kWh", self.currency),
+            PriceScale::CentsPerKwh => write!(f, "{} cents/kWh", self.currency),
+        }
+    }
+}
+
+// The typical price, when a configured threshold is so far from it that
+// one of the two is most likely in the wrong unit
+pub fn price_scale_mismatch(
+    threshold: ElectricityPrice,
+    prices: impl IntoIterator<Item = ElectricityPrice>,
+) -> Option<ElectricityPrice> {
+    let mut magnitudes: Vec<f32> = prices
+        .into_iter()
+        .map(|price| f32::from(price).abs())
+        .filter(|price| price.is_finite())
+        .collect();
+    if magnitudes.is_empty() {
+        return None;
+    }
+    magnitudes.sort_by(f32::total_cmp);
+    let typical = magnitudes[magnitudes.len() / 2];
+
+    let threshold = f32::from(threshold).abs();
+    if typical == 0.0 || threshold == 0.0 {
+        return None;
+    }
+    let ratio = threshold / typical;
+    if ratio > SCALE_MISMATCH || ratio < 1.0 / SCALE_MISMATCH {
+        return Some(ElectricityPrice::new(typical));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "EUR/MWh".parse::<PriceUnit>().unwrap(),
+            PriceUnit::EUR_PER_MWH
+        );
+        assert_eq!(
+            "Eur/MWh".parse::<PriceUnit>().unwrap(),
+            PriceUnit::EUR_PER_MWH
+        );
+        assert_eq!(
+            "EUR cents/kWh".parse::<PriceUnit>().unwrap(),
+            PriceUnit::EUR_CENTS_PER_KWH
+        );
+        assert_eq!(
+            "GBP p/kWh".parse::<PriceUnit>().unwrap(),
+            PriceUnit::GBP_PENCE_PER_KWH
+        );
+        assert_eq!(
+            "sek/kWh".parse::<PriceUnit>().unwrap(),
+            PriceUnit::new("SEK".parse().unwrap(), PriceScale::PerKwh)
+        );
+        for invalid in [
+            "EUR",
+            "c/kWh",
+            "EUR cents/MWh",
+            "EUR/GWh",
+            "EURO/kWh",
+            "EUR x/kWh",
+        ] {
+            assert!(invalid.parse::<PriceUnit>().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for unit in [
+            PriceUnit::EUR_PER_MWH,
+            PriceUnit::EUR_CENTS_PER_KWH,
+            PriceUnit::new(Currency::EUR, PriceScale::PerKwh),
+        ] {
+            assert_eq!(format!("{}", unit).parse::<PriceUnit>().unwrap(), unit);
+        }
+    }
+
+    #[test]
+    fn test_conversion() {
+        // 22.06 c/kWh in each unit
+        let expected = ElectricityPrice::new(22.06);
+        let tolerance = PriceDelta::new(0.0001);
+        assert!(PriceUnit::EUR_PER_MWH
+            .price(220.6)
+            .approx_eq(expected, tolerance));
+        assert!("EUR/kWh"
+            .parse::<PriceUnit>()
+            .unwrap()
+            .price(0.2206)
+            .approx_eq(expected, tolerance));
+        assert_eq!(PriceUnit::EUR_CENTS_PER_KWH.price(22.06), expected);
+        assert_eq!(PriceUnit::EUR_PER_MWH.delta(-25.0), PriceDelta::new(-2.5));
+    }
+
+    #[test]
+    fn test_check_currency() {
+        assert!(PriceUnit::EUR_PER_MWH.check_currency(Currency::EUR).is_ok());
+        assert!(PriceUnit::GBP_PENCE_PER_KWH
+            .check_currency(Currency::EUR)
+            .is_err());
+    }
+
+    #[test]
+    fn test_price_scale_mismatch() {
+        let prices = [4.0, 2.42, 22.06, 31.32, 40.17, -0.5, 10.0].map(ElectricityPrice::new);
+
+        // 0.15 is EUR/kWh while the prices are in c/kWh
+        assert_eq!(
+            price_scale_mismatch(ElectricityPrice::new(0.15), prices),
+            Some(ElectricityPrice::new(10.0))
+        );
+        // EUR/MWh
+        assert!(price_scale_mismatch(ElectricityPrice::new(300.0), prices).is_some());
+        assert_eq!(
+            price_scale_mismatch(ElectricityPrice::new(15.0), prices),
+            None
+        );
+        assert_eq!(price_scale_mismatch(ElectricityPrice::new(0.15), []), None);
+    }
+}
@@ -0,0 +1,192 @@
+// Representation of temperatures and prices.
+//
+// With the `fixed` feature, values are 16.16 fixed-point numbers so that
+// every decision is bit-identical on the host and the device, whatever
+// floating point support the target has. Conversion from f32 rounds to
+// the nearest representable value, with ties to even, and arithmetic
+// between values saturates.
+//
+// Fixed-point has no NaN or infinity; NaN and negative infinity map to
+// the minimum value, positive infinity and overflow to the maximum, and
+// both ends of the range are treated as not finite.
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[cfg(not(feature = "fixed"))]
+mod repr {
+    pub type Value = f32;
+
+    pub const ZERO: Value = 0.0;
+
+    pub fn from_f32(value: f32) -> Value {
+        value
+    }
+
+    pub fn to_f32(value: Value) -> f32 {
+        value
+    }
+
+    pub fn is_finite(value: Value) -> bool {
+        value.is_finite()
+    }
+
+    pub fn add(a: Value, b: Value) -> Value {
+        a + b
+    }
+
+    pub fn sub(a: Value, b: Value) -> Value {
+        a - b
+    }
+
+    pub fn neg(value: Value) -> Value {
+        -value
+    }
+
+    pub fn abs(value: Value) -> Value {
+        value.abs()
+    }
+}
+
+#[cfg(feature = "fixed")]
+mod repr {
+    use fixed::types::I16F16;
+
+    pub type Value = I16F16;
+
+    pub const ZERO: Value = I16F16::ZERO;
+
+    pub fn from_f32(value: f32) -> Value {
+        if value.is_nan() {
+            return I16F16::MIN;
+        }
+        I16F16::saturating_from_num(value)
+    }
+
+    pub fn to_f32(value: Value) -> f32 {
+        if !is_finite(value) {
+            return if value == I16F16::MAX {
+                f32::INFINITY
+            } else {
+                f32::NEG_INFINITY
+            };
+        }
+        value.to_num()
+    }
+
+    pub fn is_finite(value: Value) -> bool {
+        value != I16F16::MIN && value != I16F16::MAX
+    }
+
+    pub fn add(a: Value, b: Value) -> Value {
+        a.saturating_add(b)
+    }
+
+    pub fn sub(a: Value, b: Value) -> Value {
+        a.saturating_sub(b)
+    }
+
+    pub fn neg(value: Value) -> Value {
+        value.saturating_neg()
+    }
+
+    pub fn abs(value: Value) -> Value {
+        value.saturating_abs()
+    }
+}
+
+pub use repr::*;
+
+// Scale by an f32 factor. IEEE multiplication and division are
+// correctly rounded, so the result is rounded back to the same value on
+// every target.
+pub fn mul(value: Value, factor: f32) -> Value {
+    from_f32(to_f32(value) * factor)
+}
+
+pub fn div(value: Value, divisor: f32) -> Value {
+    from_f32(to_f32(value) / divisor)
+}
+
+pub fn ratio(a: Value, b: Value) -> f32 {
+    to_f32(a) / to_f32(b)
+}
+
+pub fn clamp(value: Value, minimum: Value, maximum: Value) -> Value {
+    if value < minimum {
+        minimum
+    } else if value > maximum {
+        maximum
+    } else {
+        value
+    }
+}
+
+// Values are always stored and sent as plain numbers
+pub fn serialize<S: Serializer>(value: &Value, serializer: S) -> Result<S::Ok, S::Error> {
+    to_f32(*value).serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+    f32::deserialize(deserializer).map(from_f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Half the resolution of the fixed-point representation
+    const ROUNDING: f32 = 1.0 / 131072.0;
+
+    #[test]
+    fn test_round_trip_within_resolution() {
+        // Floor temperatures, and prices from the most negative to a
+        // price spike
+        for step in -10000..=50000 {
+            let number = step as f32 / 100.0;
+            let error = (to_f32(from_f32(number)) - number).abs();
+            assert!(
+                error <= ROUNDING * number.abs().max(1.0),
+                "{:?} {:?}",
+                number,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn test_non_finite() {
+        for number in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            assert!(!is_finite(from_f32(number)), "{:?}", number);
+        }
+        assert!(is_finite(from_f32(21.5)));
+        assert!(!is_finite(add(from_f32(f32::INFINITY), from_f32(1.0))));
+        assert_eq!(to_f32(from_f32(f32::INFINITY)), f32::INFINITY);
+        assert_eq!(to_f32(from_f32(f32::NEG_INFINITY)), f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_clamp() {
+        let minimum = from_f32(15.0);
+        let maximum = from_f32(22.0);
+        assert_eq!(clamp(from_f32(10.0), minimum, maximum), minimum);
+        assert_eq!(clamp(from_f32(30.0), minimum, maximum), maximum);
+        assert_eq!(clamp(from_f32(20.0), minimum, maximum), from_f32(20.0));
+    }
+
+    #[cfg(feature = "fixed")]
+    #[test]
+    fn test_fixed_point_rounding() {
+        use fixed::types::I16F16;
+
+        // 0.1 is 6553.6 units of 2^-16, rounded up
+        assert_eq!(from_f32(0.1), I16F16::from_bits(6554));
+        assert_eq!(from_f32(-0.1), I16F16::from_bits(-6554));
+        // Ties round to even
+        assert_eq!(from_f32(ROUNDING), I16F16::ZERO);
+        assert_eq!(from_f32(3.0 * ROUNDING), I16F16::from_bits(2));
+
+        // Out of range saturates rather than wrapping
+        assert_eq!(from_f32(40000.0), I16F16::MAX);
+        assert_eq!(from_f32(-40000.0), I16F16::MIN);
+        assert_eq!(add(from_f32(32000.0), from_f32(1000.0)), I16F16::MAX);
+    }
+}
@@ -0,0 +1,45 @@
+use anyhow::Result;
+use log::*;
+use std::sync::{Arc, Mutex};
+
+use control::AwayMode;
+
+use crate::storage::SharedStorage;
+
+const STORAGE_KEY: &str = "away";
+
+// Away mode, settable at runtime and persisted across reboots
+#[derive(Clone)]
+pub struct SharedAwayMode {
+    away: Arc<Mutex<Option<AwayMode>>>,
+    storage: SharedStorage,
+}
+
+impl SharedAwayMode {
+    pub fn load(storage: SharedStorage) -> Result<SharedAwayMode> {
+        let away = storage.load::<AwayMode>(STORAGE_KEY)?;
+        if let Some(away) = &away {
+            info!("Loaded away mode {:?}", away);
+        }
+        let shared_away = SharedAwayMode {
+            away: Arc::new(Mutex::new(away)),
+            storage,
+        };
+        Ok(shared_away)
+    }
+
+    pub fn get(&self) -> Option<AwayMode> {
+        *self.away.lock().unwrap()
+    }
+
+    pub fn set(&self, away: Option<AwayMode>) -> Result<()> {
+        let mut current = self.away.lock().unwrap();
+        match &away {
+            Some(away) => self.storage.store(STORAGE_KEY, away)?,
+            None => self.storage.remove(STORAGE_KEY)?,
+        }
+        info!("Away mode set to {:?}", away);
+        *current = away;
+        Ok(())
+    }
+}
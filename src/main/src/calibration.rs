@@ -0,0 +1,67 @@
+use anyhow::Result;
+use log::*;
+use std::sync::{Arc, Mutex};
+
+use control::{calibrate, CalibrationPoint, ThermistorModel, ThermistorProperties};
+
+use crate::storage::SharedStorage;
+
+const STORAGE_KEY: &str = "calibration";
+
+// Thermistor calibration, replacing the configured thermistor model
+// once solved, and persisted across reboots
+#[derive(Clone)]
+pub struct SharedCalibration {
+    model: Arc<Mutex<Option<ThermistorModel>>>,
+    storage: SharedStorage,
+}
+
+impl SharedCalibration {
+    pub fn load(storage: SharedStorage) -> Result<SharedCalibration> {
+        let model = storage.load::<ThermistorModel>(STORAGE_KEY)?;
+        if let Some(model) = &model {
+            info!("Loaded thermistor calibration {:?}", model);
+        }
+        let shared_calibration = SharedCalibration {
+            model: Arc::new(Mutex::new(model)),
+            storage,
+        };
+        Ok(shared_calibration)
+    }
+
+    pub fn get(&self) -> Option<ThermistorModel> {
+        *self.model.lock().unwrap()
+    }
+
+    // Thermistor properties with the calibrated model, if any
+    pub fn apply(&self, properties: &ThermistorProperties) -> ThermistorProperties {
+        match self.get() {
+            Some(model) => ThermistorProperties {
+                model,
+                ..*properties
+            },
+            None => *properties,
+        }
+    }
+
+    pub fn calibrate(
+        &self,
+        properties: &ThermistorProperties,
+        points: &[CalibrationPoint],
+    ) -> Result<ThermistorModel> {
+        let model = calibrate(properties.reference_resistance, points)?;
+        let mut current = self.model.lock().unwrap();
+        self.storage.store(STORAGE_KEY, &model)?;
+        info!("Thermistor calibrated as {:?}", model);
+        *current = Some(model);
+        Ok(model)
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        let mut current = self.model.lock().unwrap();
+        self.storage.remove(STORAGE_KEY)?;
+        info!("Thermistor calibration cleared");
+        *current = None;
+        Ok(())
+    }
+}
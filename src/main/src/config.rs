@@ -1,7 +1,13 @@
 use anyhow::{bail, Result};
 use core::time::Duration;
 
-use control::{CoreConfig, ElectricityPrice, Temperature};
+use control::{
+    Awattar, ComfortSchedule, ControlStrategy, CoreConfig, ElectricityPrice, Entsoe,
+    FallbackConfig, FilterConfig, HeatingBudget, JsonFeed, NetworkFees, NordPool, Octopus,
+    PidConfig, PriceMode, PriceProvider, PriceSource, PriceUnit, PwmConfig, RelayConfig, Smoothing,
+    Tariff, Temperature, TemperatureDelta, ThermalModel, ThermistorModel, ThermistorProperties,
+    TimeZone,
+};
 
 mod private;
 
@@ -13,7 +19,6 @@ pub struct WifiConfig {
 
 #[derive(Copy, Clone, Debug, Default)]
 pub struct ServerConfig {
-    pub electricity_price_api: &'static str,
     #[allow(dead_code)]
     pub metrics_url: &'static str,
     #[allow(dead_code)]
@@ -23,17 +28,49 @@ pub struct ServerConfig {
 #[derive(Copy, Clone, Debug)]
 pub struct Config {
     pub measurement_interval: Duration,
+    pub burst_samples: usize,
+    pub filter: FilterConfig,
     pub set_points: CoreConfig,
+    pub schedule: ComfortSchedule,
+    pub heating_budget: Option<HeatingBudget>,
+    pub preheat: bool,
+    pub thermal_model: ThermalModel,
+    pub thermal_forgetting_factor: f32,
+    pub thermistor: ThermistorProperties,
+    pub fallback: FallbackConfig,
+    pub strategy: ControlStrategy,
+    pub relay: RelayConfig,
+    pub timezone: TimeZone,
+    pub price_provider: PriceSource,
+    pub tariff: Tariff,
     pub wifi: WifiConfig,
     pub server: ServerConfig,
 }
 
 impl Config {
     pub fn read() -> Result<Config> {
-        let config = Config::from(&private::TOML_CONFIG);
+        let config = Config::try_from(&private::TOML_CONFIG)?;
+        config.set_points.validate()?;
+        config.schedule.validate(&config.set_points)?;
+        config.thermistor.validate()?;
+        config.filter.validate()?;
+        if let ControlStrategy::Pid(pwm) = config.strategy {
+            pwm.validate(&config.relay)?;
+        }
 
-        if config.server.electricity_price_api.is_empty() {
-            bail!("Missing electricity price API configuration");
+        match config.price_provider {
+            PriceSource::Feed(feed) if feed.url.is_empty() => {
+                bail!("Missing electricity price API configuration")
+            }
+            PriceSource::Entsoe(entsoe) if entsoe.token.is_empty() => {
+                bail!("Missing ENTSO-E security token")
+            }
+            PriceSource::Octopus(octopus)
+                if octopus.product.is_empty() || octopus.tariff.is_empty() =>
+            {
+                bail!("Missing Octopus product or tariff")
+            }
+            _ => {}
         }
 
         Ok(config)
@@ -52,17 +89,170 @@ impl From<&private::TomlConfig> for WifiConfig {
 impl From<&private::TomlConfig> for ServerConfig {
     fn from(config: &private::TomlConfig) -> Self {
         ServerConfig {
-            electricity_price_api: config.electricity_price_api,
             metrics_url: config.metrics_url,
             ntp_server: config.ntp_server,
         }
     }
 }
 
-impl From<&private::TomlConfig> for Config {
-    fn from(config: &private::TomlConfig) -> Self {
-        Config {
+fn price_mode(config: &private::TomlConfig) -> Result<PriceMode> {
+    match config.price_mode {
+        "absolute" => Ok(PriceMode::Absolute),
+        "relative" => Ok(PriceMode::Relative {
+            hard_ceiling: config.relative_price_hard_ceiling,
+        }),
+        _ => bail!("Unknown price mode {:?}", config.price_mode),
+    }
+}
+
+fn smoothing(config: &private::TomlConfig) -> Result<Smoothing> {
+    match config.measurement_smoothing {
+        "none" => Ok(Smoothing::None),
+        "exponential" => Ok(Smoothing::Exponential {
+            alpha: config.measurement_smoothing_alpha,
+        }),
+        "kalman" => Ok(Smoothing::Kalman {
+            process_variance: config.measurement_process_variance,
+            measurement_variance: config.measurement_variance,
+        }),
+        _ => bail!(
+            "Unknown measurement smoothing {:?}",
+            config.measurement_smoothing
+        ),
+    }
+}
+
+fn thermistor_model(config: &private::TomlConfig) -> Result<ThermistorModel> {
+    match config.thermistor_model {
+        "beta" => Ok(ThermistorModel::Beta {
+            beta: config.thermistor_beta,
+            r1: config.thermistor_nominal_resistance,
+            t1: config.thermistor_nominal_temperature,
+        }),
+        "steinhart-hart" => Ok(ThermistorModel::SteinhartHart {
+            a: config.thermistor_a,
+            b: config.thermistor_b,
+            c: config.thermistor_c,
+        }),
+        _ => bail!("Unknown thermistor model {:?}", config.thermistor_model),
+    }
+}
+
+fn schedule(config: &private::TomlConfig) -> Result<ComfortSchedule> {
+    let schedule = ComfortSchedule {
+        weekday: config.schedule_weekday.parse()?,
+        weekend: config.schedule_weekend.parse()?,
+    };
+    Ok(schedule)
+}
+
+fn heating_budget(config: &private::TomlConfig) -> Option<HeatingBudget> {
+    if config.heating_energy_per_day > 0.0 {
+        return Some(HeatingBudget::Energy {
+            energy: config.heating_energy_per_day,
+            heater_power: config.heater_power,
+        });
+    }
+    if config.heating_hours_per_day > 0 {
+        return Some(HeatingBudget::Hours(config.heating_hours_per_day));
+    }
+    None
+}
+
+fn timezone(config: &private::TomlConfig) -> Result<TimeZone> {
+    config.timezone.parse()
+}
+
+fn maximum_price_unit(config: &private::TomlConfig) -> Result<PriceUnit> {
+    config.set_point_maximum_price_unit.parse()
+}
+
+fn tariff_price_unit(config: &private::TomlConfig) -> Result<PriceUnit> {
+    config.tariff_price_unit.parse()
+}
+
+fn tariff(config: &private::TomlConfig) -> Result<Tariff> {
+    let unit = tariff_price_unit(config)?;
+    let network_fees: NetworkFees = config.tariff_network_fees.parse()?;
+    let tariff = Tariff {
+        markup_percent: config.tariff_markup_percent,
+        fixed_fee: unit.delta(config.tariff_fixed_fee),
+        network_fees: network_fees.in_unit(&unit),
+        vat_percent: config.tariff_vat_percent,
+    };
+    Ok(tariff)
+}
+
+fn price_provider(config: &private::TomlConfig) -> Result<PriceSource> {
+    let timezone = config.price_market_timezone.parse()?;
+    let provider = match config.price_provider {
+        "feed" => PriceSource::Feed(JsonFeed {
+            url: config.electricity_price_api,
+            unit: config.electricity_price_unit.parse()?,
+        }),
+        "entsoe" => PriceSource::Entsoe(Entsoe {
+            token: config.entsoe_token,
+            area: config.entsoe_area,
+            timezone,
+        }),
+        "nordpool" => PriceSource::NordPool(NordPool {
+            area: config.nordpool_area,
+            currency: config.nordpool_currency.parse()?,
+            timezone,
+        }),
+        "awattar" => PriceSource::Awattar(Awattar {
+            country: config.awattar_country,
+            timezone,
+        }),
+        "octopus" => PriceSource::Octopus(Octopus {
+            product: config.octopus_product,
+            tariff: config.octopus_tariff,
+            timezone,
+        }),
+        _ => bail!("Unknown price provider {:?}", config.price_provider),
+    };
+    Ok(provider)
+}
+
+fn control_strategy(config: &private::TomlConfig) -> Result<ControlStrategy> {
+    match config.control_strategy {
+        "hysteresis" => Ok(ControlStrategy::Hysteresis),
+        "pid" => Ok(ControlStrategy::Pid(PwmConfig {
+            cycle_period: Duration::from_secs(config.pwm_cycle_period),
+            pid: PidConfig {
+                proportional_gain: config.pid_proportional_gain,
+                integral_gain: config.pid_integral_gain,
+                derivative_gain: config.pid_derivative_gain,
+                output_minimum: 0.0,
+                output_maximum: 1.0,
+            },
+            minimum_pulse: Duration::from_secs(config.pwm_minimum_pulse),
+        })),
+        _ => bail!("Unknown control strategy {:?}", config.control_strategy),
+    }
+}
+
+// Parse the TOML configuration; values are checked against each other
+// in `Config::read`
+impl TryFrom<&private::TomlConfig> for Config {
+    type Error = anyhow::Error;
+
+    fn try_from(config: &private::TomlConfig) -> Result<Self> {
+        // Prices are only converted between scales, never currencies
+        let price_provider = price_provider(config)?;
+        let currency = price_provider.unit().currency;
+        let maximum_price_unit = maximum_price_unit(config)?;
+        maximum_price_unit.check_currency(currency)?;
+        tariff_price_unit(config)?.check_currency(currency)?;
+
+        Ok(Config {
             measurement_interval: Duration::from_secs(config.measurement_interval),
+            burst_samples: config.measurement_burst_samples as usize,
+            filter: FilterConfig {
+                smoothing: smoothing(config)?,
+                outlier_threshold: TemperatureDelta::new(config.measurement_outlier_threshold),
+                maximum_rejections: config.measurement_maximum_rejections,
+            },
             set_points: CoreConfig {
                 minimum_temperature: Temperature::new(config.set_point_minimum_temperature),
                 fallback_minimum_temperature: Temperature::new(
@@ -70,11 +260,40 @@ impl From<&private::TomlConfig> for Config {
                 ),
                 maximum_temperature: Temperature::new(config.set_point_maximum_temperature),
                 turbo_temperature: Temperature::new(30.0),
-                maximum_price: ElectricityPrice::new(config.set_point_maximum_price),
+                maximum_price: maximum_price_unit.price(config.set_point_maximum_price),
+                price_mode: price_mode(config)?,
+                upper_hysteresis: TemperatureDelta::new(config.set_point_upper_hysteresis),
+                lower_hysteresis: TemperatureDelta::new(config.set_point_lower_hysteresis),
+            },
+            schedule: schedule(config)?,
+            heating_budget: heating_budget(config),
+            preheat: config.preheat,
+            thermal_model: ThermalModel {
+                heating_rate: config.thermal_heating_rate,
+                cooling_rate: config.thermal_cooling_rate,
+                ambient_temperature: Temperature::new(config.thermal_ambient_temperature),
+            },
+            thermal_forgetting_factor: config.thermal_forgetting_factor,
+            thermistor: ThermistorProperties {
+                reference_resistance: config.thermistor_reference_resistance,
+                model: thermistor_model(config)?,
+            },
+            fallback: FallbackConfig {
+                duty_cycle: config.fallback_duty_cycle,
+                price_biased: config.fallback_price_biased,
+                failure_threshold: config.fallback_failure_threshold,
+            },
+            strategy: control_strategy(config)?,
+            relay: RelayConfig {
+                minimum_on_time: Duration::from_secs(config.relay_minimum_on_time),
+                minimum_off_time: Duration::from_secs(config.relay_minimum_off_time),
             },
+            timezone: timezone(config)?,
+            price_provider,
+            tariff: tariff(config)?,
             wifi: WifiConfig::from(config),
             server: ServerConfig::from(config),
-        }
+        })
     }
 }
 
@@ -83,13 +302,48 @@ impl Default for Config {
         Config {
             // fixme, we should measure every few minutes at most
             measurement_interval: Duration::from_secs(1),
+            burst_samples: 5,
+            filter: FilterConfig {
+                smoothing: Smoothing::Exponential { alpha: 0.3 },
+                outlier_threshold: TemperatureDelta::new(2.0),
+                maximum_rejections: 3,
+            },
             set_points: CoreConfig {
                 minimum_temperature: Temperature::new(15.0),
                 fallback_minimum_temperature: Temperature::new(18.0),
                 maximum_temperature: Temperature::new(22.0),
                 turbo_temperature: Temperature::new(30.0),
-                maximum_price: ElectricityPrice::new(0.30),
+                maximum_price: ElectricityPrice::new(30.0),
+                price_mode: PriceMode::Absolute,
+                upper_hysteresis: TemperatureDelta::new(0.5),
+                lower_hysteresis: TemperatureDelta::new(0.5),
+            },
+            schedule: ComfortSchedule::default(),
+            heating_budget: None,
+            preheat: false,
+            thermal_model: ThermalModel {
+                heating_rate: 1.0,
+                cooling_rate: 0.05,
+                ambient_temperature: Temperature::new(15.0),
+            },
+            thermal_forgetting_factor: 0.999,
+            thermistor: ThermistorProperties::default(),
+            fallback: FallbackConfig {
+                duty_cycle: 0.3,
+                price_biased: true,
+                failure_threshold: 3,
+            },
+            strategy: ControlStrategy::Hysteresis,
+            relay: RelayConfig {
+                minimum_on_time: Duration::from_secs(600),
+                minimum_off_time: Duration::from_secs(600),
             },
+            timezone: TimeZone::UTC,
+            price_provider: PriceSource::Feed(JsonFeed {
+                url: "",
+                unit: PriceUnit::EUR_CENTS_PER_KWH,
+            }),
+            tariff: Tariff::default(),
             wifi: WifiConfig::default(),
             server: ServerConfig::default(),
         }
@@ -7,21 +7,193 @@ pub struct TomlConfig {
     wifi_psk: &'static str,
     #[default(300)]
     measurement_interval: u64,
+    // ADC conversions per measurement, taking the median
+    #[default(5)]
+    measurement_burst_samples: u8,
+    // "none", "exponential" or "kalman" smoothing across measurements
+    #[default("exponential")]
+    measurement_smoothing: &'static str,
+    // Weight of the newest measurement for exponential smoothing
+    #[default(0.3)]
+    measurement_smoothing_alpha: f32,
+    // Kalman filter variances, in degrees squared
+    #[default(0.001)]
+    measurement_process_variance: f32,
+    #[default(0.05)]
+    measurement_variance: f32,
+    // Measurements further from the filtered temperature are rejected,
+    // until more than `measurement_maximum_rejections` in a row
+    #[default(2.0)]
+    measurement_outlier_threshold: f32,
+    #[default(3)]
+    measurement_maximum_rejections: u8,
     #[default(16.0)]
     set_point_minimum_temperature: f32,
     #[default(18.0)]
     set_point_fallback_minimum_temperature: f32,
     #[default(25.0)]
     set_point_maximum_temperature: f32,
+    // Compared with the effective price, after the tariff below
     #[default(0.15)]
     set_point_maximum_price: f32,
+    // e.g. "EUR/kWh", "EUR cents/kWh" or "EUR/MWh"; in the currency of
+    // the prices
+    #[default("EUR/kWh")]
+    set_point_maximum_price_unit: &'static str,
+    // "absolute" or "relative" to the known price window
+    #[default("absolute")]
+    price_mode: &'static str,
+    // Whether the maximum price still turns heating off in relative mode
+    #[default(true)]
+    relative_price_hard_ceiling: bool,
+    #[default(0.5)]
+    set_point_upper_hysteresis: f32,
+    #[default(0.5)]
+    set_point_lower_hysteresis: f32,
+    // Comfort schedule blocks overriding the set point temperatures,
+    // written as "HH:MM minimum fallback_minimum maximum" separated by
+    // ';'; the configured temperatures apply when both are empty
+    #[default("")]
+    schedule_weekday: &'static str,
+    #[default("")]
+    schedule_weekend: &'static str,
+
+    // Heat during the cheapest hours of each day; disabled when zero
+    #[default(0)]
+    heating_hours_per_day: u8,
+    // Alternatively, the energy in kWh required each day, delivered by
+    // heating elements rated at `heater_power` kW
+    #[default(0.0)]
+    heating_energy_per_day: f32,
+    #[default(0.0)]
+    heater_power: f32,
+
+    // Charge the floor during cheap hours ahead of expensive hours
+    #[default(false)]
+    preheat: bool,
+    // Thermal model of the floor; degrees per hour heating rate,
+    // fraction of the difference to ambient lost per hour, and the
+    // temperature the floor settles to without heating
+    #[default(1.0)]
+    thermal_heating_rate: f32,
+    #[default(0.05)]
+    thermal_cooling_rate: f32,
+    #[default(15.0)]
+    thermal_ambient_temperature: f32,
+    // Weight of older samples when learning the thermal model
+    #[default(0.999)]
+    thermal_forgetting_factor: f32,
+
+    // "beta" or "steinhart-hart"
+    #[default("beta")]
+    thermistor_model: &'static str,
+    // Fixed resistor in series with the thermistor
+    #[default(12000.0)]
+    thermistor_reference_resistance: f32,
+    // Beta model; resistance at the nominal temperature
+    #[default(3750.0)]
+    thermistor_beta: f32,
+    #[default(12000.0)]
+    thermistor_nominal_resistance: f32,
+    #[default(25.0)]
+    thermistor_nominal_temperature: f32,
+    // Steinhart-Hart model coefficients
+    #[default(0.0)]
+    thermistor_a: f32,
+    #[default(0.0)]
+    thermistor_b: f32,
+    #[default(0.0)]
+    thermistor_c: f32,
+
+    // Fraction of each hour heating is on while the temperature sensor
+    // is unreadable, after `fallback_failure_threshold` failed
+    // measurements in a row; optionally shifted toward cheap hours
+    #[default(0.3)]
+    fallback_duty_cycle: f32,
+    #[default(true)]
+    fallback_price_biased: bool,
+    #[default(3)]
+    fallback_failure_threshold: u8,
 
+    // "hysteresis" switches on and off at the set points; "pid" heats
+    // for a fraction of each `pwm_cycle_period` seconds from a PID
+    // controller on the target temperature
+    #[default("hysteresis")]
+    control_strategy: &'static str,
+    #[default(1800)]
+    pwm_cycle_period: u64,
+    // At least the relay minimum on and off times below
+    #[default(600)]
+    pwm_minimum_pulse: u64,
+    // On-fraction per degree, per degree hour and per degree per hour
+    #[default(0.5)]
+    pid_proportional_gain: f32,
+    #[default(0.1)]
+    pid_integral_gain: f32,
+    #[default(0.0)]
+    pid_derivative_gain: f32,
+
+    #[default(600)]
+    relay_minimum_on_time: u64,
+    #[default(600)]
+    relay_minimum_off_time: u64,
+
+    // "feed" for the JSON API below, or "entsoe", "nordpool", "awattar"
+    // or "octopus"
+    #[default("feed")]
+    price_provider: &'static str,
+    // Time zone of the market day, as a POSIX TZ string; Octopus
+    // tariffs follow UK time, "GMT0BST,M3.5.0/1,M10.5.0"
+    #[default("CET-1CEST,M3.5.0,M10.5.0/3")]
+    price_market_timezone: &'static str,
     #[default("")]
     electricity_price_api: &'static str,
+    // Unit of the prices in the JSON API
+    #[default("EUR cents/kWh")]
+    electricity_price_unit: &'static str,
+    // Tariff applied to spot prices before any decisions; fees are in
+    // `tariff_price_unit` and the rest in percent:
+    // (spot * (1 + markup) + fixed fee + network fee) * (1 + VAT)
+    #[default(0.0)]
+    tariff_markup_percent: f32,
+    // e.g. energy tax and supplier fees
+    #[default(0.0)]
+    tariff_fixed_fee: f32,
+    // Time of use network fees on the local clock, e.g.
+    // "mon-fri 07:00 4.0; mon-fri 22:00 2.0; sat,sun 00:00 2.0"
+    #[default("")]
+    tariff_network_fees: &'static str,
+    #[default(0.0)]
+    tariff_vat_percent: f32,
+    // Unit of the fixed and network fees
+    #[default("EUR cents/kWh")]
+    tariff_price_unit: &'static str,
+    #[default("")]
+    entsoe_token: &'static str,
+    // EIC code of the bidding zone
+    #[default("10YFI-1--------U")]
+    entsoe_area: &'static str,
+    #[default("FI")]
+    nordpool_area: &'static str,
+    #[default("EUR")]
+    nordpool_currency: &'static str,
+    // "at" or "de"
+    #[default("at")]
+    awattar_country: &'static str,
+    // e.g. "AGILE-24-10-01" and "E-1R-AGILE-24-10-01-C"
+    #[default("")]
+    octopus_product: &'static str,
+    #[default("")]
+    octopus_tariff: &'static str,
 
     #[default("")]
     metrics_url: &'static str,
 
     #[default("pool.ntp.org")]
     ntp_server: &'static str,
+
+    // Local time zone for the comfort schedule, as a POSIX TZ string,
+    // e.g. "CET-1CEST,M3.5.0,M10.5.0/3"; prices are always in UTC
+    #[default("UTC0")]
+    timezone: &'static str,
 }
@@ -0,0 +1,42 @@
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+
+use control::{PowerState, SetPointReason, Temperature};
+
+// Why the heating output differs from the power of the set point
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub enum Adjustment {
+    // Time-proportional heating switches within each cycle
+    Modulated,
+    // The relay is held for its minimum on or off time
+    RelayHold,
+}
+
+// The most recent heating decision and why it was made
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Decision {
+    // State the heating output was left in
+    pub power: PowerState,
+    pub target_temperature: Temperature,
+    // None while the temperature sensor is faulty
+    pub measured_temperature: Option<Temperature>,
+    // Reason for the set point
+    pub reason: SetPointReason,
+    // None when the output follows the set point
+    pub adjustment: Option<Adjustment>,
+}
+
+#[derive(Clone, Default)]
+pub struct SharedDecision {
+    decision: Arc<Mutex<Option<Decision>>>,
+}
+
+impl SharedDecision {
+    pub fn get(&self) -> Option<Decision> {
+        *self.decision.lock().unwrap()
+    }
+
+    pub fn set(&self, decision: Decision) {
+        *self.decision.lock().unwrap() = Some(decision);
+    }
+}
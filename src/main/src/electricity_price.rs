@@ -1,144 +1,178 @@
 use anyhow::Result;
 use log::*;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use std::fmt;
 use std::sync::{Arc, Mutex};
-use time::{ext::NumericalDuration, PrimitiveDateTime, Time};
+use time::{ext::NumericalDuration, PrimitiveDateTime};
 
 use crate::http;
 use crate::StatusEvent;
-use control::ElectricityPrice;
-
-#[allow(dead_code)]
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct HourlyElectricityPrice {
-    // Validity of this data, in UTC
-    pub valid_from: PrimitiveDateTime,
-    pub valid_until: PrimitiveDateTime,
-    // Map of time at start of period, in UTC
-    // On the date YYYY-MM-DD, when the prior day is YY-MM-dd,
-    // YYYY-MM-ddT22:00:00Z (the day before) is the first item in the map
-    // YYYY-MM-DDT21:00:00Z is the final item in the map
-    pub hourly_price: HashMap<PrimitiveDateTime, ElectricityPrice>,
+use control::{
+    Clock, CoreConfig, ElectricityPrice, HeatingBudget, HeatingPlan, MultiDayElectricityPrice,
+    PreheatPlan, PriceFetcher, PriceSeries, PriceSlot, PriceSource, PriceUpdate, SetPoint, Tariff,
+    Temperature, ThermalModel, TimeZone,
+};
+
+// The ESP system clock, synchronised over SNTP
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Result<PrimitiveDateTime> {
+        Ok(crate::utils::time::get_utc_datetime()?)
+    }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize, Default)]
-pub struct MultiDayElectricityPrice {
-    today: Option<HourlyElectricityPrice>,
-    tomorrow: Option<HourlyElectricityPrice>,
-}
+pub struct HttpFetcher;
 
-impl MultiDayElectricityPrice {
-    fn fetch(url: &str, now: PrimitiveDateTime) -> Result<MultiDayElectricityPrice> {
-        let json = http::get(url)?;
-        let data: MultiDayElectricityPrice = serde_json::from_str(&json)?;
+impl PriceFetcher for HttpFetcher {
+    type Body = String;
 
-        if let Some(tomorrow) = &data.tomorrow {
-            if now >= tomorrow.valid_until {
-                error!("Received stale electricity price data!");
-                return Ok(MultiDayElectricityPrice::default());
-            }
-            if now >= tomorrow.valid_from && now < tomorrow.valid_until {
-                warn!("MultiDayElectricityPrice.tomorrow appears to be today's data");
-                let new_data = MultiDayElectricityPrice {
-                    today: Some(tomorrow.clone()),
-                    tomorrow: None,
-                };
-                return Ok(new_data);
-            }
-        }
-
-        Ok(data)
+    fn fetch(&mut self, url: &str) -> Result<String> {
+        info!("Updating electricity price data");
+        http::get(url)
     }
 }
 
+// Planning is in whole hours; shorter intervals are averaged over each
+// hour
+fn hourly_slots(day: &PriceSeries) -> Vec<PriceSlot> {
+    day.hourly().slots().copied().collect()
+}
+
 #[derive(Clone)]
 pub struct SharedElectricityPrice {
+    // Spot prices as fetched
+    spot_prices: Arc<Mutex<MultiDayElectricityPrice>>,
+    // Spot prices with the tariff applied, which decisions are made on
     prices: Arc<Mutex<MultiDayElectricityPrice>>,
+    provider: PriceSource,
+    tariff: Tariff,
+    // Network fees follow the local clock
+    timezone: TimeZone,
 }
 
 impl SharedElectricityPrice {
-    pub fn fetch(url: &str, now: PrimitiveDateTime) -> Result<SharedElectricityPrice> {
-        let data = MultiDayElectricityPrice::fetch(url, now)?;
+    pub fn fetch(
+        provider: PriceSource,
+        tariff: Tariff,
+        timezone: TimeZone,
+    ) -> Result<SharedElectricityPrice> {
         let shared_data = SharedElectricityPrice {
-            prices: Arc::new(Mutex::new(data)),
+            spot_prices: Arc::new(Mutex::new(MultiDayElectricityPrice::default())),
+            prices: Arc::new(Mutex::new(MultiDayElectricityPrice::default())),
+            provider,
+            tariff,
+            timezone,
         };
+        // Without prices the heating runs on the fallback until the next
+        // update succeeds
+        if let Err(err) = shared_data.maybe_update() {
+            error!("Failed to fetch electricity prices: {:?}", err);
+        }
         Ok(shared_data)
     }
 
     pub fn current_price(&self) -> Option<ElectricityPrice> {
-        let now = crate::utils::time::get_datetime().expect("Failed to get tiem");
-        let key_time = Time::from_hms(now.time().hour(), 0, 0).expect("Failed to construct time");
-        let key = PrimitiveDateTime::new(now.date(), key_time);
+        let now = crate::utils::time::get_utc_datetime().expect("Failed to get time");
 
         let prices = self.prices.lock().unwrap();
-        let today = prices.today.clone();
-        let tomorrow = &prices.tomorrow.clone();
+        prices.price_at(now)
+    }
 
-        if let Some(today) = today {
-            if let Some(price) = today.hourly_price.get(&key) {
-                return Some(*price);
-            }
+    // The spot price, before fees and taxes
+    pub fn current_spot_price(&self) -> Option<ElectricityPrice> {
+        let now = crate::utils::time::get_utc_datetime().expect("Failed to get time");
+
+        let prices = self.spot_prices.lock().unwrap();
+        prices.price_at(now)
+    }
+
+    // All known prices, today and tomorrow
+    pub fn known_prices(&self) -> Vec<ElectricityPrice> {
+        let prices = self.prices.lock().unwrap();
+        prices.known_prices().collect()
+    }
+
+    // Plan heating across the known price curve; requires at least
+    // today's prices
+    pub fn heating_plan(&self, config: &CoreConfig, budget: HeatingBudget) -> Option<HeatingPlan> {
+        let prices = self.prices.lock().unwrap();
+        prices.today()?;
+
+        let mut plan = HeatingPlan::new();
+        for day in prices.days() {
+            plan.add_cheapest_hours(config, budget, &hourly_slots(day));
         }
-        if let Some(tomorrow) = tomorrow {
-            if let Some(price) = tomorrow.hourly_price.get(&key) {
-                return Some(*price);
-            }
+        Some(plan)
+    }
+
+    pub fn planned_set_point(
+        &self,
+        config: &CoreConfig,
+        budget: HeatingBudget,
+    ) -> Option<SetPoint> {
+        let plan = self.heating_plan(config, budget)?;
+        debug!("Heating plan {:?}", plan);
+
+        let now = crate::utils::time::get_utc_datetime().expect("Failed to get time");
+        plan.set_point_at(now)
+    }
+
+    // Known prices from the start of the current hour onwards
+    fn upcoming_price_slots(&self, now: PrimitiveDateTime) -> Vec<PriceSlot> {
+        let prices = self.prices.lock().unwrap();
+        prices
+            .days()
+            .flat_map(hourly_slots)
+            .filter(|slot| slot.start + 1.hours() > now)
+            .collect()
+    }
+
+    pub fn preheat_set_point(
+        &self,
+        config: &CoreConfig,
+        model: &ThermalModel,
+        temperature: Temperature,
+    ) -> Option<SetPoint> {
+        let now = crate::utils::time::get_utc_datetime().expect("Failed to get time");
+        let slots = self.upcoming_price_slots(now);
+        if slots.is_empty() {
+            return None;
         }
-        None
+
+        let plan = PreheatPlan::plan(config, model, temperature, &slots);
+        for slot in plan.slots() {
+            debug!(
+                "Preheat plan {:?} {:?} at {:?}, predicted {:?}",
+                slot.start, slot.action, slot.price, slot.predicted_temperature
+            );
+        }
+        plan.set_point_at(config, now)
     }
 
     pub fn status(&self) -> Option<StatusEvent> {
         let prices = self.prices.lock().unwrap();
 
-        if prices.today.is_none() {
+        if prices.today().is_none() {
             return Some(StatusEvent::MissingData);
         }
         None
     }
 
-    pub fn maybe_update(&self, url: &str) -> Result<()> {
-        let now = crate::utils::time::get_datetime()?;
-
-        let mut prices = self.prices.lock().unwrap();
-        let today = prices.today.clone();
-        let tomorrow = &prices.tomorrow.clone();
-
-        let mut do_update = || -> Result<()> {
-            info!("Updating electricity price data");
-            let data = MultiDayElectricityPrice::fetch(url, now)?;
-            prices.today = data.today;
-            prices.tomorrow = data.tomorrow;
-            Ok(())
-        };
-
-        match today {
-            Some(today) => {
-                if now >= today.valid_from && now < today.valid_until && tomorrow.is_some() {
-                    info!("Electricity price data is current");
-                    // no need to update; we have tomorrow's data and we're still in today
-                    return Ok(());
-                }
-
-                match tomorrow {
-                    Some(tomorrow) => {
-                        if now >= tomorrow.valid_from && now < tomorrow.valid_until {
-                            info!("Promoting tomorrow's data to being in use");
-                            prices.today = Some(tomorrow.clone());
-                            prices.tomorrow = None;
-                        }
-                    }
-                    None => {
-                        if now > today.valid_until - 3.hours() {
-                            do_update()?;
-                        } else {
-                            info!("Current prices are valid and it's too early to fetch tomorrow");
-                        }
-                    }
-                }
+    pub fn maybe_update(&self) -> Result<()> {
+        let mut spot_prices = self.spot_prices.lock().unwrap();
+        let update = spot_prices.maybe_update(&SystemClock, &self.provider, &mut HttpFetcher)?;
+        *self.prices.lock().unwrap() = self.tariff.effective_prices(&spot_prices, &self.timezone);
+        match update {
+            PriceUpdate::Current => info!("Electricity price data is current"),
+            PriceUpdate::TooEarly => {
+                info!("Current prices are valid and it's too early to fetch tomorrow")
+            }
+            PriceUpdate::Promoted => info!("Promoting tomorrow's data to being in use"),
+            PriceUpdate::Fetched => {}
+            PriceUpdate::FetchedTomorrowAsToday => {
+                warn!("MultiDayElectricityPrice.tomorrow appears to be today's data")
             }
-            None => do_update()?,
+            PriceUpdate::FetchedStale => error!("Received stale electricity price data!"),
         }
         Ok(())
     }
@@ -146,9 +180,12 @@ impl SharedElectricityPrice {
 
 impl fmt::Debug for SharedElectricityPrice {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let spot_prices = self.spot_prices.lock().unwrap();
         let prices = self.prices.lock().unwrap();
         f.debug_struct("SharedElectricityPrice")
+            .field("spot_prices", &spot_prices)
             .field("prices", &prices)
+            .field("tariff", &self.tariff)
             .finish()
     }
 }
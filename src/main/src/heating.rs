@@ -1,10 +1,16 @@
 use anyhow::Result;
+use core::time::Duration;
 use esp_idf_svc::hal::gpio::{AnyOutputPin, Output, PinDriver};
 use log::*;
 
 mod event;
 
-use control::{CoreConfig, ElectricityPrice, PowerState, SetPoint, Temperature};
+use control::{
+    CoreConfig, ElectricityPrice, PowerState, RelayProtection, SetPoint, SetPointReason,
+    SwitchPriority, Temperature, TimeProportional,
+};
+
+use crate::decision::Adjustment;
 
 #[derive(Debug, Clone, Copy)]
 pub enum HeatingPower {
@@ -16,6 +22,13 @@ pub enum HeatingPower {
 pub struct HeatingEvent {
     power: HeatingPower,
     temperature: Temperature,
+    reason: SetPointReason,
+    priority: SwitchPriority,
+    // None while the temperature sensor is faulty
+    measured_temperature: Option<Temperature>,
+    // Whether time-proportional heating changed the power from that of
+    // the set point
+    modulated: bool,
 }
 
 impl From<PowerState> for HeatingPower {
@@ -27,43 +40,140 @@ impl From<PowerState> for HeatingPower {
     }
 }
 
-impl From<SetPoint> for HeatingEvent {
-    fn from(state: SetPoint) -> HeatingEvent {
+impl From<HeatingPower> for PowerState {
+    fn from(power: HeatingPower) -> PowerState {
+        match power {
+            HeatingPower::TurnOn => PowerState::On,
+            HeatingPower::TurnOff => PowerState::Off,
+        }
+    }
+}
+
+impl HeatingEvent {
+    pub fn new(state: SetPoint, measured_temperature: Temperature) -> HeatingEvent {
         HeatingEvent {
             power: HeatingPower::from(state.power),
             temperature: state.temperature,
+            reason: state.reason,
+            priority: SwitchPriority::Normal,
+            measured_temperature: Some(measured_temperature),
+            modulated: false,
         }
     }
-}
 
-impl HeatingEvent {
+    // Time-proportional heating while the sensor is unreadable
+    pub fn sensor_fallback(state: SetPoint) -> HeatingEvent {
+        HeatingEvent {
+            power: HeatingPower::from(state.power),
+            temperature: state.temperature,
+            reason: state.reason,
+            priority: SwitchPriority::Normal,
+            measured_temperature: None,
+            modulated: false,
+        }
+    }
+
+    // Heating is switched off immediately on a sensor fault
+    pub fn sensor_fault(config: &CoreConfig) -> HeatingEvent {
+        let state = SetPoint::from_sensor_fault(config);
+        HeatingEvent {
+            power: HeatingPower::from(state.power),
+            temperature: state.temperature,
+            reason: state.reason,
+            priority: SwitchPriority::Safety,
+            measured_temperature: None,
+            modulated: false,
+        }
+    }
+
+    // Spread heating over each cycle in proportion to the distance from
+    // the target temperature; sensor faults and over temperature are
+    // switched as is
+    pub fn modulate(&mut self, controller: &mut TimeProportional, now: Duration) {
+        let Some(temperature) = self.measured_temperature else {
+            return;
+        };
+        if self.priority == SwitchPriority::Safety {
+            return;
+        }
+
+        let set_point = SetPoint {
+            power: PowerState::from(self.power),
+            temperature: self.temperature,
+            reason: self.reason,
+        };
+        let power = controller.update(now, temperature, &set_point);
+        self.modulated = power != set_point.power;
+        self.power = HeatingPower::from(power);
+    }
+
+    // Why `applied`, the state the relay was left in, differs from the
+    // power of the set point
+    pub fn adjustment(&self, applied: HeatingPower) -> Option<Adjustment> {
+        if PowerState::from(applied) != PowerState::from(self.power) {
+            Some(Adjustment::RelayHold)
+        } else if self.modulated {
+            Some(Adjustment::Modulated)
+        } else {
+            None
+        }
+    }
+
+    pub fn temperature(&self) -> Temperature {
+        self.temperature
+    }
+
+    pub fn reason(&self) -> SetPointReason {
+        self.reason
+    }
+
+    pub fn measured_temperature(&self) -> Option<Temperature> {
+        self.measured_temperature
+    }
+
+    pub fn is_sensor_fault(&self) -> bool {
+        self.measured_temperature.is_none()
+    }
+
     pub fn switch_heating(
         self,
         enable: &mut PinDriver<AnyOutputPin, Output>,
+        protection: &mut RelayProtection,
+        now: Duration,
     ) -> Result<HeatingPower> {
-        match (self.power, enable.is_set_high()) {
+        let requested = PowerState::from(self.power);
+        let power = HeatingPower::from(protection.request(now, requested, self.priority));
+        if PowerState::from(power) != requested {
+            info!(
+                "Heating output held in state {:?} for another {:?}",
+                power,
+                protection.remaining_hold(now)
+            );
+        }
+
+        match (power, enable.is_set_high()) {
             (HeatingPower::TurnOn, false) => {
                 info!(
-                    "Turning on heating output; target temperature {:?}",
-                    self.temperature
+                    "Turning on heating output; target temperature {} ({:?})",
+                    self.temperature, self.reason
                 );
                 enable.set_high()?;
             }
             (HeatingPower::TurnOff, true) => {
                 info!(
-                    "Turning on heating output; target temperature {:?}",
-                    self.temperature
+                    "Turning off heating output; target temperature {} ({:?})",
+                    self.temperature, self.reason
                 );
                 enable.set_low()?;
             }
             (desired_state, _) => {
                 info!(
-                    "Heating is already in desired state {:?}; target temperature {:?}",
-                    desired_state, self.temperature
+                    "Heating is already in desired state {:?}; target temperature {} ({:?})",
+                    desired_state, self.temperature, self.reason
                 );
             }
         }
-        Ok(self.power)
+        Ok(power)
     }
 }
 
@@ -71,8 +181,21 @@ pub fn get_next_desired_state(
     config: &CoreConfig,
     temperature: Temperature,
     price: Option<ElectricityPrice>,
+    price_window: &[ElectricityPrice],
+    planned: Option<SetPoint>,
+    previous_power: PowerState,
 ) -> HeatingEvent {
-    let next_state = SetPoint::from_current_state(config, temperature, price);
+    let next_state = match planned {
+        Some(planned) => SetPoint::from_plan(config, temperature, planned, previous_power),
+        None => {
+            SetPoint::from_price_window(config, temperature, price, price_window, previous_power)
+        }
+    };
 
-    HeatingEvent::from(next_state)
+    let mut event = HeatingEvent::new(next_state, temperature);
+    // Over temperature bypasses the relay's minimum on time
+    if config.is_above(temperature, config.maximum_temperature, previous_power) {
+        event.priority = SwitchPriority::Safety;
+    }
+    event
 }
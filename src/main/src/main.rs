@@ -6,6 +6,7 @@ use esp_idf_svc::{
         gpio::{AnyOutputPin, PinDriver},
         prelude::{FromValueType, Peripherals},
     },
+    nvs::EspDefaultNvsPartition,
     sntp::{EspSntp, SntpConf, SyncStatus},
     sys::{esp, esp_wifi_connect},
     timer::EspTaskTimerService,
@@ -14,19 +15,31 @@ use esp_idf_svc::{
 use log::*;
 
 mod adc;
+mod away;
+mod calibration;
 mod config;
+mod decision;
 mod electricity_price;
 mod heating;
 mod http;
 mod i2c;
 mod measurement;
+mod relay;
 mod rgbled;
+mod server;
 mod status;
+mod storage;
+mod thermal;
 mod trigger;
 mod utils;
 mod wifi;
 
 use config::Config;
+use control::{
+    price_scale_mismatch, AwayState, ControlStrategy, MeasurementFilter, PowerState, PriceProvider,
+    SensorFallback, SensorFault, TimeProportional,
+};
+use decision::Decision;
 use heating::HeatingEvent;
 use measurement::MeasurementEvent;
 use rgbled::{RGB8, WS2812RMT};
@@ -56,6 +69,15 @@ fn main() -> Result<()> {
 
     let config = Config::read()?;
 
+    let storage = storage::SharedStorage::new(EspDefaultNvsPartition::take()?, "heating")?;
+    let thermal_estimator =
+        thermal::SharedThermalEstimator::load(storage.clone(), config.thermal_forgetting_factor)?;
+    let away_mode = away::SharedAwayMode::load(storage.clone())?;
+    let calibration = calibration::SharedCalibration::load(storage.clone())?;
+    let last_decision = decision::SharedDecision::default();
+    // Heating is switched off during initialization
+    let relay = relay::SharedRelay::new(config.relay, PowerState::Off);
+
     let i2c = peripherals.i2c0;
     let sda = peripherals.pins.gpio6;
     let scl = peripherals.pins.gpio7;
@@ -76,14 +98,31 @@ fn main() -> Result<()> {
         // Avoid move of sysloop into closure
         let localloop = sysloop.clone();
         let i2c_driver = shared_i2c_driver.clone();
+        let calibration = calibration.clone();
+        let configured_thermistor = config.thermistor;
+        let burst_samples = config.burst_samples;
+        let mut filter = MeasurementFilter::new(config.filter);
         sysloop.subscribe::<TriggerEvent, _>(move |_| {
+            let thermistor = calibration.apply(&configured_thermistor);
             localloop
                 .post::<StatusEvent>(&StatusEvent::Measuring, delay::BLOCK)
                 .expect("Failed to post status");
             let mut driver = i2c_driver.lock();
-            let temperature =
-                MeasurementEvent::take_temperature_reading(&mut thermistor_enable, &mut driver)
-                    .expect("Failed to take temperature reading");
+            let temperature = match MeasurementEvent::take_temperature_reading(
+                &mut thermistor_enable,
+                &mut driver,
+                &thermistor,
+                burst_samples,
+            ) {
+                Ok(MeasurementEvent::Measurement(value)) => {
+                    MeasurementEvent::Measurement(filter.update(value))
+                }
+                Ok(fault) => fault,
+                Err(err) => {
+                    error!("Failed to take temperature reading: {:?}", err);
+                    MeasurementEvent::SensorFault(SensorFault::Unreadable)
+                }
+            };
             localloop
                 .post::<MeasurementEvent>(&temperature, delay::BLOCK)
                 .expect("Failed to post measurement");
@@ -121,19 +160,161 @@ fn main() -> Result<()> {
 
     wait_for_sntp(&sntp)?;
 
-    let now = utils::time::get_datetime()?;
-    let electricity_prices =
-        electricity_price::SharedElectricityPrice::fetch(config.server.electricity_price_api, now)?;
+    let _server = server::start(
+        away_mode.clone(),
+        calibration.clone(),
+        config.thermistor,
+        last_decision.clone(),
+        config.set_points,
+    )?;
+
+    let electricity_prices = electricity_price::SharedElectricityPrice::fetch(
+        config.price_provider,
+        config.tariff,
+        config.timezone,
+    )?;
+    // The maximum price and the fetched prices are easily 100 times apart
+    // when a unit is configured wrongly
+    if let Some(typical) = price_scale_mismatch(
+        config.set_points.maximum_price,
+        electricity_prices.known_prices(),
+    ) {
+        warn!(
+            "Maximum price {} is far from typical prices of {} hundredths of {} per kWh; \
+             check the configured price units",
+            config.set_points.maximum_price,
+            typical,
+            config.price_provider.unit().currency
+        );
+    }
+
+    let timer_service = EspTaskTimerService::new()?;
+    // Measure again at the next on/off transition of time-proportional
+    // heating when it falls between regular measurements
+    let transition_timer = {
+        // Avoid move of sysloop into closure
+        let localloop = sysloop.clone();
+        timer_service.timer(move || {
+            localloop
+                .post::<TriggerEvent>(&TriggerEvent, delay::BLOCK)
+                .expect("Failed to post trigger");
+        })?
+    };
 
     let _measurement_handler = {
         // Avoid move of sysloop into closure
         let localloop = sysloop.clone();
-        let set_points = config.set_points;
+        let configured_set_points = config.set_points;
+        let schedule = config.schedule;
+        let timezone = config.timezone;
+        let heating_budget = config.heating_budget;
+        let preheat = config.preheat;
+        let configured_model = config.thermal_model;
+        let local_thermal = thermal_estimator.clone();
+        let local_away = away_mode.clone();
         let local_prices = electricity_prices.clone();
+        let mut sensor_fallback = SensorFallback::new(config.fallback);
+        let mut time_proportional = match config.strategy {
+            ControlStrategy::Hysteresis => None,
+            ControlStrategy::Pid(pwm) => Some(TimeProportional::new(pwm)),
+        };
+        let measurement_interval = config.measurement_interval;
+        let local_relay = relay.clone();
         sysloop.subscribe::<MeasurementEvent, _>(move |event| {
+            let clock = utils::time::get_clock_time(&timezone).ok();
+            // Price based control operates within the active block of
+            // the comfort schedule, which follows the local clock
+            let mut set_points = match clock {
+                Some(clock) => schedule.apply(&configured_set_points, clock.local),
+                None => configured_set_points,
+            };
             let price = local_prices.current_price();
-            warn!("current price {:?}", price);
-            if let Some(heating_event) = event.handle(&set_points, price) {
+            warn!(
+                "current price {:?}, spot price {:?}",
+                price,
+                local_prices.current_spot_price()
+            );
+            // Prefer the learned thermal model once available
+            let thermal_model = local_thermal.model().unwrap_or(configured_model);
+
+            let mut planned = None;
+            let mut holding = None;
+            // The return time is on the local clock; away mode applies
+            // whether or not the temperature could be read
+            if let Some((mut away, clock)) = local_away.get().zip(clock) {
+                let was_returning = away.returning;
+                let temperature = event.value().ok();
+                match away.state(&set_points, &thermal_model, temperature, clock.local) {
+                    AwayState::Away => {
+                        set_points = away.set_points(&set_points);
+                        planned = Some(away.set_point());
+                        holding = Some(away);
+                    }
+                    AwayState::Returning => {
+                        info!("Heating ahead of return from away mode");
+                        // Keep heating back after a reboot
+                        if !was_returning {
+                            if let Err(err) = local_away.set(Some(away)) {
+                                error!("Failed to store away mode: {:?}", err);
+                            }
+                        }
+                    }
+                    AwayState::Home => {
+                        if let Err(err) = local_away.set(None) {
+                            error!("Failed to clear away mode: {:?}", err);
+                        }
+                    }
+                }
+            }
+
+            // Follow the plan over the full price curve when available,
+            // with charging ahead of expensive hours taking priority
+            let planned = planned.or_else(|| {
+                preheat
+                    .then_some(thermal_model)
+                    .zip(event.value().ok())
+                    .and_then(|(model, temperature)| {
+                        local_prices.preheat_set_point(&set_points, &model, temperature)
+                    })
+                    .or_else(|| {
+                        heating_budget
+                            .and_then(|budget| local_prices.planned_set_point(&set_points, budget))
+                    })
+            });
+            let price_window = local_prices.known_prices();
+
+            if sensor_fallback.record(event.value().is_ok()) {
+                warn!("Temperature sensor unreadable, heating on a fallback duty cycle");
+            }
+            let fallback = clock.and_then(|clock| match &holding {
+                Some(away) => sensor_fallback.away_set_point(away, &thermal_model, clock.utc),
+                None => sensor_fallback.set_point(&set_points, clock.utc, price, &price_window),
+            });
+
+            // Thresholds follow the state the relay is actually in, which
+            // differs from the previous request while the relay is held
+            let previous_power = local_relay.state();
+            if let Some(mut heating_event) = event.handle(
+                &set_points,
+                price,
+                &price_window,
+                planned,
+                fallback,
+                previous_power,
+            ) {
+                if let Some(controller) = time_proportional.as_mut() {
+                    let uptime = utils::time::get_uptime();
+                    heating_event.modulate(controller, uptime);
+                    if let Some(cycle) = controller.cycle() {
+                        let delay = cycle.next_transition(uptime).saturating_sub(uptime);
+                        if delay < measurement_interval {
+                            transition_timer
+                                .cancel()
+                                .and_then(|_| transition_timer.after(delay))
+                                .expect("Failed to schedule heating transition");
+                        }
+                    }
+                }
                 localloop
                     .post::<HeatingEvent>(&heating_event, delay::BLOCK)
                     .expect("Failed to post heating event event");
@@ -147,12 +328,34 @@ fn main() -> Result<()> {
         let local_prices = electricity_prices.clone();
         sysloop.subscribe::<HeatingEvent, _>(move |event| {
             info!("Received event {:?}", event);
+            let now = utils::time::get_uptime();
+            let mut relay_protection = relay.lock();
+            // The relay has been in its current state since the previous
+            // measurement
+            if let Some(temperature) = event.measured_temperature() {
+                if let Err(err) =
+                    thermal_estimator.update(now, temperature, relay_protection.state())
+                {
+                    error!("Failed to update thermal model estimate: {:?}", err);
+                }
+            }
             let power_state = event
-                .switch_heating(&mut heating_enable)
+                .switch_heating(&mut heating_enable, &mut relay_protection, now)
                 .expect("Failed to switch heating");
-            let status = match local_prices.status() {
-                Some(status) => status,
-                None => StatusEvent::from(power_state),
+            last_decision.set(Decision {
+                power: PowerState::from(power_state),
+                target_temperature: event.temperature(),
+                measured_temperature: event.measured_temperature(),
+                reason: event.reason(),
+                adjustment: event.adjustment(power_state),
+            });
+            let status = if event.is_sensor_fault() {
+                StatusEvent::SensorFault
+            } else {
+                match local_prices.status() {
+                    Some(status) => status,
+                    None => StatusEvent::from(power_state),
+                }
             };
             localloop
                 .post::<StatusEvent>(&status, delay::BLOCK)
@@ -160,14 +363,15 @@ fn main() -> Result<()> {
         })?
     };
 
-    let timer_service = EspTaskTimerService::new()?;
     let measurement_timer = {
         // Avoid move of sysloop into closure
         let localloop = sysloop.clone();
         timer_service.timer(move || {
-            electricity_prices
-                .maybe_update(config.server.electricity_price_api)
-                .expect("Failed to update");
+            // Providers fail routinely, e.g. before prices are published;
+            // the previous prices are kept until a later update succeeds
+            if let Err(err) = electricity_prices.maybe_update() {
+                error!("Failed to update electricity prices: {:?}", err);
+            }
             localloop
                 .post::<TriggerEvent>(&TriggerEvent, delay::BLOCK)
                 .expect("Failed to post trigger");
@@ -193,7 +397,7 @@ fn wait_for_sntp(sntp: &EspSntp) -> Result<()> {
             break;
         }
     }
-    let now = utils::time::get_datetime()?;
-    info!("Time sync completed at {:?}", now);
+    let now = utils::time::get_utc_datetime()?;
+    info!("Time sync completed at {:?} UTC", now);
     Ok(())
 }
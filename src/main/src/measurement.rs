@@ -1,14 +1,15 @@
-use anyhow::Result;
-use esp_idf_svc::{
-    hal::{
-        delay,
-        gpio::{AnyOutputPin, Output, PinDriver},
-        i2c::I2cDriver,
-    },
+use anyhow::{anyhow, Result};
+use esp_idf_svc::hal::{
+    delay,
+    gpio::{AnyOutputPin, Output, PinDriver},
+    i2c::I2cDriver,
 };
 use log::*;
 
-use control::{temperature_from_voltage, CoreConfig, ElectricityPrice, Temperature};
+use control::{
+    median, temperature_from_voltage, CoreConfig, ElectricityPrice, PowerState, SensorFault,
+    SetPoint, Temperature, ThermistorProperties, MAX_BURST_SAMPLES,
+};
 
 mod event;
 
@@ -18,12 +19,15 @@ use crate::heating::{get_next_desired_state, HeatingEvent};
 #[derive(Copy, Clone, Debug)]
 pub enum MeasurementEvent {
     Measurement(Temperature),
+    SensorFault(SensorFault),
 }
 
 impl MeasurementEvent {
     pub fn take_temperature_reading(
         enable: &mut PinDriver<AnyOutputPin, Output>,
         i2c_driver: &mut I2cDriver,
+        thermistor: &ThermistorProperties,
+        burst_samples: usize,
     ) -> Result<MeasurementEvent> {
         let reference_adc_config = adc::AdcConfig {
             input: adc::AnalogInput::SingleEndedAni0,
@@ -45,14 +49,31 @@ impl MeasurementEvent {
             ..reference_adc_config
         };
 
-        let result = adc::read(i2c_driver, &adc_config);
+        // Median of a burst of conversions rejects single noisy samples
+        let mut samples = [0.0; MAX_BURST_SAMPLES];
+        let burst_samples = burst_samples.clamp(1, MAX_BURST_SAMPLES);
+        let result = samples[..burst_samples]
+            .iter_mut()
+            .try_for_each(|sample| -> Result<()> {
+                *sample = adc::read(i2c_driver, &adc_config)?;
+                Ok(())
+            });
         // Disable current through thermistor before checking error
         enable.set_low()?;
 
-        let thermistor_voltage = result?;
+        result?;
+        let thermistor_voltage = median(&mut samples[..burst_samples]).unwrap_or_default();
+        // Logged for thermistor calibration against a reference thermometer
+        info!(
+            "Thermistor voltage ratio {}",
+            thermistor_voltage / reference_voltage
+        );
 
-        let temperature = temperature_from_voltage(reference_voltage, thermistor_voltage);
-        let event = MeasurementEvent::Measurement(Temperature::new(temperature));
+        let event =
+            match temperature_from_voltage(thermistor, reference_voltage, thermistor_voltage) {
+                Ok(temperature) => MeasurementEvent::Measurement(Temperature::new(temperature)),
+                Err(fault) => MeasurementEvent::SensorFault(fault),
+            };
 
         Ok(event)
     }
@@ -60,6 +81,7 @@ impl MeasurementEvent {
     pub fn value(self) -> Result<Temperature> {
         match self {
             MeasurementEvent::Measurement(value) => Ok(value),
+            MeasurementEvent::SensorFault(fault) => Err(anyhow!("Sensor fault {:?}", fault)),
         }
     }
 
@@ -67,13 +89,27 @@ impl MeasurementEvent {
         self,
         set_points: &CoreConfig,
         price: Option<ElectricityPrice>,
+        price_window: &[ElectricityPrice],
+        planned: Option<SetPoint>,
+        fallback: Option<SetPoint>,
+        previous_power: PowerState,
     ) -> Option<HeatingEvent> {
-        match self.value() {
-            Ok(value) => {
-                return Some(get_next_desired_state(set_points, value, price));
+        match self {
+            MeasurementEvent::Measurement(value) => Some(get_next_desired_state(
+                set_points,
+                value,
+                price,
+                price_window,
+                planned,
+                previous_power,
+            )),
+            MeasurementEvent::SensorFault(fault) => {
+                error!("Temperature sensor fault {:?}", fault);
+                match fallback {
+                    Some(state) => Some(HeatingEvent::sensor_fallback(state)),
+                    None => Some(HeatingEvent::sensor_fault(set_points)),
+                }
             }
-            Err(err) => error!("Received bad event {:?}: {:?}", self, err),
         }
-        None
     }
 }
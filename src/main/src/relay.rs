@@ -0,0 +1,27 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use control::{PowerState, RelayConfig, RelayProtection};
+
+// Relay protection shared between the measurement handler, which decides
+// against the state the relay is actually in, and the heating handler,
+// which switches it
+#[derive(Clone)]
+pub struct SharedRelay {
+    protection: Arc<Mutex<RelayProtection>>,
+}
+
+impl SharedRelay {
+    pub fn new(config: RelayConfig, state: PowerState) -> SharedRelay {
+        SharedRelay {
+            protection: Arc::new(Mutex::new(RelayProtection::new(config, state))),
+        }
+    }
+
+    pub fn state(&self) -> PowerState {
+        self.protection.lock().unwrap().state()
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, RelayProtection> {
+        self.protection.lock().unwrap()
+    }
+}
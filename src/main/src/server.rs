@@ -0,0 +1,141 @@
+use anyhow::{anyhow, Result};
+use embedded_svc::http::server::Request;
+use embedded_svc::http::Method;
+use embedded_svc::io::{Read, Write};
+use esp_idf_svc::http::server::{Configuration, EspHttpConnection, EspHttpServer};
+
+use control::{AwayMode, CalibrationPoint, CoreConfig, ThermistorProperties};
+
+use crate::away::SharedAwayMode;
+use crate::calibration::SharedCalibration;
+use crate::decision::SharedDecision;
+
+// Largest request body accepted
+const MAX_REQUEST_LENGTH: usize = 256;
+
+// HTTP control API:
+//
+//   GET /away            current away mode, or null
+//   PUT /away            set away mode from a JSON body, e.g.
+//                        {"frost_protection_temperature": 8.0,
+//                         "return_time": "2024-10-27 18:00:00.0"}
+//   DELETE /away         cancel away mode
+//
+//   GET /calibration     calibrated thermistor model, or null
+//   PUT /calibration     calibrate from two or three points, e.g.
+//                        [{"voltage_ratio": 0.52, "temperature": 21.3},
+//                         {"voltage_ratio": 0.41, "temperature": 28.9}]
+//                        using the voltage ratios logged with each
+//                        measurement
+//   DELETE /calibration  revert to the configured thermistor model
+//
+//   GET /decision        the latest heating decision and its reason,
+//                        or null before the first measurement; the
+//                        adjustment is "Modulated" or "RelayHold" when
+//                        the heating output differs from the set point
+pub fn start(
+    away: SharedAwayMode,
+    calibration: SharedCalibration,
+    thermistor: ThermistorProperties,
+    decision: SharedDecision,
+    set_points: CoreConfig,
+) -> Result<EspHttpServer<'static>> {
+    let mut server = EspHttpServer::new(&Configuration::default())?;
+
+    let local_away = away.clone();
+    server.fn_handler::<anyhow::Error, _>("/away", Method::Get, move |req| {
+        let json = serde_json::to_string(&local_away.get())?;
+        req.into_response(200, None, &[("Content-Type", "application/json")])?
+            .write_all(json.as_bytes())?;
+        Ok(())
+    })?;
+
+    let local_away = away.clone();
+    server.fn_handler::<anyhow::Error, _>("/away", Method::Put, move |mut req| {
+        let Some(body) = read_body(&mut req)? else {
+            req.into_status_response(413)?
+                .write_all("Request too large".as_bytes())?;
+            return Ok(());
+        };
+        let away = serde_json::from_slice::<AwayMode>(&body)
+            .map_err(anyhow::Error::from)
+            .and_then(|away| away.validate(&set_points).map(|_| away));
+        match away {
+            Ok(away) => {
+                local_away.set(Some(away))?;
+                req.into_ok_response()?;
+            }
+            Err(err) => {
+                req.into_status_response(400)?
+                    .write_all(format!("Invalid away mode: {}", err).as_bytes())?;
+            }
+        }
+        Ok(())
+    })?;
+
+    server.fn_handler::<anyhow::Error, _>("/away", Method::Delete, move |req| {
+        away.set(None)?;
+        req.into_ok_response()?;
+        Ok(())
+    })?;
+
+    let local_calibration = calibration.clone();
+    server.fn_handler::<anyhow::Error, _>("/calibration", Method::Get, move |req| {
+        let json = serde_json::to_string(&local_calibration.get())?;
+        req.into_response(200, None, &[("Content-Type", "application/json")])?
+            .write_all(json.as_bytes())?;
+        Ok(())
+    })?;
+
+    let local_calibration = calibration.clone();
+    server.fn_handler::<anyhow::Error, _>("/calibration", Method::Put, move |mut req| {
+        let Some(body) = read_body(&mut req)? else {
+            req.into_status_response(413)?
+                .write_all("Request too large".as_bytes())?;
+            return Ok(());
+        };
+        let calibrated = serde_json::from_slice::<Vec<CalibrationPoint>>(&body)
+            .map_err(anyhow::Error::from)
+            .and_then(|points| local_calibration.calibrate(&thermistor, &points));
+        match calibrated {
+            Ok(model) => {
+                let json = serde_json::to_string(&model)?;
+                req.into_response(200, None, &[("Content-Type", "application/json")])?
+                    .write_all(json.as_bytes())?;
+            }
+            Err(err) => {
+                req.into_status_response(400)?
+                    .write_all(format!("Invalid calibration: {}", err).as_bytes())?;
+            }
+        }
+        Ok(())
+    })?;
+
+    server.fn_handler::<anyhow::Error, _>("/calibration", Method::Delete, move |req| {
+        calibration.clear()?;
+        req.into_ok_response()?;
+        Ok(())
+    })?;
+
+    server.fn_handler::<anyhow::Error, _>("/decision", Method::Get, move |req| {
+        let json = serde_json::to_string(&decision.get())?;
+        req.into_response(200, None, &[("Content-Type", "application/json")])?
+            .write_all(json.as_bytes())?;
+        Ok(())
+    })?;
+
+    Ok(server)
+}
+
+// Request body, or None if it is too large to accept
+fn read_body(req: &mut Request<&mut EspHttpConnection>) -> Result<Option<Vec<u8>>> {
+    let length = req.content_len().unwrap_or(0) as usize;
+    if length > MAX_REQUEST_LENGTH {
+        return Ok(None);
+    }
+
+    let mut buffer = vec![0; length];
+    req.read_exact(&mut buffer)
+        .map_err(|err| anyhow!("Failed to read request: {:?}", err))?;
+    Ok(Some(buffer))
+}
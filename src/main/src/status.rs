@@ -11,6 +11,7 @@ pub enum StatusEvent {
     Ready,
     Measuring,
     HeatingOn,
+    SensorFault,
 }
 
 impl From<StatusEvent> for RGB8 {
@@ -21,6 +22,7 @@ impl From<StatusEvent> for RGB8 {
             StatusEvent::Ready => RGB8::new(0, 10, 0),
             StatusEvent::Measuring => RGB8::new(0, 0, 10),
             StatusEvent::HeatingOn => RGB8::new(10, 0, 0),
+            StatusEvent::SensorFault => RGB8::new(10, 4, 0),
         }
     }
 }
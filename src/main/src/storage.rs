@@ -0,0 +1,46 @@
+use anyhow::Result;
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use serde::{de::DeserializeOwned, Serialize};
+use std::sync::{Arc, Mutex};
+
+// Values persisted across reboots in NVS, stored as JSON
+#[derive(Clone)]
+pub struct SharedStorage {
+    nvs: Arc<Mutex<EspNvs<NvsDefault>>>,
+}
+
+impl SharedStorage {
+    pub fn new(partition: EspDefaultNvsPartition, namespace: &str) -> Result<SharedStorage> {
+        let nvs = EspNvs::new(partition, namespace, true)?;
+        let shared_storage = SharedStorage {
+            nvs: Arc::new(Mutex::new(nvs)),
+        };
+        Ok(shared_storage)
+    }
+
+    pub fn load<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let nvs = self.nvs.lock().unwrap();
+        let Some(length) = nvs.str_len(key)? else {
+            return Ok(None);
+        };
+
+        let mut buffer = vec![0; length];
+        match nvs.get_str(key, &mut buffer)? {
+            Some(json) => Ok(Some(serde_json::from_str(json)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn store<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        let json = serde_json::to_string(value)?;
+        let mut nvs = self.nvs.lock().unwrap();
+        nvs.set_str(key, &json)?;
+        Ok(())
+    }
+
+    pub fn remove(&self, key: &str) -> Result<()> {
+        let mut nvs = self.nvs.lock().unwrap();
+        nvs.remove(key)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+use anyhow::Result;
+use core::time::Duration;
+use log::*;
+use std::sync::{Arc, Mutex};
+
+use control::{PowerState, Temperature, ThermalEstimate, ThermalEstimator, ThermalModel};
+
+use crate::storage::SharedStorage;
+
+const STORAGE_KEY: &str = "thermal";
+
+// Persist the estimate every this many samples to limit flash wear
+const PERSIST_INTERVAL: u32 = 12;
+
+#[derive(Clone)]
+pub struct SharedThermalEstimator {
+    estimator: Arc<Mutex<ThermalEstimator>>,
+    storage: SharedStorage,
+}
+
+impl SharedThermalEstimator {
+    pub fn load(storage: SharedStorage, forgetting_factor: f32) -> Result<SharedThermalEstimator> {
+        let estimator = match storage.load::<ThermalEstimate>(STORAGE_KEY)? {
+            Some(estimate) => {
+                info!("Loaded thermal model estimate {:?}", estimate.model());
+                ThermalEstimator::from_estimate(estimate, forgetting_factor)
+            }
+            None => ThermalEstimator::new(forgetting_factor),
+        };
+        let shared_estimator = SharedThermalEstimator {
+            estimator: Arc::new(Mutex::new(estimator)),
+            storage,
+        };
+        Ok(shared_estimator)
+    }
+
+    // Learned model, once enough measurements have been seen
+    pub fn model(&self) -> Option<ThermalModel> {
+        self.estimator.lock().unwrap().model()
+    }
+
+    pub fn update(&self, now: Duration, temperature: Temperature, power: PowerState) -> Result<()> {
+        let mut estimator = self.estimator.lock().unwrap();
+        if !estimator.update(now, temperature, power) {
+            return Ok(());
+        }
+
+        let estimate = *estimator.estimate();
+        let samples = estimate.heating_samples + estimate.cooling_samples;
+        if samples % PERSIST_INTERVAL == 0 {
+            info!(
+                "Learned thermal model {:?} from {} samples",
+                estimate.model(),
+                samples
+            );
+            self.storage.store(STORAGE_KEY, &estimate)?;
+        }
+        Ok(())
+    }
+}
@@ -1,17 +1,26 @@
 pub mod time {
-    use std::{convert::TryFrom, time::SystemTime};
+    use control::{ClockTime, TimeZone};
+    use std::time::SystemTime;
     use time::*;
 
-    pub fn get_datetime() -> Result<PrimitiveDateTime> {
+    // Current time in UTC, the time scale of all price data
+    pub fn get_utc_datetime() -> Result<PrimitiveDateTime> {
         let unixtime = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .unwrap();
 
-        let tm = unsafe { *esp_idf_svc::sys::localtime(&(unixtime.as_secs() as i64)) };
-        let month = Month::try_from(1u8 + tm.tm_mon as u8)?;
-        let date = Date::from_calendar_date(1900 + tm.tm_year, month, tm.tm_mday as _)?;
-        let time = Time::from_hms(tm.tm_hour as _, tm.tm_min as _, tm.tm_sec as _)?;
+        let now = OffsetDateTime::from_unix_timestamp(unixtime.as_secs() as i64)?;
+        Ok(PrimitiveDateTime::new(now.date(), now.time()))
+    }
+
+    // Current time in UTC and on the local clock of `timezone`
+    pub fn get_clock_time(timezone: &TimeZone) -> Result<ClockTime> {
+        Ok(timezone.clock_time(get_utc_datetime()?))
+    }
 
-        Ok(PrimitiveDateTime::new(date, time))
+    // Monotonic time since boot
+    pub fn get_uptime() -> std::time::Duration {
+        let micros = unsafe { esp_idf_svc::sys::esp_timer_get_time() };
+        std::time::Duration::from_micros(micros as u64)
     }
 }